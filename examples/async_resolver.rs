@@ -0,0 +1,52 @@
+//! Example demonstrating concurrent lookups using `AsyncResolver`
+//!
+//! Run with `cargo run --features async --example async_resolver <host> [...]`
+
+extern crate futures;
+extern crate resolve;
+extern crate tokio;
+
+use std::env::args;
+
+use futures::Future;
+use futures::future::join_all;
+
+use resolve::DnsConfig;
+use resolve::async_resolver::AsyncResolver;
+
+fn main() {
+    let args = args().collect::<Vec<_>>();
+
+    if args.len() == 1 {
+        println!("Usage: {} <host name> [...]", args[0]);
+        return;
+    }
+
+    let config = match DnsConfig::load_default() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("failed to load system configuration: {}", e);
+            return;
+        }
+    };
+
+    let resolver = AsyncResolver::new(config);
+
+    let lookups = args[1..].iter().map(|host| {
+        let host = host.clone();
+
+        resolver.resolve_host(&host).then(move |res| {
+            match res {
+                Ok(addrs) => {
+                    for addr in addrs {
+                        println!("\"{}\" resolved to {}", host, addr);
+                    }
+                }
+                Err(e) => println!("failed to resolve \"{}\": {}", host, e)
+            }
+            Ok(())
+        })
+    }).collect::<Vec<_>>();
+
+    tokio::run(join_all(lookups).map(|_| ()));
+}
@@ -34,7 +34,8 @@ fn main() {
     match resolver.resolve_record::<Txt>(&args[1]) {
         Ok(records) => {
             for txt in records {
-                let data = match str::from_utf8(&txt.data) {
+                let data = txt.join();
+                let data = match str::from_utf8(&data) {
                     Ok(string) => string,
                     Err(e) => {
                         println!("Failed to decode UTF8 data: {}", e);
@@ -0,0 +1,580 @@
+//! Futures-based resolver operations
+//!
+//! Requires the `async` feature. The futures returned by `AsyncResolver`
+//! must be run on a tokio runtime.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use tokio_io::io::{read_exact, write_all};
+use tokio_tcp::TcpStream;
+use tokio_timer::Timeout;
+use tokio_udp::UdpSocket;
+
+use address::{address_name, socket_address_equal};
+use config::DnsConfig;
+use message::{DecodeError, Edns, Message, Qr, Question, Resource, MESSAGE_LIMIT};
+use record::{Class, Ptr, Record, RecordType};
+use resolver::{follow_answer, host_addrs, rejects_edns, search_names, v4_addrs, v6_addrs};
+use resolver::{HostResults, ResolveError, ResolveHost};
+use socket::Error;
+use transport::question_matches;
+
+/// A boxed future, as returned by `AsyncResolver` methods
+pub type BoxFuture<T, E> = Box<dyn Future<Item=T, Error=E> + Send>;
+
+/// Performs resolution operations asynchronously
+///
+/// Cloning an `AsyncResolver` produces a handle to the same resolver.
+#[derive(Clone)]
+pub struct AsyncResolver {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: DnsConfig,
+    /// Index of `config.name_servers` to use in next DNS request;
+    /// ignored if `config.rotate` is `false`.
+    next_ns: AtomicUsize,
+}
+
+impl AsyncResolver {
+    /// Constructs an `AsyncResolver` using the given configuration.
+    pub fn new(config: DnsConfig) -> AsyncResolver {
+        AsyncResolver{
+            inner: Arc::new(Inner{
+                config: config,
+                next_ns: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the resolver configuration.
+    pub fn config(&self) -> &DnsConfig {
+        &self.inner.config
+    }
+
+    /// Resolves an IPv4 or IPv6 address to a hostname.
+    pub fn resolve_addr(&self, addr: &IpAddr) -> BoxFuture<String, ResolveError> {
+        Box::new(self.lookup(&address_name(addr), RecordType::Ptr).and_then(|(_, answer)| {
+            match answer.first() {
+                Some(rr) => {
+                    let ptr = try!(rr.read_rdata::<Ptr>());
+                    let mut name = ptr.name;
+                    if name.ends_with('.') {
+                        name.pop();
+                    }
+                    Ok(name)
+                }
+                None => Err(ResolveError::NoData)
+            }
+        }))
+    }
+
+    /// Resolves a hostname to a series of IPv4 or IPv6 addresses.
+    ///
+    /// `CNAME` and `DNAME` aliases are followed; the canonical name of the
+    /// host is available from the returned `ResolveHost`.
+    ///
+    /// `A` and `AAAA` queries for each name are sent concurrently, unless
+    /// `single_request` or `use_inet6` is set in the resolver configuration.
+    pub fn resolve_host(&self, host: &str) -> BoxFuture<ResolveHost, ResolveError> {
+        let resolver = self.clone();
+        let names = search_names(host, self.config());
+
+        let f = future::loop_fn((names.into_iter(), None), move |(mut names, err)| {
+            let name = match names.next() {
+                Some(name) => name,
+                None => {
+                    return Either::A(future::err(err.unwrap_or(ResolveError::NoData)));
+                }
+            };
+
+            info!("attempting lookup of name \"{}\"", name);
+
+            let config = resolver.config();
+            let sort_resolver = resolver.clone();
+
+            let v4 = {
+                let (resolver, name) = (resolver.clone(), name.clone());
+                move || resolver.lookup(&name, RecordType::A).then(|res| Ok(v4_addrs(res)))
+            };
+            let v6 = resolver.lookup(&name, RecordType::AAAA).then(|res| Ok(v6_addrs(res)));
+
+            let both: BoxFuture<HostResults, ResolveError> = if config.use_inet6 {
+                // IPv4 addresses are queried only if there are no IPv6 addresses
+                Box::new(v6.and_then(move |v6| match v6 {
+                    Ok((_, ref addrs)) if !addrs.is_empty() =>
+                        Either::A(future::ok((None, Some(v6)))),
+                    _ => Either::B(v4().map(move |v4| (Some(v4), Some(v6))))
+                }))
+            } else if config.single_request {
+                Box::new(v4().and_then(move |v4| v6.map(move |v6| (Some(v4), Some(v6)))))
+            } else {
+                Box::new(v4().join(v6).map(|(v4, v6)| (Some(v4), Some(v6))))
+            };
+
+            Either::B(both.map(move |(v4, v6)| {
+                match host_addrs(v4, v6, sort_resolver.config()) {
+                    Ok(host) => Loop::Break(host),
+                    Err(e) => Loop::Continue((names, Some(e)))
+                }
+            }))
+        });
+
+        Box::new(f)
+    }
+
+    /// Requests a type of record from the DNS server and returns the results.
+    ///
+    /// `CNAME` and `DNAME` aliases are followed, as by `DnsResolver`.
+    /// If the name exists, but has no records of the requested type,
+    /// `ResolveError::NoData` is returned.
+    pub fn resolve_record<Rec>(&self, name: &str) -> BoxFuture<Vec<Rec>, ResolveError>
+            where Rec: Record + Send + 'static {
+        Box::new(self.lookup(name, Rec::record_type()).and_then(|(_, answer)| {
+            if answer.is_empty() {
+                return Err(ResolveError::NoData);
+            }
+
+            let mut rec = Vec::with_capacity(answer.len());
+
+            for rr in answer {
+                rec.push(try!(rr.read_rdata::<Rec>()));
+            }
+
+            Ok(rec)
+        }))
+    }
+
+    /// Queries the given name and record type, following any `CNAME` or
+    /// `DNAME` aliases. Returns the canonical name, without a trailing `.`,
+    /// and the records of the requested type owned by that name.
+    ///
+    /// Aliases are followed first within each response. If the target of an
+    /// alias is not answered in the same response, it is queried separately.
+    fn lookup(&self, name: &str, r_type: RecordType)
+            -> BoxFuture<(String, Vec<Resource<'static>>), ResolveError> {
+        let resolver = self.clone();
+
+        Box::new(future::loop_fn(vec![name.to_owned()], move |mut chain| {
+            resolver.query(chain.last().unwrap(), r_type).map_err(ResolveError::from)
+                .and_then(move |answer| {
+                    match try!(follow_answer(&answer, r_type, &mut chain)) {
+                        Some(res) => Ok(Loop::Break(res)),
+                        None => Ok(Loop::Continue(chain))
+                    }
+                })
+        }))
+    }
+
+    /// Queries the given name and record type and returns the records in the
+    /// answer section of the response.
+    fn query(&self, name: &str, r_type: RecordType)
+            -> BoxFuture<Vec<Resource<'static>>, Error> {
+        let mut msg = self.basic_message();
+
+        msg.question.push(Question::new(name.to_owned(), r_type, Class::Internet));
+
+        Box::new(self.send_message(&msg).map(|reply| reply.answer))
+    }
+
+    /// Sends a message to the DNS server and attempts to read a response.
+    ///
+    /// If the response received over UDP is truncated, the query is repeated
+    /// over TCP to the same name server.
+    ///
+    /// If the message contains EDNS data and the name server rejects it,
+    /// the message is sent again without EDNS data.
+    pub fn send_message(&self, out_msg: &Message) -> BoxFuture<Message<'static>, Error> {
+        let use_edns = out_msg.edns.is_some();
+        let mut plain = out_msg.clone().into_owned();
+        plain.edns = None;
+
+        let resolver = self.clone();
+
+        Box::new(self.send_attempts(out_msg)
+            .and_then(move |msg| {
+                if use_edns && rejects_edns(&msg) {
+                    info!("name server rejected EDNS query; retrying without EDNS");
+                    Either::A(resolver.send_attempts(&plain))
+                } else {
+                    Either::B(future::ok(msg))
+                }
+            })
+            .and_then(|msg| {
+                try!(msg.get_error());
+                Ok(msg)
+            }))
+    }
+
+    /// Sends a message to each name server in turn, for the configured
+    /// number of attempts, until a response is received.
+    fn send_attempts(&self, out_msg: &Message) -> BoxFuture<Message<'static>, Error> {
+        let mut buf = [0; MESSAGE_LIMIT];
+
+        let data = match out_msg.encode(&mut buf) {
+            Ok(data) => Arc::new(data.to_vec()),
+            Err(e) => return Box::new(future::err(Error::EncodeError(e)))
+        };
+
+        let id = out_msg.header.id;
+        let question = Arc::new(out_msg.question.clone());
+        let resolver = self.clone();
+
+        Box::new(future::loop_fn((0, None), move |(retries, last_err)| {
+            let config = resolver.config();
+
+            if retries >= config.attempts {
+                return Either::A(future::err(last_err.unwrap_or_else(|| {
+                    Error::IoError(io::Error::new(io::ErrorKind::Other,
+                        "no attempts made"))
+                })));
+            }
+
+            let ns_addr = if config.rotate {
+                resolver.next_nameserver()
+            } else {
+                let n = config.name_servers.len();
+                config.name_servers[retries as usize % n]
+            };
+
+            let timeout = config.timeout;
+            let tcp_data = data.clone();
+
+            info!("resolver sending message to {}", ns_addr);
+
+            Either::B(send_udp(data.clone(), id, question.clone(), ns_addr, timeout)
+                .and_then(move |msg| {
+                    if msg.header.truncated {
+                        info!("response from {} truncated; retrying over TCP", ns_addr);
+                        Either::A(send_tcp(tcp_data, id, ns_addr, timeout))
+                    } else {
+                        Either::B(future::ok(msg))
+                    }
+                })
+                .then(move |res| match res {
+                    Ok(msg) => Ok(Loop::Break(msg)),
+                    // Retry on timeout
+                    Err(ref e) if e.is_timeout() => Ok(Loop::Continue((retries + 1, res.err()))),
+                    // Immediately bail for other errors
+                    Err(e) => Err(e)
+                }))
+        }))
+    }
+
+    fn basic_message<'a>(&self) -> Message<'a> {
+        let mut msg = Message::new();
+
+        msg.header.recursion_desired = true;
+
+        if self.config().edns0 {
+            msg.edns = Some(Edns::new());
+        }
+
+        msg
+    }
+
+    fn next_nameserver(&self) -> SocketAddr {
+        let config = self.config();
+        let n = self.inner.next_ns.fetch_add(1, Ordering::Relaxed);
+        config.name_servers[n % config.name_servers.len()]
+    }
+}
+
+/// Sends encoded message data over UDP from a newly bound socket and waits
+/// for a response with the given ID and question.
+fn send_udp(data: Arc<Vec<u8>>, id: u16, question: Arc<Vec<Question>>,
+        ns_addr: SocketAddr, timeout: Duration) -> BoxFuture<Message<'static>, Error> {
+    let bind = match ns_addr {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+        SocketAddr::V6(_) => SocketAddr::new(
+            IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0),
+    };
+
+    let sock = match UdpSocket::bind(&bind) {
+        Ok(sock) => sock,
+        Err(e) => return Box::new(future::err(Error::IoError(e)))
+    };
+
+    let f = sock.send_dgram((*data).clone(), &ns_addr).map_err(Error::IoError)
+        .and_then(move |(sock, _)| future::loop_fn(sock, move |sock| {
+            let question = question.clone();
+
+            sock.recv_dgram(vec![0; MESSAGE_LIMIT]).map_err(Error::IoError)
+                .and_then(move |(sock, buf, n, addr)| {
+                    // Ignore irrelevant messages
+                    if !socket_address_equal(&addr, &ns_addr) {
+                        return Ok(Loop::Continue(sock));
+                    }
+
+                    let msg = match Message::decode(&buf[..n]) {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            info!("discarding invalid message from {}: {}", addr, e);
+                            return Ok(Loop::Continue(sock));
+                        }
+                    };
+
+                    if msg.header.id == id && msg.header.qr == Qr::Response &&
+                            question_matches(&question, &msg.question) {
+                        Ok(Loop::Break(msg.into_owned()))
+                    } else {
+                        Ok(Loop::Continue(sock))
+                    }
+                })
+        }));
+
+    with_timeout(f, timeout)
+}
+
+/// Sends encoded message data over a new TCP connection and reads a response.
+fn send_tcp(data: Arc<Vec<u8>>, id: u16, ns_addr: SocketAddr, timeout: Duration)
+        -> BoxFuture<Message<'static>, Error> {
+    let mut framed = Vec::with_capacity(data.len() + 2);
+
+    framed.push((data.len() >> 8) as u8);
+    framed.push(data.len() as u8);
+    framed.extend_from_slice(&data);
+
+    let f = TcpStream::connect(&ns_addr)
+        .and_then(move |stream| write_all(stream, framed))
+        .and_then(|(stream, _)| read_exact(stream, [0; 2]))
+        .and_then(|(stream, len)| {
+            let len = ((len[0] as usize) << 8) | len[1] as usize;
+            read_exact(stream, vec![0; len])
+        })
+        .map_err(Error::IoError)
+        .and_then(move |(_, buf)| {
+            let msg = try!(Message::decode(&buf));
+
+            if msg.header.id != id || msg.header.qr != Qr::Response {
+                return Err(Error::DecodeError(DecodeError::InvalidMessage));
+            }
+
+            Ok(msg.into_owned())
+        });
+
+    with_timeout(f, timeout)
+}
+
+fn with_timeout<F>(f: F, timeout: Duration) -> BoxFuture<F::Item, Error>
+        where F: Future<Error=Error> + Send + 'static {
+    Box::new(Timeout::new(f, timeout).map_err(|e| {
+        if e.is_elapsed() {
+            Error::IoError(io::Error::new(io::ErrorKind::TimedOut,
+                "timed out waiting for response"))
+        } else if e.is_inner() {
+            e.into_inner().unwrap()
+        } else {
+            Error::IoError(io::Error::new(io::ErrorKind::Other, e.to_string()))
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, UdpSocket};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use tokio::runtime::current_thread::Runtime;
+
+    use config::DnsConfig;
+    use message::{Message, Qr, Resource, MESSAGE_LIMIT};
+    use record::{A, AAAA, CName, Class, Ptr, RecordType};
+    use resolver::ResolveError;
+    use super::AsyncResolver;
+
+    #[test]
+    fn test_resolve_host() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        thread::spawn(move || {
+            for _ in 0..2 {
+                let mut buf = [0; MESSAGE_LIMIT];
+                let (n, peer) = server.recv_from(&mut buf).unwrap();
+                let query = Message::decode(&buf[..n]).unwrap();
+
+                let mut msg = Message::with_id(query.header.id);
+                msg.header.qr = Qr::Response;
+                msg.question = query.question.clone();
+
+                let q = &query.question[0];
+                let mut rr = Resource::new(q.name.clone(), q.q_type, Class::Internet, 60);
+
+                match q.q_type {
+                    RecordType::A => rr.write_rdata(
+                        &A{address: "10.0.0.1".parse().unwrap()}).unwrap(),
+                    _ => rr.write_rdata(
+                        &AAAA{address: "2001:db8::1".parse().unwrap()}).unwrap(),
+                }
+
+                msg.answer.push(rr);
+
+                // Invalid messages and answers to other questions are ignored
+                server.send_to(b"\xff", peer).unwrap();
+
+                let mut other = msg.clone();
+                other.question[0].name = "example.net.".to_owned();
+                other.answer.clear();
+
+                let mut out = [0; MESSAGE_LIMIT];
+                server.send_to(other.encode(&mut out).unwrap(), peer).unwrap();
+                server.send_to(msg.encode(&mut out).unwrap(), peer).unwrap();
+            }
+        });
+
+        let resolver = AsyncResolver::new(DnsConfig::with_name_servers(vec![addr]));
+
+        let mut rt = Runtime::new().unwrap();
+        let addrs = rt.block_on(resolver.resolve_host("example.com."))
+            .unwrap().collect::<Vec<_>>();
+
+        assert_eq!(addrs, [
+            "10.0.0.1".parse::<IpAddr>().unwrap(),
+            "2001:db8::1".parse::<IpAddr>().unwrap(),
+        ]);
+    }
+
+    #[test]
+    fn test_use_inet6() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let server_queries = queries.clone();
+
+        // Only `www.example.com.` has an IPv6 address
+        thread::spawn(move || loop {
+            let mut buf = [0; MESSAGE_LIMIT];
+            let (n, peer) = server.recv_from(&mut buf).unwrap();
+            let query = Message::decode(&buf[..n]).unwrap();
+
+            let mut msg = Message::with_id(query.header.id);
+            msg.header.qr = Qr::Response;
+            msg.question = query.question.clone();
+
+            let q = &query.question[0];
+            server_queries.lock().unwrap().push((q.name.clone(), q.q_type));
+
+            let mut rr = Resource::new(q.name.clone(), q.q_type, Class::Internet, 60);
+
+            match q.q_type {
+                RecordType::A => {
+                    rr.write_rdata(&A{address: "10.0.0.1".parse().unwrap()}).unwrap();
+                    msg.answer.push(rr);
+                }
+                _ => if q.name == "www.example.com." {
+                    rr.write_rdata(&AAAA{address: "2001:db8::1".parse().unwrap()}).unwrap();
+                    msg.answer.push(rr);
+                }
+            }
+
+            let mut out = [0; MESSAGE_LIMIT];
+            server.send_to(msg.encode(&mut out).unwrap(), peer).unwrap();
+        });
+
+        let mut config = DnsConfig::with_name_servers(vec![addr]);
+        config.use_inet6 = true;
+
+        let resolver = AsyncResolver::new(config);
+        let mut rt = Runtime::new().unwrap();
+
+        let addrs = rt.block_on(resolver.resolve_host("www.example.com."))
+            .unwrap().collect::<Vec<_>>();
+        assert_eq!(addrs, ["2001:db8::1".parse::<IpAddr>().unwrap()]);
+
+        let addrs = rt.block_on(resolver.resolve_host("mail.example.com."))
+            .unwrap().collect::<Vec<_>>();
+        assert_eq!(addrs, ["::ffff:10.0.0.1".parse::<IpAddr>().unwrap()]);
+
+        assert_eq!(*queries.lock().unwrap(), [
+            ("www.example.com.".to_owned(), RecordType::AAAA),
+            ("mail.example.com.".to_owned(), RecordType::AAAA),
+            ("mail.example.com.".to_owned(), RecordType::A),
+        ]);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // Each response holds only the alias or only the address, along with
+        // an unrelated record of the requested type
+        thread::spawn(move || loop {
+            let mut buf = [0; MESSAGE_LIMIT];
+            let (n, peer) = server.recv_from(&mut buf).unwrap();
+            let query = Message::decode(&buf[..n]).unwrap();
+
+            let mut msg = Message::with_id(query.header.id);
+            msg.header.qr = Qr::Response;
+            msg.question = query.question.clone();
+
+            let q = &query.question[0];
+
+            if q.name == "www.example.com." {
+                let mut rr = Resource::new(q.name.clone(), RecordType::CName,
+                    Class::Internet, 60);
+                rr.write_rdata(&CName{name: "host.example.net.".to_owned()}).unwrap();
+                msg.answer.push(rr);
+            }
+
+            if q.q_type == RecordType::A {
+                let mut rr = Resource::new(q.name.clone(), RecordType::A,
+                    Class::Internet, 60);
+                rr.write_rdata(&A{address: "10.0.0.2".parse().unwrap()}).unwrap();
+
+                if q.name == "www.example.com." {
+                    rr.name = "other.example.com.".to_owned();
+                }
+
+                msg.answer.push(rr);
+            }
+
+            // Classless reverse delegation, as described in RFC 2317
+            if q.q_type == RecordType::Ptr {
+                let mut rr = Resource::new(q.name.clone(), RecordType::CName,
+                    Class::Internet, 60);
+
+                if q.name == "2.0.0.10.in-addr.arpa." {
+                    rr.write_rdata(&CName{name: "2.0-7.0.0.10.in-addr.arpa.".to_owned()})
+                        .unwrap();
+                } else {
+                    rr.r_type = RecordType::Ptr;
+                    rr.write_rdata(&Ptr{name: "host.example.net.".to_owned()}).unwrap();
+                }
+
+                msg.answer.push(rr);
+            }
+
+            let mut out = [0; MESSAGE_LIMIT];
+            server.send_to(msg.encode(&mut out).unwrap(), peer).unwrap();
+        });
+
+        let resolver = AsyncResolver::new(DnsConfig::with_name_servers(vec![addr]));
+
+        let mut rt = Runtime::new().unwrap();
+        let host = rt.block_on(resolver.resolve_host("www.example.com.")).unwrap();
+
+        assert_eq!(host.canonical_name(), Some("host.example.net"));
+        assert_eq!(host.collect::<Vec<_>>(), ["10.0.0.2".parse::<IpAddr>().unwrap()]);
+
+        let rec = rt.block_on(resolver.resolve_record::<A>("www.example.com.")).unwrap();
+        assert_eq!(rec, [A{address: "10.0.0.2".parse().unwrap()}]);
+
+        match rt.block_on(resolver.resolve_record::<AAAA>("www.example.com.")) {
+            Err(ResolveError::NoData) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        let name = rt.block_on(resolver.resolve_addr(&"10.0.0.2".parse().unwrap())).unwrap();
+        assert_eq!(name, "host.example.net");
+    }
+}
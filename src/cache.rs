@@ -0,0 +1,394 @@
+//! In-memory cache of resolver responses
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use message::{Message, RCode, Resource};
+use record::{Class, RecordType, Soa};
+
+/// Identifies a set of cached records.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CacheKey {
+    /// Queried name, with ASCII letters in lowercase and a trailing `.`
+    pub name: String,
+    /// Queried record type
+    pub r_type: RecordType,
+    /// Queried class
+    pub r_class: Class,
+}
+
+impl CacheKey {
+    /// Constructs a new `CacheKey`. The name is normalized so that lookups
+    /// are not sensitive to ASCII case or the presence of a trailing `.`.
+    ///
+    /// As in name comparison in DNS, other characters are not case folded.
+    pub fn new(name: &str, r_type: RecordType, r_class: Class) -> CacheKey {
+        let mut name = name.to_ascii_lowercase();
+
+        if !name.ends_with('.') {
+            name.push('.');
+        }
+
+        CacheKey{
+            name: name,
+            r_type: r_type,
+            r_class: r_class,
+        }
+    }
+}
+
+/// Represents the cached result of a query.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CachedAnswer {
+    /// Records from the answer section of the response
+    Records(Vec<Resource<'static>>),
+    /// The queried name does not exist
+    NoSuchName,
+    /// The queried name exists, but has no records of the queried type
+    NoData,
+}
+
+struct Entry {
+    answer: CachedAnswer,
+    inserted: Instant,
+    expires: Instant,
+    /// Distinguishes entries with the same expiry time in `Entries::expiry`
+    seq: u64,
+}
+
+/// Cache entries, indexed by key and by expiry time
+#[derive(Default)]
+struct Entries {
+    map: HashMap<CacheKey, Entry>,
+    expiry: BTreeMap<(Instant, u64), CacheKey>,
+    next_seq: u64,
+}
+
+impl Entries {
+    fn insert(&mut self, key: CacheKey, answer: CachedAnswer, inserted: Instant,
+            expires: Instant) {
+        self.remove(&key);
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        self.expiry.insert((expires, seq), key.clone());
+        self.map.insert(key, Entry{
+            answer: answer,
+            inserted: inserted,
+            expires: expires,
+            seq: seq,
+        });
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Option<Entry> {
+        let ent = self.map.remove(key);
+
+        if let Some(ref ent) = ent {
+            self.expiry.remove(&(ent.expires, ent.seq));
+        }
+
+        ent
+    }
+
+    /// Returns the expiry time and key of the entry closest to expiring.
+    fn first(&self) -> Option<(Instant, CacheKey)> {
+        self.expiry.iter().next().map(|(&(expires, _), key)| (expires, key.clone()))
+    }
+
+    /// Removes all entries which have expired at the given time.
+    fn remove_expired(&mut self, now: Instant) {
+        while let Some((expires, key)) = self.first() {
+            if expires > now {
+                break;
+            }
+            self.remove(&key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.expiry.clear();
+    }
+}
+
+/// Caches query results for the duration of their time-to-live.
+///
+/// Negative results are cached according to RFC 2308, using the TTL of the
+/// `SOA` record from the authority section of the response.
+///
+/// When the cache is full, expired entries are removed first; if none are
+/// expired, the entry closest to expiring is removed.
+pub struct DnsCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+impl DnsCache {
+    /// Constructs a `DnsCache` which holds up to `capacity` entries.
+    pub fn new(capacity: usize) -> DnsCache {
+        DnsCache{
+            capacity: capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Returns the maximum number of entries held by the cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries in the cache, including any which
+    /// have expired but not yet been removed.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the cached answer for the given query, if one exists and
+    /// has not expired. The TTL of each returned record is reduced by the
+    /// time elapsed since it was cached.
+    pub fn get(&self, name: &str, r_type: RecordType, r_class: Class)
+            -> Option<CachedAnswer> {
+        self.get_at(&CacheKey::new(name, r_type, r_class), Instant::now())
+    }
+
+    fn get_at(&self, key: &CacheKey, now: Instant) -> Option<CachedAnswer> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let expired = match entries.map.get(key) {
+            Some(ent) => ent.expires <= now,
+            None => return None
+        };
+
+        if expired {
+            entries.remove(key);
+            return None;
+        }
+
+        let ent = &entries.map[key];
+
+        match ent.answer {
+            CachedAnswer::Records(ref rrs) => {
+                let elapsed = secs(now.duration_since(ent.inserted));
+
+                Some(CachedAnswer::Records(rrs.iter().map(|rr| {
+                    let mut rr = rr.clone();
+                    rr.ttl = rr.ttl.saturating_sub(elapsed);
+                    rr
+                }).collect()))
+            }
+            ref answer => Some(answer.clone())
+        }
+    }
+
+    /// Caches an answer for the given query, which will expire after `ttl`
+    /// seconds. If `ttl` is zero, the answer is not cached.
+    pub fn insert(&self, name: &str, r_type: RecordType, r_class: Class,
+            answer: CachedAnswer, ttl: u32) {
+        self.insert_at(CacheKey::new(name, r_type, r_class), answer, ttl, Instant::now());
+    }
+
+    fn insert_at(&self, key: CacheKey, answer: CachedAnswer, ttl: u32, now: Instant) {
+        if ttl == 0 || self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(&key) {
+            entries.remove_expired(now);
+
+            if entries.map.len() >= self.capacity {
+                if let Some((_, oldest)) = entries.first() {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        entries.insert(key, answer, now, now + Duration::from_secs(ttl as u64));
+    }
+
+    /// Caches the result of a query, given the response message.
+    ///
+    /// Responses other than successful answers, `NXDOMAIN`, and `NODATA`
+    /// are not cached, nor are negative responses which lack an `SOA` record.
+    pub fn insert_response(&self, name: &str, r_type: RecordType, r_class: Class,
+            msg: &Message) {
+        if let Some((answer, ttl)) = response_answer(msg) {
+            self.insert(name, r_type, r_class, answer, ttl);
+        }
+    }
+
+    /// Removes the cached answer for the given query.
+    /// Returns whether an entry was removed.
+    pub fn remove(&self, name: &str, r_type: RecordType, r_class: Class) -> bool {
+        self.entries.lock().unwrap()
+            .remove(&CacheKey::new(name, r_type, r_class)).is_some()
+    }
+
+    /// Returns all unexpired entries, along with the time remaining
+    /// before each expires.
+    pub fn entries(&self) -> Vec<(CacheKey, CachedAnswer, Duration)> {
+        let now = Instant::now();
+
+        self.entries.lock().unwrap().map.iter()
+            .filter(|&(_, ent)| ent.expires > now)
+            .map(|(key, ent)| (key.clone(), ent.answer.clone(), ent.expires - now))
+            .collect()
+    }
+
+    /// Removes all expired entries.
+    pub fn purge_expired(&self) {
+        self.entries.lock().unwrap().remove_expired(Instant::now());
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Returns the answer to be cached for a response and its TTL.
+fn response_answer(msg: &Message) -> Option<(CachedAnswer, u32)> {
+    match msg.header.rcode {
+        RCode::NoError if !msg.answer.is_empty() => {
+            let ttl = msg.answer.iter().map(|rr| rr.ttl).min().unwrap_or(0);
+            let rrs = msg.answer.iter().map(|rr| rr.clone().into_owned()).collect();
+
+            Some((CachedAnswer::Records(rrs), ttl))
+        }
+        RCode::NoError => negative_ttl(msg).map(|ttl| (CachedAnswer::NoData, ttl)),
+        RCode::NameError => negative_ttl(msg).map(|ttl| (CachedAnswer::NoSuchName, ttl)),
+        _ => None
+    }
+}
+
+/// Returns the TTL of a negative response, as described in RFC 2308,
+/// section 5: the lesser of the `SOA` record TTL and its `minimum` field.
+fn negative_ttl(msg: &Message) -> Option<u32> {
+    msg.authority.iter()
+        .filter(|rr| rr.r_type == RecordType::Soa)
+        .filter_map(|rr| rr.read_rdata::<Soa>().ok().map(|soa| (rr.ttl, soa)))
+        .map(|(ttl, soa)| if ttl < soa.minimum { ttl } else { soa.minimum })
+        .next()
+}
+
+fn secs(d: Duration) -> u32 {
+    if d.as_secs() > u32::max_value() as u64 {
+        u32::max_value()
+    } else {
+        d.as_secs() as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use std::time::{Duration, Instant};
+
+    use message::{Message, RCode, Resource};
+    use record::{A, Class, RecordType, Soa};
+    use super::{CacheKey, CachedAnswer, DnsCache};
+
+    fn a_record(ttl: u32) -> Resource<'static> {
+        let mut rr = Resource::new("example.com.".to_owned(),
+            RecordType::A, Class::Internet, ttl);
+        rr.write_rdata(&A{address: Ipv4Addr::new(10, 0, 0, 1)}).unwrap();
+        rr
+    }
+
+    fn key(name: &str) -> CacheKey {
+        CacheKey::new(name, RecordType::A, Class::Internet)
+    }
+
+    #[test]
+    fn test_ttl() {
+        let cache = DnsCache::new(10);
+        let now = Instant::now();
+
+        cache.insert_at(key("Example.COM"),
+            CachedAnswer::Records(vec![a_record(60)]), 60, now);
+
+        match cache.get_at(&key("example.com."), now + Duration::from_secs(20)) {
+            Some(CachedAnswer::Records(rrs)) => assert_eq!(rrs[0].ttl, 40),
+            r => panic!("unexpected cache result: {:?}", r)
+        }
+
+        assert_eq!(cache.get_at(&key("example.com"), now + Duration::from_secs(60)), None);
+        assert!(cache.is_empty());
+
+        assert_eq!(key("BÜCHER.de").name, "bÜcher.de.");
+    }
+
+    #[test]
+    fn test_negative() {
+        let mut msg = Message::new();
+        msg.header.rcode = RCode::NameError;
+
+        let cache = DnsCache::new(10);
+
+        // Negative responses without an SOA record are not cached
+        cache.insert_response("missing.example.com", RecordType::A, Class::Internet, &msg);
+        assert!(cache.is_empty());
+
+        let mut soa = Resource::new("example.com.".to_owned(),
+            RecordType::Soa, Class::Internet, 3600);
+        soa.write_rdata(&Soa{
+            mname: "ns.example.com.".to_owned(),
+            rname: "admin.example.com.".to_owned(),
+            serial: 1,
+            refresh: 7200,
+            retry: 3600,
+            expire: 86400,
+            minimum: 300,
+        }).unwrap();
+        msg.authority.push(soa);
+
+        cache.insert_response("missing.example.com", RecordType::A, Class::Internet, &msg);
+        assert_eq!(cache.get("missing.example.com", RecordType::A, Class::Internet),
+            Some(CachedAnswer::NoSuchName));
+
+        let entries = cache.entries();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].2 <= Duration::from_secs(300));
+
+        msg.header.rcode = RCode::NoError;
+        cache.insert_response("example.com", RecordType::Mx, Class::Internet, &msg);
+        assert_eq!(cache.get("example.com", RecordType::Mx, Class::Internet),
+            Some(CachedAnswer::NoData));
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = DnsCache::new(2);
+        let now = Instant::now();
+
+        cache.insert_at(key("a.com"), CachedAnswer::NoData, 30, now);
+        cache.insert_at(key("b.com"), CachedAnswer::NoData, 10, now);
+        cache.insert_at(key("c.com"), CachedAnswer::NoData, 20, now);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_at(&key("a.com"), now).is_some());
+        assert!(cache.get_at(&key("b.com"), now).is_none());
+        assert!(cache.get_at(&key("c.com"), now).is_some());
+
+        // Replacing an entry changes its expiry time; expired entries
+        // are removed before the entry closest to expiring
+        cache.insert_at(key("c.com"), CachedAnswer::NoData, 5, now);
+        cache.insert_at(key("d.com"), CachedAnswer::NoData, 1, now + Duration::from_secs(6));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_at(&key("a.com"), now).is_some());
+        assert!(cache.get_at(&key("c.com"), now).is_none());
+
+        assert!(cache.remove("a.com", RecordType::A, Class::Internet));
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -4,6 +4,8 @@ use std::io;
 use std::net::SocketAddr;
 use std::time::Duration;
 
+use sort::SortlistEntry;
+
 /// Configures the behavior of DNS requests
 #[derive(Clone, Debug)]
 pub struct DnsConfig {
@@ -24,6 +26,19 @@ pub struct DnsConfig {
     /// If `true`, perform `AAAA` queries first and return IPv4 addresses
     /// as IPv4-mapped IPv6 addresses.
     pub use_inet6: bool,
+    /// If `true`, send `A` and `AAAA` queries one after the other,
+    /// rather than in parallel
+    pub single_request: bool,
+    /// Whether to send EDNS(0) data in queries, advertising a larger
+    /// UDP payload size
+    pub edns0: bool,
+
+    /// Whether to order the addresses returned by `resolve_host` according
+    /// to the destination address selection rules of RFC 6724
+    pub sort_addresses: bool,
+    /// Networks by which to order the addresses returned by `resolve_host`;
+    /// addresses matching no entry are placed last
+    pub sortlist: Vec<SortlistEntry>,
 }
 
 impl DnsConfig {
@@ -45,6 +60,11 @@ impl DnsConfig {
 
             rotate: false,
             use_inet6: false,
+            single_request: false,
+            edns0: false,
+
+            sort_addresses: false,
+            sortlist: Vec::new(),
         }
     }
 }
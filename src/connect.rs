@@ -0,0 +1,395 @@
+//! Establishing TCP connections to hosts using Happy Eyeballs
+//!
+//! Implements the connection procedure of RFC 8305: `AAAA` and `A` queries
+//! are sent at once, and connection attempts to the resulting addresses are
+//! made in turn, alternating between address families and starting a new
+//! attempt if the previous one has not succeeded after a short delay.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::DnsConfig;
+use record::RecordType;
+use resolver::{DnsResolver, ResolveError};
+use sort::sort_addresses;
+
+/// Time to wait for an `AAAA` response after an `A` response is received,
+/// before beginning connection attempts
+pub const RESOLUTION_DELAY: Duration = Duration::from_millis(50);
+
+/// Time to wait for a connection attempt to succeed before beginning
+/// the next attempt
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Time allowed for resolution and all connection attempts to complete
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum Event {
+    Resolved(RecordType, Result<Vec<IpAddr>, ResolveError>),
+    Connected(io::Result<TcpStream>),
+}
+
+/// Resolves a host using the default system configuration and connects
+/// to the given port on one of its addresses.
+///
+/// See `connect_host_with` for details.
+pub fn connect_host(host: &str, port: u16) -> io::Result<TcpStream> {
+    let r = try!(DnsResolver::new(try!(DnsConfig::load_default())));
+    connect_host_with(&Arc::new(r), host, port)
+}
+
+/// Resolves a host using the given resolver and connects to the given port
+/// on one of its addresses, following RFC 8305.
+///
+/// `AAAA` and `A` queries are sent in parallel. Connection attempts begin
+/// once `AAAA` records are received or, if `A` records are received first,
+/// after `RESOLUTION_DELAY`. Attempts alternate between IPv6 and IPv4
+/// addresses, beginning with the family of the address preferred by
+/// RFC 6724 among all those received, and a new attempt is started each
+/// `CONNECTION_ATTEMPT_DELAY` or as soon as the previous attempt fails.
+/// The first connection established is returned. Addresses of each family
+/// are attempted in the order given by RFC 6724.
+///
+/// If no connection is established within `CONNECT_TIMEOUT`, an error of
+/// kind `TimedOut` is returned. Once this function returns, any attempts
+/// still in progress are abandoned, and connections they establish are
+/// closed immediately.
+///
+/// If `host` is an IP address, it is connected to directly.
+pub fn connect_host_with(resolver: &Arc<DnsResolver>, host: &str, port: u16)
+        -> io::Result<TcpStream> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return TcpStream::connect_timeout(&SocketAddr::new(ip, port), CONNECT_TIMEOUT);
+    }
+
+    let (tx, rx) = channel();
+
+    for &r_type in &[RecordType::AAAA, RecordType::A] {
+        let resolver = resolver.clone();
+        let host = host.to_owned();
+        let tx = tx.clone();
+
+        thread::spawn(move || {
+            let _ = tx.send(Event::Resolved(r_type, resolver.resolve_family(&host, r_type)));
+        });
+    }
+
+    let mut state = ConnectState::new(port, Instant::now() + CONNECT_TIMEOUT);
+
+    loop {
+        let now = Instant::now();
+
+        state.start_attempts(now, &tx);
+
+        if state.is_finished() {
+            return Err(state.take_error());
+        }
+        if now >= state.deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut,
+                "timed out connecting to host"));
+        }
+
+        let deadline = state.next_deadline();
+
+        if deadline <= now {
+            continue;
+        }
+
+        let event = match rx.recv_timeout(deadline - now) {
+            Ok(ev) => ev,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => unreachable!(),
+        };
+
+        match event {
+            Event::Resolved(r_type, res) => state.resolved(r_type, res, Instant::now()),
+            Event::Connected(Ok(stream)) => return Ok(stream),
+            Event::Connected(Err(e)) => state.failed(e),
+        }
+    }
+}
+
+/// Tracks the progress of resolution and connection attempts.
+struct ConnectState {
+    port: u16,
+    /// IPv6 addresses not yet attempted
+    v6: Vec<IpAddr>,
+    /// IPv4 addresses not yet attempted
+    v4: Vec<IpAddr>,
+    /// Whether the next attempt should prefer IPv6;
+    /// `None` until the first attempt is made
+    prefer_v6: Option<bool>,
+    v6_done: bool,
+    v4_done: bool,
+    /// Time after which connection attempts may begin without `AAAA` results
+    resolution_deadline: Option<Instant>,
+    /// Time after which the next connection attempt may begin
+    next_attempt: Option<Instant>,
+    /// Time by which a connection must be established
+    deadline: Instant,
+    in_flight: usize,
+    /// Set when no further connections are wanted
+    cancelled: Arc<AtomicBool>,
+    resolve_err: Option<ResolveError>,
+    connect_err: Option<io::Error>,
+}
+
+impl ConnectState {
+    fn new(port: u16, deadline: Instant) -> ConnectState {
+        ConnectState{
+            port: port,
+            v6: Vec::new(),
+            v4: Vec::new(),
+            prefer_v6: None,
+            v6_done: false,
+            v4_done: false,
+            resolution_deadline: None,
+            next_attempt: None,
+            deadline: deadline,
+            in_flight: 0,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            resolve_err: None,
+            connect_err: None,
+        }
+    }
+
+    fn resolved(&mut self, r_type: RecordType, res: Result<Vec<IpAddr>, ResolveError>,
+            now: Instant) {
+        let addrs = match res {
+            Ok(addrs) => addrs,
+            Err(e) => {
+                self.resolve_err = Some(e);
+                Vec::new()
+            }
+        };
+
+        if r_type == RecordType::AAAA {
+            self.v6_done = true;
+            self.v6.extend(addrs);
+            self.resolution_deadline = None;
+        } else {
+            self.v4_done = true;
+            self.v4.extend(addrs);
+
+            if !self.v6_done {
+                self.resolution_deadline = Some(now + RESOLUTION_DELAY);
+            }
+        }
+    }
+
+    fn failed(&mut self, err: io::Error) {
+        self.in_flight -= 1;
+        self.connect_err = Some(err);
+        // Begin the next attempt immediately
+        self.next_attempt = None;
+    }
+
+    /// Returns whether attempts may begin, given the state of resolution.
+    fn may_attempt(&self, now: Instant) -> bool {
+        self.v6_done || match self.resolution_deadline {
+            Some(deadline) => now >= deadline,
+            None => false
+        }
+    }
+
+    fn start_attempts(&mut self, now: Instant, tx: &Sender<Event>) {
+        if !self.may_attempt(now) || now >= self.deadline {
+            return;
+        }
+
+        if let Some(next) = self.next_attempt {
+            if self.in_flight != 0 && now < next {
+                return;
+            }
+        }
+
+        if let Some(addr) = self.next_addr() {
+            let addr = SocketAddr::new(addr, self.port);
+            let tx = tx.clone();
+            let cancelled = self.cancelled.clone();
+
+            let timeout = self.deadline - now;
+
+            info!("attempting connection to {}", addr);
+
+            // Each attempt ends by the overall deadline, even once abandoned
+            thread::spawn(move || {
+                let res = TcpStream::connect_timeout(&addr, timeout);
+
+                // A connection which is no longer wanted is dropped, closing it
+                if !cancelled.load(Ordering::SeqCst) {
+                    let _ = tx.send(Event::Connected(res));
+                }
+            });
+
+            self.in_flight += 1;
+            self.next_attempt = Some(now + CONNECTION_ATTEMPT_DELAY);
+        }
+    }
+
+    /// Returns the next address to attempt, alternating address families.
+    fn next_addr(&mut self) -> Option<IpAddr> {
+        let prefer_v6 = match self.prefer_v6 {
+            Some(prefer) => prefer,
+            None => {
+                // RFC 8305, section 4: the first attempt is made to the
+                // address preferred by RFC 6724 among all those received
+                let mut addrs = self.v6.iter().chain(&self.v4).cloned().collect::<Vec<_>>();
+                sort_addresses(&mut addrs);
+                !matches!(addrs.first(), Some(&IpAddr::V4(_)))
+            }
+        };
+
+        let (first, second) = if prefer_v6 {
+            (&mut self.v6, &mut self.v4)
+        } else {
+            (&mut self.v4, &mut self.v6)
+        };
+
+        let addr = if !first.is_empty() {
+            Some(first.remove(0))
+        } else if !second.is_empty() {
+            Some(second.remove(0))
+        } else {
+            None
+        };
+
+        if let Some(addr) = addr {
+            self.prefer_v6 = Some(addr.is_ipv4());
+        }
+
+        addr
+    }
+
+    /// Returns the next time at which an attempt may be started, or the
+    /// overall deadline, if that is sooner.
+    fn next_deadline(&self) -> Instant {
+        let next = if self.v6.is_empty() && self.v4.is_empty() {
+            None
+        } else if !self.may_attempt(Instant::now()) {
+            self.resolution_deadline
+        } else {
+            self.next_attempt
+        };
+
+        next.map_or(self.deadline, |next| next.min(self.deadline))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.v6_done && self.v4_done && self.in_flight == 0 &&
+            self.v6.is_empty() && self.v4.is_empty()
+    }
+
+    fn take_error(&mut self) -> io::Error {
+        match self.connect_err.take() {
+            Some(e) => e,
+            None => io::Error::from(self.resolve_err.take().unwrap_or(ResolveError::NoData))
+        }
+    }
+}
+
+impl Drop for ConnectState {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener};
+    use std::sync::Arc;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use config::DnsConfig;
+    use message::{Message, Qr, Resource};
+    use record::{A, AAAA, Class, RecordType};
+    use resolver::DnsResolver;
+    use transport::MockTransport;
+    use super::{connect_host_with, ConnectState};
+
+    #[test]
+    fn test_connect_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"hello").unwrap();
+        });
+
+        // Nothing listens on the port at the IPv6 loopback address;
+        // the IPv4 attempt begins after that attempt fails.
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let q = &query.question[0];
+            let mut msg = Message::new();
+            msg.header.qr = Qr::Response;
+            msg.question = query.question.clone();
+
+            let mut rr = Resource::new(q.name.clone(), q.q_type, Class::Internet, 60);
+
+            if q.q_type == RecordType::AAAA {
+                rr.write_rdata(&AAAA{address: Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)})
+                    .unwrap();
+            } else {
+                rr.write_rdata(&A{address: Ipv4Addr::new(127, 0, 0, 1)}).unwrap();
+            }
+
+            msg.answer.push(rr);
+            Some(msg)
+        }));
+        let tcp = Arc::new(MockTransport::new(|_, _| None));
+
+        let config = DnsConfig::with_name_servers(vec!["192.0.2.1:53".parse().unwrap()]);
+        let resolver = Arc::new(DnsResolver::with_transport(config,
+            Box::new(udp.clone()), Box::new(tcp)));
+
+        let mut stream = connect_host_with(&resolver, "example.com.", port).unwrap();
+        let mut buf = String::new();
+        stream.read_to_string(&mut buf).unwrap();
+
+        assert_eq!(buf, "hello");
+        assert_eq!(udp.queries().len(), 2);
+    }
+
+    #[test]
+    fn test_deadline() {
+        let (tx, _rx) = channel();
+        let now = Instant::now();
+        let mut state = ConnectState::new(80, now + Duration::from_secs(1));
+
+        state.resolved(RecordType::AAAA, Ok(Vec::new()), now);
+        state.resolved(RecordType::A, Ok(vec![Ipv4Addr::new(192, 0, 2, 1).into()]), now);
+
+        assert_eq!(state.next_deadline(), now + Duration::from_secs(1));
+
+        // No attempt is started once the deadline has passed
+        state.start_attempts(now + Duration::from_secs(2), &tx);
+        assert_eq!(state.in_flight, 0);
+        assert!(!state.v4.is_empty());
+    }
+
+    #[test]
+    fn test_family_order() {
+        let now = Instant::now();
+        let mut state = ConnectState::new(80, now + Duration::from_secs(1));
+
+        // A Teredo address has lower precedence than any IPv4 address
+        let v6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 1));
+        let v4 = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        state.resolved(RecordType::AAAA, Ok(vec![v6]), now);
+        state.resolved(RecordType::A, Ok(vec![v4]), now);
+
+        assert_eq!(state.next_addr(), Some(v4));
+        assert_eq!(state.next_addr(), Some(v6));
+        assert_eq!(state.next_addr(), None);
+    }
+}
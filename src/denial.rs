@@ -0,0 +1,596 @@
+//! Authenticated denial of existence, using `NSEC` and `NSEC3` records
+//!
+//! The functions of this module check only that the records in a response
+//! prove the nonexistence of a name or type, as described in RFC 4035,
+//! section 5.4, and RFC 5155, section 8. Signatures over the records are
+//! not verified; that is done by `Validator`.
+
+use ring::digest;
+
+use dnssec::name_wire;
+use message::{Message, RCode, Resource};
+use name::Name;
+use record::{decode_base32hex, CName, Nsec, Nsec3, RecordType};
+
+/// Maximum number of additional `NSEC3` hash iterations. As recommended by
+/// RFC 9276, records with more iterations are not used as proof.
+pub const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// Verdict of checking the proof that a name or type does not exist
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Denial {
+    /// The name does not exist, nor does any wildcard which could match it
+    NoSuchName,
+    /// The name, or a wildcard matching it, exists, but has no records
+    /// of the queried type
+    NoData,
+    /// The name is a delegation to an unsigned zone; returned only
+    /// when `DS` records are queried
+    InsecureDelegation,
+    /// The name falls within a span of `NSEC3` records with the opt-out flag,
+    /// in which unsigned delegations may exist; its nonexistence is insecure
+    OptOut,
+    /// The records do not prove the nonexistence of the name or type
+    Unproven,
+}
+
+/// Checks the proof, in the authority section of a response, that the name
+/// and type of its question do not exist.
+///
+/// If the response code is `NameError`, the nonexistence of the name is
+/// checked; otherwise, that of the type. If the answer section contains
+/// a chain of `CNAME` records, the target of the chain is checked.
+pub fn check_denial(msg: &Message) -> Denial {
+    let q = match msg.question.first() {
+        Some(q) => q,
+        None => return Denial::Unproven
+    };
+
+    let name = match chain_target(&q.name, &msg.answer) {
+        Some(name) => name,
+        None => return Denial::Unproven
+    };
+
+    let name_error = msg.header.rcode == RCode::NameError;
+
+    match Records::new(&msg.authority) {
+        Records::Nsec(ref recs) => nsec_denial(&name, q.q_type, name_error, recs),
+        Records::Nsec3(ref recs) => nsec3_denial(&name, q.q_type, name_error, recs),
+        Records::None => Denial::Unproven,
+    }
+}
+
+/// Checks the proof, in the authority section of a response, that records
+/// owned by `name` and signed with the given number of labels were correctly
+/// synthesized from a wildcard; that is, that no closer match for `name`
+/// exists.
+///
+/// Returns `NoSuchName` if the proof holds.
+pub fn check_expansion(authority: &[Resource], name: &str, labels: u8) -> Denial {
+    let name = match Name::from_idna(name) {
+        Ok(name) => name,
+        Err(_) => return Denial::Unproven
+    };
+
+    let labels = labels as usize;
+
+    if labels >= name.num_labels() {
+        return Denial::Unproven;
+    }
+
+    match Records::new(authority) {
+        Records::Nsec(ref recs) => {
+            if recs.iter().any(|r| r.covers(&name)) {
+                Denial::NoSuchName
+            } else {
+                Denial::Unproven
+            }
+        }
+        Records::Nsec3(ref recs) => {
+            // The wildcard's parent is the closest encloser of `name`
+            let n = name.num_labels();
+            let next_closer = Name::from_raw_labels(name.labels()[n - labels - 1..].to_vec());
+
+            match recs.covering(&next_closer) {
+                Some(r) if r.is_opt_out() => Denial::OptOut,
+                Some(_) => Denial::NoSuchName,
+                None => Denial::Unproven
+            }
+        }
+        Records::None => Denial::Unproven,
+    }
+}
+
+/// Computes the `NSEC3` hash of a name, as described in RFC 5155, section 5.
+pub fn nsec3_hash(name: &Name, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut data = name_wire(&name.to_lowercase());
+    data.extend_from_slice(salt);
+
+    let mut hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &data);
+
+    for _ in 0..iterations {
+        let mut data = hash.as_ref().to_vec();
+        data.extend_from_slice(salt);
+        hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &data);
+    }
+
+    hash.as_ref().to_vec()
+}
+
+/// Returns the target of a chain of `CNAME` records beginning at `name`.
+fn chain_target(name: &str, answer: &[Resource]) -> Option<Name> {
+    let mut name = match Name::from_idna(name) {
+        Ok(name) => name,
+        Err(_) => return None
+    };
+
+    for _ in 0..answer.len() {
+        let next = answer.iter()
+            .filter(|rr| rr.r_type == RecordType::CName)
+            .find(|rr| Name::from_idna(&rr.name).ok().as_ref() == Some(&name))
+            .and_then(|rr| rr.read_rdata::<CName>().ok())
+            .and_then(|cname| Name::from_idna(&cname.name).ok());
+
+        match next {
+            Some(next) => name = next,
+            None => break
+        }
+    }
+
+    Some(name)
+}
+
+/// Returns the verdict for a record proving that its owner has
+/// no records of type `q_type`.
+fn nodata(q_type: RecordType, types: &[RecordType]) -> Denial {
+    let has = |t| types.contains(&t);
+
+    if has(q_type) || has(RecordType::CName) {
+        return Denial::Unproven;
+    }
+
+    if q_type == RecordType::Ds {
+        // The record must come from the parent side of a zone cut
+        return if has(RecordType::Soa) {
+            Denial::Unproven
+        } else if has(RecordType::Ns) {
+            Denial::InsecureDelegation
+        } else {
+            Denial::NoData
+        };
+    }
+
+    // At a zone cut, the parent's record says nothing of the child's types
+    if has(RecordType::Ns) && !has(RecordType::Soa) {
+        return Denial::Unproven;
+    }
+
+    Denial::NoData
+}
+
+/// Returns whether a record at an ancestor of a name shows that the name
+/// is beneath a zone cut or `DNAME` record, and so cannot be proven not
+/// to exist by the records of this zone, as described in RFC 6840, section 4.1.
+fn is_cut(types: &[RecordType]) -> bool {
+    types.contains(&RecordType::DName) ||
+        (types.contains(&RecordType::Ns) && !types.contains(&RecordType::Soa))
+}
+
+fn wildcard(name: &Name) -> Name {
+    let mut labels = vec![b"*".to_vec()];
+    labels.extend_from_slice(name.labels());
+    Name::from_raw_labels(labels)
+}
+
+/// Returns the longest name of which both `a` and `b` are subdomains.
+fn common_ancestor(a: &Name, b: &Name) -> Name {
+    let n = a.labels().iter().rev().zip(b.labels().iter().rev())
+        .take_while(|&(a, b)| a.eq_ignore_ascii_case(b)).count();
+
+    Name::from_raw_labels(a.labels()[a.num_labels() - n..].to_vec())
+}
+
+fn nsec_denial(name: &Name, q_type: RecordType, name_error: bool,
+        recs: &[NsecRecord]) -> Denial {
+    if !name_error {
+        if let Some(r) = recs.iter().find(|r| r.owner == *name) {
+            return nodata(q_type, &r.nsec.types);
+        }
+    }
+
+    let cover = match recs.iter().find(|r| r.covers(name)) {
+        Some(r) => r,
+        None => return Denial::Unproven
+    };
+
+    // A name followed in canonical order by one of its subdomains is an
+    // empty non-terminal; it exists, but has no records of any type,
+    // as described in RFC 4035, section 3.1.3.2.
+    if cover.next != *name && cover.next.is_subdomain_of(name) {
+        return if name_error { Denial::Unproven } else { nodata(q_type, &[]) };
+    }
+
+    // The closest existing ancestor of the name is the longer
+    // of those shared with the owner and next names
+    let ce = common_ancestor(name, &cover.owner);
+    let ce_next = common_ancestor(name, &cover.next);
+    let ce = if ce_next.num_labels() > ce.num_labels() { ce_next } else { ce };
+
+    let wildcard = wildcard(&ce);
+
+    if name_error {
+        if recs.iter().any(|r| r.covers(&wildcard)) {
+            Denial::NoSuchName
+        } else {
+            Denial::Unproven
+        }
+    } else {
+        match recs.iter().find(|r| r.owner == wildcard) {
+            Some(r) => nodata(q_type, &r.nsec.types),
+            None => Denial::Unproven
+        }
+    }
+}
+
+fn nsec3_denial(name: &Name, q_type: RecordType, name_error: bool,
+        recs: &Nsec3Records) -> Denial {
+    if !name_error {
+        if let Some(r) = recs.matching(name) {
+            return nodata(q_type, &r.nsec3.types);
+        }
+    }
+
+    let (ce, cover) = match recs.closest_encloser(name) {
+        Some(proof) => proof,
+        None => return Denial::Unproven
+    };
+
+    let wildcard = wildcard(&ce);
+
+    if name_error {
+        if recs.covering(&wildcard).is_none() {
+            Denial::Unproven
+        } else if cover.is_opt_out() {
+            Denial::OptOut
+        } else {
+            Denial::NoSuchName
+        }
+    } else {
+        match recs.matching(&wildcard) {
+            Some(r) if q_type != RecordType::Ds => nodata(q_type, &r.nsec3.types),
+            _ if cover.is_opt_out() => Denial::OptOut,
+            _ => Denial::Unproven
+        }
+    }
+}
+
+/// Denial records of the authority section of a response
+enum Records {
+    Nsec(Vec<NsecRecord>),
+    Nsec3(Nsec3Records),
+    None,
+}
+
+impl Records {
+    fn new(authority: &[Resource]) -> Records {
+        let mut nsec = Vec::new();
+        let mut nsec3 = Vec::new();
+
+        for rr in authority {
+            let owner = match Name::from_idna(&rr.name) {
+                Ok(owner) => owner,
+                Err(_) => continue
+            };
+
+            match rr.r_type {
+                RecordType::Nsec => {
+                    if let Ok(rec) = rr.read_rdata::<Nsec>() {
+                        if let Ok(next) = Name::from_idna(&rec.next_name) {
+                            nsec.push(NsecRecord{owner: owner, next: next, nsec: rec});
+                        }
+                    }
+                }
+                RecordType::Nsec3 => {
+                    let hash = owner.labels().first()
+                        .and_then(|l| ::std::str::from_utf8(l).ok())
+                        .and_then(decode_base32hex);
+
+                    if let (Some(hash), Ok(rec)) = (hash, rr.read_rdata::<Nsec3>()) {
+                        nsec3.push(Nsec3Record{
+                            zone: owner.parent().unwrap(),
+                            hash: hash,
+                            nsec3: rec,
+                        });
+                    }
+                }
+                _ => ()
+            }
+        }
+
+        if !nsec.is_empty() {
+            Records::Nsec(nsec)
+        } else if let Some(first) = nsec3.first().cloned() {
+            // All records must use the same hash parameters
+            let recs = nsec3.into_iter()
+                .filter(|r| r.zone == first.zone &&
+                    r.nsec3.hash_algorithm == first.nsec3.hash_algorithm &&
+                    r.nsec3.iterations == first.nsec3.iterations &&
+                    r.nsec3.salt == first.nsec3.salt)
+                .collect();
+
+            if first.nsec3.hash_algorithm != 1 ||
+                    first.nsec3.iterations > MAX_NSEC3_ITERATIONS {
+                return Records::None;
+            }
+
+            Records::Nsec3(Nsec3Records{
+                zone: first.zone,
+                salt: first.nsec3.salt,
+                iterations: first.nsec3.iterations,
+                recs: recs,
+            })
+        } else {
+            Records::None
+        }
+    }
+}
+
+struct NsecRecord {
+    owner: Name,
+    next: Name,
+    nsec: Nsec,
+}
+
+impl NsecRecord {
+    /// Returns whether the record proves that `name` does not exist.
+    fn covers(&self, name: &Name) -> bool {
+        let in_span = if self.owner < self.next {
+            self.owner < *name && *name < self.next
+        } else {
+            // The last record of a zone refers back to the apex
+            name.is_subdomain_of(&self.next) && (*name > self.owner || *name < self.next)
+        };
+
+        in_span && !(name.is_subdomain_of(&self.owner) && is_cut(&self.nsec.types))
+    }
+}
+
+#[derive(Clone)]
+struct Nsec3Record {
+    zone: Name,
+    hash: Vec<u8>,
+    nsec3: Nsec3,
+}
+
+impl Nsec3Record {
+    fn is_opt_out(&self) -> bool {
+        self.nsec3.flags & Nsec3::OPT_OUT != 0
+    }
+
+    fn covers(&self, hash: &[u8]) -> bool {
+        let (owner, next) = (&self.hash[..], &self.nsec3.next_hashed[..]);
+
+        if owner < next {
+            owner < hash && hash < next
+        } else {
+            hash > owner || hash < next
+        }
+    }
+}
+
+struct Nsec3Records {
+    zone: Name,
+    salt: Vec<u8>,
+    iterations: u16,
+    recs: Vec<Nsec3Record>,
+}
+
+impl Nsec3Records {
+    fn hash(&self, name: &Name) -> Option<Vec<u8>> {
+        if name.is_subdomain_of(&self.zone) {
+            Some(nsec3_hash(name, &self.salt, self.iterations))
+        } else {
+            None
+        }
+    }
+
+    fn matching(&self, name: &Name) -> Option<&Nsec3Record> {
+        self.hash(name).and_then(|h| self.recs.iter().find(|r| r.hash == h))
+    }
+
+    fn covering(&self, name: &Name) -> Option<&Nsec3Record> {
+        self.hash(name).and_then(|h| self.recs.iter().find(|r| r.covers(&h)))
+    }
+
+    /// Finds the closest existing ancestor of `name` and the record covering
+    /// the "next closer" name, one label longer, as described in RFC 5155,
+    /// section 8.3.
+    fn closest_encloser(&self, name: &Name) -> Option<(Name, &Nsec3Record)> {
+        let mut next_closer = name.clone();
+
+        while let Some(ce) = next_closer.parent() {
+            if let Some(r) = self.matching(&ce) {
+                if is_cut(&r.nsec3.types) && ce != self.zone {
+                    return None;
+                }
+
+                return self.covering(&next_closer).map(|cover| (ce, cover));
+            }
+
+            next_closer = ce;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use message::{Message, Question, RCode, Resource};
+    use name::Name;
+    use record::{encode_base32hex, Class, CName, Nsec, Nsec3, Record, RecordType};
+    use super::{check_denial, check_expansion, nsec3_hash, Denial};
+
+    fn rr<R: Record>(name: &str, rec: &R) -> Resource<'static> {
+        let mut rr = Resource::new(name.to_owned(), R::record_type(), Class::Internet, 3600);
+        rr.write_rdata(rec).unwrap();
+        rr
+    }
+
+    fn response(name: &str, q_type: RecordType, rcode: RCode,
+            authority: Vec<Resource<'static>>) -> Message<'static> {
+        let mut msg = Message::new();
+        msg.header.rcode = rcode;
+        msg.question.push(Question::new(name.to_owned(), q_type, Class::Internet));
+        msg.authority = authority;
+        msg
+    }
+
+    fn nsec(owner: &str, rdata: &str) -> Resource<'static> {
+        rr(owner, &rdata.parse::<Nsec>().unwrap())
+    }
+
+    fn nsec3(rdata: &str) -> Resource<'static> {
+        let (owner, rdata) = rdata.split_at(rdata.find(' ').unwrap());
+        rr(owner, &rdata.parse::<Nsec3>().unwrap())
+    }
+
+    #[test]
+    fn test_nsec3_hash() {
+        // Examples from RFC 5155, Appendix A
+        let hash = |name: &str| {
+            let name = Name::from_idna(name).unwrap();
+            encode_base32hex(&nsec3_hash(&name, &[0xaa, 0xbb, 0xcc, 0xdd], 12))
+        };
+
+        assert_eq!(hash("example."), "0P9MHAVEQVM6T7VBL5LOP2U3T2RP3TOM");
+        assert_eq!(hash("A.EXAMPLE."), "35MTHGPGCU1QG68FAB165KLNSNK3DPVL");
+        assert_eq!(hash("ns1.example."), "2T7B4G4VSA5SMI47K61MV5BV1A22BOJR");
+        assert_eq!(hash("*.w.example."), "R53BQ7CC2UVMUBFU5OCMM6PERS9TK9EN");
+        assert_eq!(hash("x.w.example."), "B4UM86EGHHDS6NEA196SMVMLO4ORS995");
+    }
+
+    #[test]
+    fn test_nsec() {
+        let chain = || vec![
+            nsec("example.", "a.example. NS SOA RRSIG NSEC DNSKEY"),
+            nsec("a.example.", "b.example. NS DS RRSIG NSEC"),
+            nsec("b.example.", "c.example. NS RRSIG NSEC"),
+            nsec("c.example.", "*.w.example. MX RRSIG NSEC"),
+            nsec("*.w.example.", "x.example. MX RRSIG NSEC"),
+            nsec("x.example.", "example. A RRSIG NSEC"),
+        ];
+        let check = |name: &str, q_type, rcode| {
+            check_denial(&response(name, q_type, rcode, chain()))
+        };
+
+        assert_eq!(check("bb.example.", RecordType::A, RCode::NameError), Denial::NoSuchName);
+        assert_eq!(check("z.example.", RecordType::A, RCode::NameError), Denial::NoSuchName);
+        assert_eq!(check("c.example.", RecordType::A, RCode::NoError), Denial::NoData);
+        assert_eq!(check("c.example.", RecordType::Mx, RCode::NoError), Denial::Unproven);
+        assert_eq!(check("b.example.", RecordType::Ds, RCode::NoError),
+            Denial::InsecureDelegation);
+
+        // Parent side of a delegation cannot prove anything about the child
+        assert_eq!(check("b.example.", RecordType::A, RCode::NoError), Denial::Unproven);
+        assert_eq!(check("x.b.example.", RecordType::A, RCode::NameError), Denial::Unproven);
+
+        // A wildcard exists beneath w.example.
+        assert_eq!(check("v.w.example.", RecordType::A, RCode::NameError), Denial::Unproven);
+        assert_eq!(check("v.w.example.", RecordType::A, RCode::NoError), Denial::NoData);
+        assert_eq!(check("v.w.example.", RecordType::Mx, RCode::NoError), Denial::Unproven);
+
+        // Empty non-terminal; the wildcard's types are not those of its parent
+        assert_eq!(check("w.example.", RecordType::Mx, RCode::NoError), Denial::NoData);
+        assert_eq!(check("w.example.", RecordType::A, RCode::NameError), Denial::Unproven);
+
+        let ent = vec![
+            nsec("example.", "a.b.example. SOA RRSIG NSEC DNSKEY"),
+            nsec("a.b.example.", "example. A RRSIG NSEC"),
+        ];
+        let msg = response("b.example.", RecordType::A, RCode::NoError, ent);
+        assert_eq!(check_denial(&msg), Denial::NoData);
+
+        // Names outside the zone are not covered by the last record
+        assert_eq!(check("a.", RecordType::A, RCode::NameError), Denial::Unproven);
+
+        assert_eq!(check_expansion(&chain(), "v.w.example.", 2), Denial::NoSuchName);
+        assert_eq!(check_expansion(&chain(), "c.example.", 1), Denial::Unproven);
+
+        // The target of a CNAME chain is checked
+        let mut msg = response("alias.example.", RecordType::A, RCode::NameError, chain());
+        msg.answer.push(rr("alias.example.", &CName{name: "bb.example.".to_owned()}));
+        assert_eq!(check_denial(&msg), Denial::NoSuchName);
+
+        let msg = response("bb.example.", RecordType::A, RCode::NameError, Vec::new());
+        assert_eq!(check_denial(&msg), Denial::Unproven);
+    }
+
+    #[test]
+    fn test_nsec3() {
+        // Examples from RFC 5155, Appendix B, without the opt-out flag
+        let nxdomain = || vec![
+            nsec3("0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example. 1 0 12 aabbccdd \
+                2t7b4g4vsa5smi47k61mv5bv1a22bojr MX DNSKEY NS SOA NSEC3PARAM RRSIG"),
+            nsec3("b4um86eghhds6nea196smvmlo4ors995.example. 1 0 12 aabbccdd \
+                gjeqe526plbf1g8mklp59enfd789njgi MX RRSIG"),
+            nsec3("35mthgpgcu1qg68fab165klnsnk3dpvl.example. 1 0 12 aabbccdd \
+                b4um86eghhds6nea196smvmlo4ors995 NS DS RRSIG"),
+        ];
+
+        let msg = response("a.c.x.w.example.", RecordType::A, RCode::NameError, nxdomain());
+        assert_eq!(check_denial(&msg), Denial::NoSuchName);
+
+        let nodata = vec![nsec3("2t7b4g4vsa5smi47k61mv5bv1a22bojr.example. 1 0 12 aabbccdd \
+            2vptu5timamqttgl4luu9kg21e0aor3s A RRSIG")];
+
+        let msg = response("ns1.example.", RecordType::Mx, RCode::NoError, nodata.clone());
+        assert_eq!(check_denial(&msg), Denial::NoData);
+        let msg = response("ns1.example.", RecordType::A, RCode::NoError, nodata);
+        assert_eq!(check_denial(&msg), Denial::Unproven);
+
+        // Empty non-terminal
+        let msg = response("y.w.example.", RecordType::A, RCode::NoError, vec![
+            nsec3("ji6neoaepv8b5o6k4ev33abha8ht9fgc.example. 1 0 12 aabbccdd \
+                k8udemvp1j2f7eg6jebps17vp3n8i58h")]);
+        assert_eq!(check_denial(&msg), Denial::NoData);
+
+        // Wildcard no data
+        let wildcard = vec![
+            nsec3("k8udemvp1j2f7eg6jebps17vp3n8i58h.example. 1 0 12 aabbccdd \
+                kohar7mbb8dc2ce8a9qvl8hon4k53uhi"),
+            nsec3("q04jkcevqvmu85r014c7dkba38o0ji5r.example. 1 0 12 aabbccdd \
+                r53bq7cc2uvmubfu5ocmm6pers9tk9en A RRSIG"),
+            nsec3("r53bq7cc2uvmubfu5ocmm6pers9tk9en.example. 1 0 12 aabbccdd \
+                t644ebqk9bibcna874givr6joj62mlhv MX RRSIG"),
+        ];
+
+        let msg = response("a.z.w.example.", RecordType::AAAA, RCode::NoError,
+            wildcard.clone());
+        assert_eq!(check_denial(&msg), Denial::NoData);
+        let msg = response("a.z.w.example.", RecordType::Mx, RCode::NoError,
+            wildcard.clone());
+        assert_eq!(check_denial(&msg), Denial::Unproven);
+
+        // Wildcard expansion of a.z.w.example. from *.w.example.
+        assert_eq!(check_expansion(&wildcard, "a.z.w.example.", 2), Denial::NoSuchName);
+
+        // A name error cannot be proven where the next closer name
+        // is covered by an opt-out record
+        let opt_out = nxdomain().into_iter().map(|mut rr| {
+            let mut rec = rr.read_rdata::<Nsec3>().unwrap();
+            rec.flags = Nsec3::OPT_OUT;
+            rr.write_rdata(&rec).unwrap();
+            rr
+        }).collect::<Vec<_>>();
+
+        let msg = response("a.c.x.w.example.", RecordType::A, RCode::NameError, opt_out);
+        assert_eq!(check_denial(&msg), Denial::OptOut);
+
+        // Unsupported parameters
+        let msg = response("ns1.example.", RecordType::Mx, RCode::NoError,
+            vec![nsec3("2t7b4g4vsa5smi47k61mv5bv1a22bojr.example. 1 0 500 aabbccdd \
+                2vptu5timamqttgl4luu9kg21e0aor3s A RRSIG")]);
+        assert_eq!(check_denial(&msg), Denial::Unproven);
+    }
+}
@@ -0,0 +1,1139 @@
+//! DNSSEC validation, as described in RFC 4033, RFC 4034, and RFC 4035
+
+use std::collections::HashMap;
+use std::fmt;
+use std::slice;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::digest;
+use ring::signature::{self, RsaParameters, RsaPublicKeyComponents, UnparsedPublicKey,
+    VerificationAlgorithm};
+
+use denial::{check_denial, check_expansion, Denial};
+use message::{Message, RCode, Resource};
+use name::Name;
+use record::{tokenize, Class, Dnskey, Ds, Fields, ParseError, ParseRecord, RecordType, Rrsig};
+use resolver::ResolveError;
+
+/// Security status of a set of records, as described in RFC 4035, section 4.3
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Validation {
+    /// Records were verified by a chain of signatures from a trust anchor
+    Secure,
+    /// Records are known, by a chain of signatures from a trust anchor,
+    /// to belong to an unsigned zone
+    Insecure,
+    /// Records should be signed, but signatures are missing or invalid
+    Bogus,
+    /// No trust anchor covers the records
+    Indeterminate,
+}
+
+impl Validation {
+    /// Returns the status of a combination of records with the status
+    /// of `self` and of `other`; that is, the less secure of the two.
+    pub fn and(self, other: Validation) -> Validation {
+        fn rank(v: Validation) -> u8 {
+            match v {
+                Validation::Bogus => 0,
+                Validation::Indeterminate => 1,
+                Validation::Insecure => 2,
+                Validation::Secure => 3,
+            }
+        }
+
+        if rank(other) < rank(self) { other } else { self }
+    }
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Validation::Secure => "secure",
+            Validation::Insecure => "insecure",
+            Validation::Bogus => "bogus",
+            Validation::Indeterminate => "indeterminate",
+        })
+    }
+}
+
+/// A `Ds` record trusted to identify a key of a zone, from which
+/// chains of signatures are verified
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrustAnchor {
+    /// Name of the zone
+    pub name: String,
+    /// Delegation signer record identifying a key of the zone
+    pub ds: Ds,
+}
+
+impl FromStr for TrustAnchor {
+    type Err = ParseError;
+
+    /// Parses a trust anchor in master file format, without a TTL;
+    /// e.g. `example.com. IN DS 12345 8 2 <digest>`. The class is optional.
+    fn from_str(s: &str) -> Result<TrustAnchor, ParseError> {
+        let mut f = Fields::new(try!(tokenize(s)), None);
+        let name = try!(f.name());
+        let mut tok = try!(f.next());
+
+        if tok.text.parse::<Class>() == Ok(Class::Internet) {
+            tok = try!(f.next());
+        }
+
+        if try!(tok.text.parse::<RecordType>()) != RecordType::Ds {
+            return Err(ParseError::UnknownType);
+        }
+
+        let ds = try!(Ds::parse(&mut f));
+        try!(f.finish());
+
+        Ok(TrustAnchor{name: name, ds: ds})
+    }
+}
+
+/// Longest time, in seconds, for which validated zone keys are cached
+const MAX_KEYS_TTL: u32 = 86400;
+
+/// Validates the signatures of records, beginning from a set of trust anchors
+///
+/// The keys of each zone validated are cached until their records or
+/// signatures expire, and are shared by all checks made by the validator.
+pub struct Validator {
+    anchors: Vec<TrustAnchor>,
+    /// Validated zone keys, with the time at which each expires
+    zones: Mutex<HashMap<Name, (ZoneKeys, u32)>>,
+}
+
+impl Clone for Validator {
+    /// Returns a validator with the same trust anchors and an empty cache.
+    fn clone(&self) -> Validator {
+        Validator::new(self.anchors.clone())
+    }
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Validator")
+            .field("anchors", &self.anchors)
+            .finish()
+    }
+}
+
+impl Validator {
+    /// Constructs a `Validator` using the given trust anchors.
+    pub fn new(anchors: Vec<TrustAnchor>) -> Validator {
+        Validator{
+            anchors: anchors,
+            zones: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Discards all cached zone keys.
+    pub fn clear_cache(&self) {
+        if let Ok(mut zones) = self.zones.lock() {
+            zones.clear();
+        }
+    }
+
+    /// Returns the trust anchors of the validator.
+    pub fn anchors(&self) -> &[TrustAnchor] {
+        &self.anchors
+    }
+
+    /// Validates each set of records in the answer section of a response,
+    /// returning the status of the least secure.
+    ///
+    /// `query` is called to fetch the `DNSKEY`, `DS`, and `SOA` records
+    /// needed to build each chain of signatures. Its responses should
+    /// include `RRSIG` records; that is, queries should set the `DO` bit.
+    pub fn validate_answer<F>(&self, msg: &Message, query: F) -> Validation
+            where F: FnMut(&str, RecordType) -> Result<Message<'static>, ResolveError> {
+        Context::new(self, query).validate_answer(msg)
+    }
+
+    /// Validates the proof, in the authority section of a response, that the
+    /// name or type of its question does not exist.
+    ///
+    /// Returns the status of the proof, along with the verdict of
+    /// `check_denial`. The status is `Secure` only if the records of the
+    /// authority section are secure and prove the denial.
+    pub fn validate_denial<F>(&self, msg: &Message, query: F) -> (Validation, Denial)
+            where F: FnMut(&str, RecordType) -> Result<Message<'static>, ResolveError> {
+        Context::new(self, query).validate_denial(msg)
+    }
+
+    /// Validates a complete response, as `validate_answer` does. If the
+    /// response code is `NameError` or the answer section is empty, the proof
+    /// of denial is also validated, as by `validate_denial`.
+    ///
+    /// Returns the status of the least secure part of the response, along
+    /// with the verdict on its proof of denial, if one was checked.
+    pub fn validate_response<F>(&self, msg: &Message, query: F)
+            -> (Validation, Option<Denial>)
+            where F: FnMut(&str, RecordType) -> Result<Message<'static>, ResolveError> {
+        let mut cx = Context::new(self, query);
+        let status = cx.validate_answer(msg);
+
+        if msg.header.rcode == RCode::NameError || msg.answer.is_empty() {
+            let (res, denial) = cx.validate_denial(msg);
+            (status.and(res), Some(denial))
+        } else {
+            (status, None)
+        }
+    }
+}
+
+/// Keys of a zone, or the reason that no keys are trusted
+#[derive(Clone, Debug)]
+enum ZoneKeys {
+    Secure(Vec<Dnskey>),
+    Status(Validation),
+}
+
+/// State of a single validation
+struct Context<'a, F> {
+    anchors: &'a [TrustAnchor],
+    cache: &'a Mutex<HashMap<Name, (ZoneKeys, u32)>>,
+    query: F,
+    /// Current time, in seconds since the epoch, modulo 2^32
+    now: u32,
+    zones: HashMap<Name, ZoneKeys>,
+}
+
+impl<'a, F> Context<'a, F>
+        where F: FnMut(&str, RecordType) -> Result<Message<'static>, ResolveError> {
+    fn new(validator: &'a Validator, query: F) -> Context<'a, F> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32).unwrap_or(0);
+
+        Context{
+            anchors: &validator.anchors,
+            cache: &validator.zones,
+            query: query,
+            now: now,
+            zones: HashMap::new(),
+        }
+    }
+
+    /// Validates each set of records in the answer section of a response.
+    fn validate_answer(&mut self, msg: &Message) -> Validation {
+        let mut status = Validation::Secure;
+
+        for (rrset, sigs) in rrsets(&msg.answer) {
+            let (res, wildcard) = self.verify_rrset(&rrset, &sigs);
+            status = status.and(res);
+
+            // Records synthesized from a wildcard are secure only if
+            // the name queried does not itself exist
+            if let Some(labels) = wildcard {
+                let proof = check_expansion(&msg.authority, &rrset[0].name, labels);
+
+                status = status.and(match proof {
+                    Denial::NoSuchName => self.validate_section(&msg.authority),
+                    Denial::OptOut => Validation::Insecure,
+                    _ => Validation::Bogus
+                });
+            }
+        }
+
+        status
+    }
+
+    /// Validates a set of records with the same owner, type, and class,
+    /// given the signatures covering them.
+    fn validate_rrset(&mut self, rrset: &[&Resource], sigs: &[Rrsig]) -> Validation {
+        self.verify_rrset(rrset, sigs).0
+    }
+
+    /// Validates each set of records in a section of a response.
+    fn validate_section(&mut self, records: &[Resource]) -> Validation {
+        rrsets(records).into_iter().fold(Validation::Secure,
+            |status, (rrset, sigs)| status.and(self.validate_rrset(&rrset, &sigs)))
+    }
+
+    /// Validates a set of records, as `validate_rrset`. If the records were
+    /// synthesized from a wildcard, also returns the number of labels
+    /// of the verified signature.
+    fn verify_rrset(&mut self, rrset: &[&Resource], sigs: &[Rrsig])
+            -> (Validation, Option<u8>) {
+        let owner = match Name::from_idna(&rrset[0].name) {
+            Ok(name) => name,
+            Err(_) => return (Validation::Bogus, None)
+        };
+        let is_ds = rrset[0].r_type == RecordType::Ds;
+
+        for sig in sigs {
+            let signer = match Name::from_idna(&sig.signer_name) {
+                Ok(name) => name,
+                Err(_) => continue
+            };
+
+            // `DS` records are signed by the parent zone
+            if !owner.is_subdomain_of(&signer) || (is_ds && signer == owner) ||
+                    sig.labels as usize > owner.num_labels() || !self.is_current(sig) {
+                continue;
+            }
+
+            if let ZoneKeys::Secure(keys) = self.zone_keys(&signer) {
+                if keys.iter().any(|key| verify_rrset(rrset, sig, key)) {
+                    let wildcard = if (sig.labels as usize) < owner.num_labels() &&
+                            owner.labels()[0] != b"*" {
+                        Some(sig.labels)
+                    } else {
+                        None
+                    };
+
+                    return (Validation::Secure, wildcard);
+                }
+            }
+        }
+
+        // No signature could be verified; records are acceptable only if
+        // they belong to a zone which is not secure.
+        let name = if is_ds { owner.parent() } else { Some(owner) };
+
+        match name {
+            Some(ref name) => (self.unsigned_status(name), None),
+            None => (Validation::Bogus, None)
+        }
+    }
+
+    /// Validates the proof of nonexistence in a response.
+    fn validate_denial(&mut self, msg: &Message) -> (Validation, Denial) {
+        let denial = check_denial(msg);
+
+        let status = if msg.authority.is_empty() {
+            match msg.question.first().map(|q| Name::from_idna(&q.name)) {
+                Some(Ok(ref name)) => self.unsigned_status(name),
+                _ => Validation::Bogus
+            }
+        } else {
+            self.validate_section(&msg.authority)
+        };
+
+        let status = match (status, denial) {
+            (Validation::Secure, Denial::Unproven) => Validation::Bogus,
+            (Validation::Secure, Denial::OptOut) => Validation::Insecure,
+            (status, _) => status
+        };
+
+        (status, denial)
+    }
+
+    /// Returns the status of unsigned records owned by `name`.
+    fn unsigned_status(&mut self, name: &Name) -> Validation {
+        match self.find_zone(name) {
+            Some(zone) => match self.zone_keys(&zone) {
+                ZoneKeys::Secure(_) => Validation::Bogus,
+                ZoneKeys::Status(status) => status,
+            },
+            None if self.is_anchored(name) => Validation::Bogus,
+            None => Validation::Indeterminate
+        }
+    }
+
+    /// Returns the apex of the zone containing `name`, found by querying
+    /// its `SOA` record.
+    fn find_zone(&mut self, name: &Name) -> Option<Name> {
+        let msg = match (self.query)(&name.to_string(), RecordType::Soa) {
+            Ok(msg) => msg,
+            Err(_) => return None
+        };
+
+        msg.answer.iter().chain(&msg.authority)
+            .filter(|rr| rr.r_type == RecordType::Soa)
+            .filter_map(|rr| Name::from_idna(&rr.name).ok())
+            .find(|zone| name.is_subdomain_of(zone))
+    }
+
+    /// Returns whether a trust anchor is held for any zone which
+    /// encloses `name`.
+    fn is_anchored(&self, name: &Name) -> bool {
+        self.anchors.iter().any(|a| match Name::from_idna(&a.name) {
+            Ok(ref zone) => name.is_subdomain_of(zone),
+            Err(_) => false
+        })
+    }
+
+    /// Returns the trusted keys of a zone.
+    fn zone_keys(&mut self, zone: &Name) -> ZoneKeys {
+        if let Some(keys) = self.zones.get(zone) {
+            return keys.clone();
+        }
+
+        let cached = self.cache.lock().ok().and_then(|cache| cache.get(zone).cloned());
+
+        if let Some((keys, _)) = cached.filter(|&(_, expires)| self.is_before(expires)) {
+            self.zones.insert(zone.clone(), keys.clone());
+            return keys;
+        }
+
+        // Guards against cycles in the chain of trust
+        self.zones.insert(zone.clone(), ZoneKeys::Status(Validation::Bogus));
+
+        let (keys, expires) = self.fetch_zone_keys(zone);
+        self.zones.insert(zone.clone(), keys.clone());
+
+        // Failures may be transient, so only conclusive results are kept
+        if let Some(expires) = expires {
+            if let Ok(mut cache) = self.cache.lock() {
+                cache.insert(zone.clone(), (keys.clone(), expires));
+            }
+        }
+
+        keys
+    }
+
+    /// Fetches and verifies the keys of a zone. If the result may be cached,
+    /// also returns the time at which it expires.
+    fn fetch_zone_keys(&mut self, zone: &Name) -> (ZoneKeys, Option<u32>) {
+        let mut expires = self.now.wrapping_add(MAX_KEYS_TTL);
+
+        let mut ds_set = self.anchors.iter()
+            .filter(|a| Name::from_idna(&a.name).ok().as_ref() == Some(zone))
+            .map(|a| a.ds.clone()).collect::<Vec<_>>();
+
+        if ds_set.is_empty() {
+            if zone.is_root() || !self.is_anchored(&zone.parent().unwrap()) {
+                return (ZoneKeys::Status(Validation::Indeterminate), None);
+            }
+
+            let msg = match (self.query)(&zone.to_string(), RecordType::Ds) {
+                Ok(msg) => msg,
+                Err(_) => return (ZoneKeys::Status(Validation::Bogus), None)
+            };
+
+            let mut sets = rrsets(&msg.answer).into_iter()
+                .filter(|&(ref rrset, _)| rrset[0].r_type == RecordType::Ds &&
+                    Name::from_idna(&rrset[0].name).ok().as_ref() == Some(zone));
+
+            let (rrset, sigs) = match sets.next() {
+                Some(set) => set,
+                // Without a DS record, the zone is unsigned, but only if
+                // the parent zone proves the delegation to be insecure
+                None => return match self.validate_denial(&msg) {
+                    (Validation::Secure, Denial::InsecureDelegation) => {
+                        let ttl = msg.authority.iter().map(|rr| rr.ttl).min().unwrap_or(0);
+                        let expires = self.expiry(expires, ttl, &[]);
+                        (ZoneKeys::Status(Validation::Insecure), Some(expires))
+                    }
+                    (Validation::Secure, _) => (ZoneKeys::Status(Validation::Bogus), None),
+                    (status, _) => (ZoneKeys::Status(status), None)
+                }
+            };
+
+            match self.validate_rrset(&rrset, &sigs) {
+                Validation::Secure => (),
+                status => return (ZoneKeys::Status(status), None)
+            }
+
+            expires = self.expiry(expires, rrset[0].ttl, &sigs);
+
+            for rr in rrset {
+                match rr.read_rdata::<Ds>() {
+                    Ok(ds) => ds_set.push(ds),
+                    Err(_) => return (ZoneKeys::Status(Validation::Bogus), None)
+                }
+            }
+        }
+
+        // A zone whose keys use only unsupported algorithms is treated
+        // as unsigned, as described in RFC 4035, section 5.2.
+        ds_set.retain(|ds| ds_digest(ds.digest_type).is_some() &&
+            verification_algorithm(ds.algorithm).is_some());
+
+        if ds_set.is_empty() {
+            return (ZoneKeys::Status(Validation::Insecure), Some(expires));
+        }
+
+        let msg = match (self.query)(&zone.to_string(), RecordType::Dnskey) {
+            Ok(msg) => msg,
+            Err(_) => return (ZoneKeys::Status(Validation::Bogus), None)
+        };
+
+        for (rrset, sigs) in rrsets(&msg.answer) {
+            if rrset[0].r_type != RecordType::Dnskey ||
+                    Name::from_idna(&rrset[0].name).ok().as_ref() != Some(zone) {
+                continue;
+            }
+
+            let keys = rrset.iter().filter_map(|rr| rr.read_rdata::<Dnskey>().ok())
+                .filter(|key| key.protocol == Dnskey::PROTOCOL &&
+                    key.flags & Dnskey::ZONE_KEY != 0 && key.flags & Dnskey::REVOKE == 0)
+                .collect::<Vec<_>>();
+
+            // The key set must be signed by a key identified by a DS record
+            let verified = sigs.iter()
+                .filter(|sig| Name::from_idna(&sig.signer_name).ok().as_ref() == Some(zone) &&
+                    self.is_current(sig))
+                .find(|sig| keys.iter()
+                    .filter(|key| ds_set.iter().any(|ds| ds_matches(ds, zone, key)))
+                    .any(|key| verify_rrset(&rrset, sig, key)));
+
+            if let Some(sig) = verified {
+                let expires = self.expiry(expires, rrset[0].ttl, slice::from_ref(sig));
+                return (ZoneKeys::Secure(keys), Some(expires));
+            }
+        }
+
+        (ZoneKeys::Status(Validation::Bogus), None)
+    }
+
+    /// Returns whether the current time falls within the validity period
+    /// of a signature, using serial number arithmetic.
+    fn is_current(&self, sig: &Rrsig) -> bool {
+        self.now.wrapping_sub(sig.inception) as i32 >= 0 &&
+            sig.expiration.wrapping_sub(self.now) as i32 >= 0
+    }
+
+    /// Returns whether the current time is before `time`.
+    fn is_before(&self, time: u32) -> bool {
+        time.wrapping_sub(self.now) as i32 > 0
+    }
+
+    /// Returns the earliest of `expires`, the expiry of records with the
+    /// given TTL, and the expiration of each signature.
+    fn expiry(&self, expires: u32, ttl: u32, sigs: &[Rrsig]) -> u32 {
+        let ttl = self.now.wrapping_add(ttl.min(MAX_KEYS_TTL));
+
+        sigs.iter().map(|sig| sig.expiration).chain(Some(ttl))
+            .fold(expires, |a, b| if (b.wrapping_sub(a) as i32) < 0 { b } else { a })
+    }
+}
+
+/// Groups records into sets of the same owner, type, and class, each with
+/// the `RRSIG` records which cover it.
+fn rrsets<'a, 'b>(records: &'a [Resource<'b>]) -> Vec<(Vec<&'a Resource<'b>>, Vec<Rrsig>)> {
+    let mut sets: Vec<(Vec<&Resource>, Vec<Rrsig>)> = Vec::new();
+
+    for rr in records.iter().filter(|rr| rr.r_type != RecordType::Rrsig) {
+        match sets.iter_mut().find(|&&mut (ref set, _)| same_rrset(set[0], rr)) {
+            Some(&mut (ref mut set, _)) => set.push(rr),
+            None => sets.push((vec![rr], Vec::new()))
+        }
+    }
+
+    for rr in records.iter().filter(|rr| rr.r_type == RecordType::Rrsig) {
+        let sig = match rr.read_rdata::<Rrsig>() {
+            Ok(sig) => sig,
+            Err(_) => continue
+        };
+
+        for &mut (ref set, ref mut sigs) in &mut sets {
+            if set[0].r_type == sig.type_covered && same_owner(set[0], rr) &&
+                    set[0].r_class == rr.r_class {
+                sigs.push(sig.clone());
+            }
+        }
+    }
+
+    sets
+}
+
+fn same_rrset(a: &Resource, b: &Resource) -> bool {
+    a.r_type == b.r_type && a.r_class == b.r_class && same_owner(a, b)
+}
+
+fn same_owner(a: &Resource, b: &Resource) -> bool {
+    match (Name::from_idna(&a.name), Name::from_idna(&b.name)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false
+    }
+}
+
+/// Returns whether a key is identified by a `DS` record.
+fn ds_matches(ds: &Ds, zone: &Name, key: &Dnskey) -> bool {
+    if key.key_tag() != ds.key_tag || key.algorithm != ds.algorithm {
+        return false;
+    }
+
+    match ds_digest(ds.digest_type) {
+        Some(alg) => {
+            let mut data = name_wire(&zone.to_lowercase());
+            data.extend_from_slice(&key_rdata(key));
+            digest::digest(alg, &data).as_ref() == &ds.digest[..]
+        }
+        None => false
+    }
+}
+
+/// Returns the digest algorithm of a `DS` digest type number.
+fn ds_digest(digest_type: u8) -> Option<&'static digest::Algorithm> {
+    match digest_type {
+        1 => Some(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        2 => Some(&digest::SHA256),
+        4 => Some(&digest::SHA384),
+        _ => None
+    }
+}
+
+/// Verifies a signature over a set of records using the given key.
+fn verify_rrset(rrset: &[&Resource], sig: &Rrsig, key: &Dnskey) -> bool {
+    if key.key_tag() != sig.key_tag || key.algorithm != sig.algorithm {
+        return false;
+    }
+
+    match signed_data(rrset, sig) {
+        Some(data) => verify_signature(key, &data, &sig.signature),
+        None => false
+    }
+}
+
+enum Algorithm {
+    Rsa(&'static RsaParameters),
+    Ecdsa(&'static dyn VerificationAlgorithm),
+    Ed25519,
+}
+
+/// Returns the verification algorithm of a DNSSEC algorithm number.
+fn verification_algorithm(algorithm: u8) -> Option<Algorithm> {
+    match algorithm {
+        // RSASHA1 and RSASHA1-NSEC3-SHA1
+        5 | 7 => Some(Algorithm::Rsa(&signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY)),
+        8 => Some(Algorithm::Rsa(&signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY)),
+        10 => Some(Algorithm::Rsa(&signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY)),
+        13 => Some(Algorithm::Ecdsa(&signature::ECDSA_P256_SHA256_FIXED)),
+        14 => Some(Algorithm::Ecdsa(&signature::ECDSA_P384_SHA384_FIXED)),
+        15 => Some(Algorithm::Ed25519),
+        _ => None
+    }
+}
+
+/// Verifies a signature using the public key of a `Dnskey`.
+fn verify_signature(key: &Dnskey, data: &[u8], sig: &[u8]) -> bool {
+    let key_data = &key.public_key[..];
+
+    match verification_algorithm(key.algorithm) {
+        Some(Algorithm::Rsa(params)) => {
+            // RFC 3110: exponent length, exponent, modulus
+            let (len, rest) = match key_data.split_first() {
+                Some((&0, rest)) if rest.len() >= 2 =>
+                    (u16::from_be_bytes([rest[0], rest[1]]) as usize, &rest[2..]),
+                Some((&n, rest)) => (n as usize, rest),
+                None => return false
+            };
+
+            if len == 0 || rest.len() <= len {
+                return false;
+            }
+
+            let (e, n) = rest.split_at(len);
+            RsaPublicKeyComponents{n: n, e: e}.verify(params, data, sig).is_ok()
+        }
+        Some(Algorithm::Ecdsa(alg)) => {
+            // Keys are given as uncompressed points, without the leading 4
+            let mut point = vec![4];
+            point.extend_from_slice(key_data);
+            UnparsedPublicKey::new(alg, &point).verify(data, sig).is_ok()
+        }
+        Some(Algorithm::Ed25519) =>
+            UnparsedPublicKey::new(&signature::ED25519, key_data).verify(data, sig).is_ok(),
+        None => false
+    }
+}
+
+/// Returns the data over which an `Rrsig` is computed for a set of records,
+/// as described in RFC 4034, section 3.1.8.1.
+///
+/// Returns `None` if the records cannot be put in canonical form.
+fn signed_data(rrset: &[&Resource], sig: &Rrsig) -> Option<Vec<u8>> {
+    let (signer, owner) = match (Name::from_idna(&sig.signer_name),
+            Name::from_idna(&rrset[0].name)) {
+        (Ok(signer), Ok(owner)) => (signer.to_lowercase(), owner.to_lowercase()),
+        _ => return None
+    };
+
+    // Records synthesized from a wildcard are signed with the wildcard owner
+    let n = sig.labels as usize;
+    let owner = if n < owner.num_labels() {
+        let mut labels = vec![b"*".to_vec()];
+        labels.extend_from_slice(&owner.labels()[owner.num_labels() - n..]);
+        Name::from_raw_labels(labels)
+    } else {
+        owner
+    };
+
+    let mut data = Vec::new();
+
+    data.extend_from_slice(&sig.type_covered.to_u16().to_be_bytes());
+    data.push(sig.algorithm);
+    data.push(sig.labels);
+    data.extend_from_slice(&sig.original_ttl.to_be_bytes());
+    data.extend_from_slice(&sig.expiration.to_be_bytes());
+    data.extend_from_slice(&sig.inception.to_be_bytes());
+    data.extend_from_slice(&sig.key_tag.to_be_bytes());
+    data.extend_from_slice(&name_wire(&signer));
+
+    let mut rdatas = Vec::with_capacity(rrset.len());
+
+    for rr in rrset {
+        match canonical_rdata(rr) {
+            Some(rdata) => rdatas.push(rdata),
+            None => return None
+        }
+    }
+
+    rdatas.sort();
+    rdatas.dedup();
+
+    let owner = name_wire(&owner);
+
+    for rdata in rdatas {
+        data.extend_from_slice(&owner);
+        data.extend_from_slice(&rrset[0].r_type.to_u16().to_be_bytes());
+        data.extend_from_slice(&rrset[0].r_class.to_u16().to_be_bytes());
+        data.extend_from_slice(&sig.original_ttl.to_be_bytes());
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(&rdata);
+    }
+
+    Some(data)
+}
+
+/// Returns the rdata of a record in canonical form, as described in
+/// RFC 4034, section 6.2: names are uncompressed and, except in `NSEC`
+/// records (RFC 6840, section 5.1), converted to lowercase.
+///
+/// Names in the rdata of the obsolete types `MB`, `MD`, `MF`, `MG`, `MINFO`,
+/// `MR`, `SIG`, `NXT`, and `A6` are not converted.
+fn canonical_rdata(rr: &Resource) -> Option<Vec<u8>> {
+    let data = match rr.uncompressed_rdata() {
+        Ok(data) => data,
+        Err(_) => return None
+    };
+
+    match rr.r_type {
+        RecordType::CName | RecordType::DName | RecordType::Ns | RecordType::Ptr =>
+            lowercase_names(&data, 0, 1),
+        RecordType::Mx => lowercase_names(&data, 2, 1),
+        RecordType::Rrsig => lowercase_names(&data, 18, 1),
+        RecordType::Soa => lowercase_names(&data, 0, 2),
+        RecordType::Srv => lowercase_names(&data, 6, 1),
+        // `RP`
+        RecordType::Other(17) => lowercase_names(&data, 0, 2),
+        // `AFSDB`, `RT`, and `KX`
+        RecordType::Other(18) | RecordType::Other(21) | RecordType::Other(36) =>
+            lowercase_names(&data, 2, 1),
+        // `PX`
+        RecordType::Other(26) => lowercase_names(&data, 2, 2),
+        // `NAPTR`; order and preference, then three character strings
+        RecordType::Other(35) => {
+            let mut pos = 4;
+
+            for _ in 0..3 {
+                match data.get(pos) {
+                    Some(&len) => pos += 1 + len as usize,
+                    None => return None
+                }
+            }
+
+            lowercase_names(&data, pos, 1)
+        }
+        _ => Some(data.into_owned())
+    }
+}
+
+/// Returns rdata with `count` consecutive uncompressed names, beginning
+/// at `pos`, converted to lowercase. Names in record types not described
+/// in RFC 1035 are never compressed (RFC 3597, section 4).
+fn lowercase_names(data: &[u8], mut pos: usize, count: usize) -> Option<Vec<u8>> {
+    let mut data = data.to_vec();
+
+    for _ in 0..count {
+        loop {
+            let len = match data.get(pos) {
+                Some(&len) if len & 0xc0 == 0 => len as usize,
+                _ => return None
+            };
+
+            if pos + 1 + len > data.len() {
+                return None;
+            }
+
+            data[pos + 1..pos + 1 + len].make_ascii_lowercase();
+            pos += 1 + len;
+
+            if len == 0 {
+                break;
+            }
+        }
+    }
+
+    Some(data)
+}
+
+/// Returns a name in wire format, without compression.
+pub(crate) fn name_wire(name: &Name) -> Vec<u8> {
+    let mut data = Vec::with_capacity(name.wire_len());
+
+    for label in name.labels() {
+        data.push(label.len() as u8);
+        data.extend_from_slice(label);
+    }
+
+    data.push(0);
+    data
+}
+
+/// Returns the rdata of a `Dnskey`.
+fn key_rdata(key: &Dnskey) -> Vec<u8> {
+    let mut data = key.flags.to_be_bytes().to_vec();
+    data.push(key.protocol);
+    data.push(key.algorithm);
+    data.extend_from_slice(&key.public_key);
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use ring::digest;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use config::DnsConfig;
+    use denial::Denial;
+    use message::{Message, Qr, RCode, Resource};
+    use name::Name;
+    use record::{A, AnyRecord, Class, Dnskey, Ds, Mx, Nsec, Record, RecordType, Rrsig, Soa};
+    use resolver::{DnsResolver, ResolveError};
+    use transport::MockTransport;
+    use super::{canonical_rdata, ds_matches, key_rdata, name_wire, signed_data, verify_rrset};
+    use super::{TrustAnchor, Validation, Validator};
+
+    struct Signer {
+        zone: &'static str,
+        key_pair: Ed25519KeyPair,
+        key: Dnskey,
+    }
+
+    impl Signer {
+        fn new(zone: &'static str, seed: u8) -> Signer {
+            let key_pair = Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap();
+            let key = Dnskey{
+                flags: Dnskey::ZONE_KEY | Dnskey::SECURE_ENTRY_POINT,
+                protocol: Dnskey::PROTOCOL,
+                algorithm: 15,
+                public_key: key_pair.public_key().as_ref().to_vec(),
+            };
+
+            Signer{zone: zone, key_pair: key_pair, key: key}
+        }
+
+        fn ds(&self) -> Ds {
+            let mut data = name_wire(&Name::from_idna(self.zone).unwrap());
+            data.extend_from_slice(&key_rdata(&self.key));
+
+            Ds{
+                key_tag: self.key.key_tag(),
+                algorithm: 15,
+                digest_type: 2,
+                digest: digest::digest(&digest::SHA256, &data).as_ref().to_vec(),
+            }
+        }
+
+        fn sign(&self, rrset: &[Resource]) -> Resource<'static> {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+            let owner = Name::from_idna(&rrset[0].name).unwrap();
+
+            let mut sig = Rrsig{
+                type_covered: rrset[0].r_type,
+                algorithm: 15,
+                labels: owner.num_labels() as u8,
+                original_ttl: rrset[0].ttl,
+                expiration: now + 3600,
+                inception: now - 3600,
+                key_tag: self.key.key_tag(),
+                signer_name: self.zone.to_owned(),
+                signature: Vec::new(),
+            };
+
+            let data = signed_data(&rrset.iter().collect::<Vec<_>>(), &sig).unwrap();
+            sig.signature = self.key_pair.sign(&data).as_ref().to_vec();
+
+            rr(&rrset[0].name, &sig)
+        }
+    }
+
+    fn rr<R: Record>(name: &str, rec: &R) -> Resource<'static> {
+        let mut rr = Resource::new(name.to_owned(), R::record_type(), Class::Internet, 3600);
+        rr.write_rdata(rec).unwrap();
+        rr
+    }
+
+    fn soa(zone: &str) -> Soa {
+        Soa{
+            mname: format!("ns.{}", zone.trim_start_matches('.')),
+            rname: format!("hostmaster.{}", zone.trim_start_matches('.')),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+        }
+    }
+
+    type Zone = HashMap<(String, RecordType), Vec<Resource<'static>>>;
+
+    fn add(zone: &mut Zone, signer: Option<&Signer>, rrset: Vec<Resource<'static>>) {
+        let mut records = rrset.clone();
+
+        if let Some(signer) = signer {
+            records.push(signer.sign(&rrset));
+        }
+
+        zone.insert((rrset[0].name.clone(), rrset[0].r_type), records);
+    }
+
+    /// Serves a signed root zone, a signed `example.` zone, and an unsigned
+    /// `insecure.example.` zone from a single mock name server. If `proofs`
+    /// is `false`, `NSEC` records are omitted from negative responses.
+    fn fixture(root: &Signer, proofs: bool) -> Arc<MockTransport> {
+        let example = Signer::new("example.", 2);
+        let mut zone = Zone::new();
+        let mut nsec = HashMap::new();
+
+        add(&mut zone, Some(root), vec![rr(".", &root.key)]);
+        add(&mut zone, Some(root), vec![rr(".", &soa("."))]);
+        add(&mut zone, Some(root), vec![rr("example.", &example.ds())]);
+
+        add(&mut zone, Some(&example), vec![rr("example.", &example.key)]);
+        add(&mut zone, Some(&example), vec![rr("example.", &soa("example."))]);
+        add(&mut zone, Some(&example), vec![
+            rr("www.example.", &A{address: Ipv4Addr::new(192, 0, 2, 2)}),
+            rr("www.example.", &A{address: Ipv4Addr::new(192, 0, 2, 1)}),
+        ]);
+        add(&mut zone, Some(&example),
+            vec![rr("bad.example.", &A{address: Ipv4Addr::new(192, 0, 2, 3)})]);
+        add(&mut zone, None,
+            vec![rr("unsigned.example.", &A{address: Ipv4Addr::new(192, 0, 2, 4)})]);
+
+        add(&mut zone, None, vec![rr("insecure.example.", &soa("insecure.example."))]);
+        add(&mut zone, None,
+            vec![rr("host.insecure.example.", &A{address: Ipv4Addr::new(192, 0, 2, 5)})]);
+
+        let chains = [
+            (root, vec![
+                (".", "example. NS SOA RRSIG NSEC DNSKEY"),
+                ("example.", ". NS DS RRSIG NSEC"),
+            ]),
+            (&example, vec![
+                ("example.", "bad.example. SOA RRSIG NSEC DNSKEY"),
+                ("bad.example.", "insecure.example. A RRSIG NSEC"),
+                ("insecure.example.", "unsigned.example. NS RRSIG NSEC"),
+                ("unsigned.example.", "www.example. A RRSIG NSEC"),
+                ("www.example.", "example. A RRSIG NSEC"),
+            ]),
+        ];
+
+        for &(signer, ref chain) in &chains {
+            let records = nsec.entry(signer.zone).or_insert_with(Vec::new);
+
+            for &(owner, rdata) in chain {
+                let rrset = vec![rr(owner, &rdata.parse::<Nsec>().unwrap())];
+                records.push(signer.sign(&rrset));
+                records.extend(rrset);
+            }
+        }
+
+        // Replace signed data with other data
+        zone.get_mut(&("bad.example.".to_owned(), RecordType::A)).unwrap()[0]
+            .write_rdata(&A{address: Ipv4Addr::new(192, 0, 2, 99)}).unwrap();
+
+        Arc::new(MockTransport::new(move |msg: &Message, _: &SocketAddr| {
+            let q = &msg.question[0];
+            let mut reply = Message::new();
+            reply.header.qr = Qr::Response;
+            reply.question = msg.question.clone();
+
+            match zone.get(&(q.name.clone(), q.q_type)) {
+                Some(records) => reply.answer = records.clone(),
+                None => {
+                    // `DS` records at a zone apex are served by the parent zone
+                    let is_apex = zone.contains_key(&(q.name.clone(), RecordType::Soa));
+                    let name = if q.q_type == RecordType::Ds && is_apex {
+                        &q.name[q.name.find('.').unwrap() + 1..]
+                    } else {
+                        &q.name[..]
+                    };
+
+                    let apex = *["insecure.example.", "example.", "."].iter()
+                        .find(|apex| name.ends_with(*apex)).unwrap();
+
+                    let exists = zone.keys().any(|&(ref owner, _)|
+                        owner == &q.name || owner.ends_with(&format!(".{}", q.name)));
+
+                    if !exists {
+                        reply.header.rcode = RCode::NameError;
+                    }
+
+                    reply.authority = zone[&(apex.to_owned(), RecordType::Soa)].clone();
+
+                    if proofs {
+                        reply.authority.extend(nsec.get(apex).cloned().unwrap_or_default());
+                    }
+                }
+            }
+
+            Some(reply)
+        }))
+    }
+
+    fn resolver(udp: &Arc<MockTransport>, anchor: Option<TrustAnchor>) -> DnsResolver {
+        let config = DnsConfig::with_name_servers(vec!["127.0.0.1:53".parse().unwrap()]);
+        let mut r = DnsResolver::with_transport(config,
+            Box::new(udp.clone()), Box::new(udp.clone()));
+
+        r.set_validator(anchor.map(|anchor| Validator::new(vec![anchor])));
+        r
+    }
+
+    fn status(r: &DnsResolver, name: &str) -> Result<Validation, ResolveError> {
+        r.resolve_record_validated::<A>(name).map(|(_, status)| status)
+    }
+
+    #[test]
+    fn test_validate() {
+        let root = Signer::new(".", 1);
+        let udp = fixture(&root, true);
+        let r = resolver(&udp, Some(TrustAnchor{name: ".".to_owned(), ds: root.ds()}));
+
+        let (addrs, res) = r.resolve_record_validated::<A>("www.example.").unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(res, Validation::Secure);
+
+        // Zone keys are cached by the validator; only the answer is queried
+        let sent = udp.queries().len();
+        assert_eq!(status(&r, "www.example.").unwrap(), Validation::Secure);
+        assert_eq!(udp.queries().len(), sent + 1);
+
+        match status(&r, "bad.example.") {
+            Err(ResolveError::Bogus) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+        match r.resolve_record::<A>("unsigned.example.") {
+            Err(ResolveError::Bogus) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        assert_eq!(status(&r, "host.insecure.example.").unwrap(), Validation::Insecure);
+
+        match status(&r, "nope.example.") {
+            Err(ResolveError::Denied(Denial::NoSuchName)) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+        match r.resolve_record_validated::<Mx>("www.example.") {
+            Err(ResolveError::Denied(Denial::NoData)) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        assert!(udp.queries().iter().all(|&(_, ref msg)| msg.edns.as_ref().unwrap().dnssec_ok));
+
+        // A trust anchor which does not match the root key
+        let other = Signer::new(".", 3);
+        let r = resolver(&udp, Some(TrustAnchor{name: ".".to_owned(), ds: other.ds()}));
+        match status(&r, "www.example.") {
+            Err(ResolveError::Bogus) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        // A root key with a protocol other than 3 is not used
+        let mut bad_root = Signer::new(".", 1);
+        bad_root.key.protocol = 2;
+        let bad_udp = fixture(&bad_root, true);
+        let r = resolver(&bad_udp, Some(TrustAnchor{name: ".".to_owned(), ds: bad_root.ds()}));
+        match status(&r, "www.example.") {
+            Err(ResolveError::Bogus) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        // A trust anchor which does not cover the records
+        let other = Signer::new("other.", 3);
+        let r = resolver(&udp, Some(TrustAnchor{name: "other.".to_owned(), ds: other.ds()}));
+        assert_eq!(status(&r, "www.example.").unwrap(), Validation::Indeterminate);
+
+        let r = resolver(&udp, None);
+        assert_eq!(status(&r, "bad.example.").unwrap(), Validation::Indeterminate);
+
+        // Without proof of nonexistence, neither a name error nor
+        // an unsigned delegation is accepted
+        let udp = fixture(&root, false);
+        let r = resolver(&udp, Some(TrustAnchor{name: ".".to_owned(), ds: root.ds()}));
+
+        for name in &["nope.example.", "host.insecure.example."] {
+            match status(&r, name) {
+                Err(ResolveError::Bogus) => (),
+                res => panic!("unexpected result for {}: {:?}", name, res)
+            }
+        }
+    }
+
+    #[test]
+    fn test_rfc8080() {
+        // Example from RFC 8080, section 6.1
+        let key: Dnskey = "257 3 15 l02Woi0iS8Aa25FQkUd9RMzZHJpBoRQwAQEX1SxZJA4="
+            .parse().unwrap();
+        assert_eq!(key.key_tag(), 3613);
+
+        let anchor: TrustAnchor = "example.com. IN DS 3613 15 2 \
+            3aa5ab37efce57f737fc1627013fee07bdf241bd10f3b1964ab55c78e79a304b"
+            .parse().unwrap();
+        assert!(ds_matches(&anchor.ds, &Name::from_idna(&anchor.name).unwrap(), &key));
+
+        // Names are compared in lowercase
+        let rr = rr("EXAMPLE.com.", &Mx{preference: 10, exchange: "Mail.Example.com.".to_owned()});
+        let sig: Rrsig = "MX 15 2 3600 1440021600 1438207200 3613 example.com. \
+            oL9krJun7xfBOIWcGHi7mag5/hdZrKWw15jPGrHpjQeRAvTdszaPD+QLs3f\
+            x8A4M3e23mRZ9VrbpMngwcrqNAg==".parse().unwrap();
+        assert!(verify_rrset(&[&rr], &sig, &key));
+
+        let mut bad = sig.clone();
+        bad.original_ttl = 60;
+        assert!(!verify_rrset(&[&rr], &bad, &key));
+    }
+
+    #[test]
+    fn test_canonical_rdata() {
+        // Names in `NSEC` records keep their case
+        let rr = rr("a.example.", &"B.Example. A RRSIG NSEC".parse::<Nsec>().unwrap());
+        assert_eq!(canonical_rdata(&rr).unwrap(), rr.get_rdata());
+
+        let raw = |r_type: u16, rdata: &str| {
+            let mut rr = Resource::new("example.".to_owned(),
+                RecordType::Other(r_type), Class::Internet, 3600);
+            let rec = AnyRecord::Unknown(RecordType::Other(r_type), rdata.parse().unwrap());
+            rr.write_any(&rec).unwrap();
+            rr
+        };
+
+        // `RP`; `A.b.` and `C.`
+        let rr = raw(17, "\\# 8 0141 0162 00 0143 00");
+        assert_eq!(canonical_rdata(&rr).unwrap(), b"\x01a\x01b\x00\x01c\x00");
+
+        // `AFSDB`; 1 `A.`
+        let rr = raw(18, "\\# 5 0001 0141 00");
+        assert_eq!(canonical_rdata(&rr).unwrap(), b"\x00\x01\x01a\x00");
+
+        // `NAPTR`; 1 2 "S" "" "" `A.`; character strings keep their case
+        let rr = raw(35, "\\# 11 0001 0002 0153 00 00 0141 00");
+        assert_eq!(canonical_rdata(&rr).unwrap(), b"\x00\x01\x00\x02\x01S\x00\x00\x01a\x00");
+
+        // Truncated names are rejected
+        assert_eq!(canonical_rdata(&raw(17, "\\# 2 0341")), None);
+
+        // `MX` exchange `XN--BCHER-KVA.Example.`, compressed in a response;
+        // labels are lowercased as raw ASCII, without IDNA conversion
+        let msg = Message::decode(b"\
+            \x00\x00\x84\x00\x00\x00\x00\x01\x00\x00\x00\x00\
+            \x07Example\x00\x00\x0f\x00\x01\x00\x00\x00\x3c\x00\x12\
+            \x00\x0a\x0dXN--BCHER-KVA\xc0\x0c").unwrap();
+        assert_eq!(canonical_rdata(&msg.answer[0]).unwrap(),
+            &b"\x00\x0a\x0dxn--bcher-kva\x07example\x00"[..]);
+    }
+}
@@ -5,6 +5,8 @@ use std::io::{self, Read};
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 
+use name::Name;
+
 /// Represents a host table, consisting of addresses mapped to names.
 #[derive(Clone, Debug)]
 pub struct HostTable {
@@ -34,12 +36,19 @@ impl HostTable {
         self.hosts.iter().find(|h| h.address == addr)
     }
 
-    /// Returns the first host matching the given address.
+    /// Returns the first host matching the given name.
     ///
+    /// Names are compared as `Name` values, without regard to ASCII case or
+    /// a trailing `.`; internationalized labels may be given in either form.
     /// If no match is found, `None` is returned.
     pub fn find_host_by_name(&self, name: &str) -> Option<&Host> {
-        self.hosts.iter().find(|h| h.name == name ||
-            h.aliases.iter().any(|a| a == name))
+        let name = match Name::from_idna(name) {
+            Ok(name) => name,
+            Err(_) => return None
+        };
+        let matches = |s: &str| Name::from_idna(s).ok().as_ref() == Some(&name);
+
+        self.hosts.iter().find(|h| matches(&h.name) || h.aliases.iter().any(|a| matches(a)))
     }
 }
 
@@ -145,12 +154,17 @@ mod test {
 ::1             ip6-localhost
 
 192.168.10.1    foo foo.bar foo.local # Mid-line comment
+192.168.10.2    bücher.example
 ").unwrap();
 
         assert_eq!(hosts.find_address("localhost"), Some(ip("127.0.0.1")));
         assert_eq!(hosts.find_address("ip6-localhost"), Some(ip("::1")));
         assert_eq!(hosts.find_name(ip("192.168.10.1")), Some("foo"));
 
+        assert_eq!(hosts.find_address("LocalHost"), Some(ip("127.0.0.1")));
+        assert_eq!(hosts.find_address("FOO.Local."), Some(ip("192.168.10.1")));
+        assert_eq!(hosts.find_address("XN--BCHER-KVA.example"), Some(ip("192.168.10.2")));
+
         assert_eq!(hosts.find_address("missing"), None);
         assert_eq!(hosts.find_name(ip("0.0.0.0")), None);
 
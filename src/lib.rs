@@ -2,27 +2,52 @@
 
 #![deny(missing_docs)]
 
+#[cfg(feature = "async")] extern crate futures;
 extern crate idna as external_idna;
 extern crate libc;
 #[macro_use] extern crate log;
 extern crate rand;
+#[cfg(feature = "dnssec")] extern crate ring;
+#[cfg(feature = "async")] extern crate tokio_io;
+#[cfg(feature = "async")] extern crate tokio_tcp;
+#[cfg(feature = "async")] extern crate tokio_timer;
+#[cfg(feature = "async")] extern crate tokio_udp;
+
+#[cfg(all(test, feature = "async"))] extern crate tokio;
 
 pub use address::address_name;
+pub use cache::DnsCache;
 pub use config::DnsConfig;
+#[cfg(feature = "dnssec")] pub use denial::Denial;
+#[cfg(feature = "dnssec")] pub use dnssec::{TrustAnchor, Validation, Validator};
+pub use connect::connect_host;
 pub use idna::{to_ascii, to_unicode};
-pub use message::{DecodeError, EncodeError, Message, Question, Resource,
+pub use message::{DecodeError, Edns, EncodeError, Message, Question, Resource,
     MESSAGE_LIMIT};
-pub use record::{Class, Record, RecordType};
-pub use resolver::{resolve_addr, resolve_host, DnsResolver};
-pub use socket::{DnsSocket, Error};
+pub use name::Name;
+pub use record::{AnyRecord, Class, Record, RecordType};
+pub use resolver::{resolve_addr, resolve_host, DnsResolver, ResolveError};
+pub use socket::{DnsSocket, DnsStream, Error};
+pub use transport::Transport;
+pub use view::MessageView;
 
 pub mod address;
+#[cfg(feature = "async")] pub mod async_resolver;
+pub mod cache;
 pub mod config;
+pub mod connect;
+#[cfg(feature = "dnssec")] pub mod denial;
+#[cfg(feature = "dnssec")] pub mod dnssec;
 pub mod hosts;
 pub mod hostname;
 pub mod idna;
 pub mod message;
+pub mod name;
 pub mod record;
 #[cfg(unix)] pub mod resolv_conf;
 pub mod resolver;
 pub mod socket;
+pub mod sort;
+pub mod transport;
+pub mod view;
+pub mod zone;
@@ -2,7 +2,9 @@
 
 use std::borrow::Cow::{self, Borrowed, Owned};
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::default::Default;
+use std::error::Error;
 use std::fmt;
 use std::io::{Cursor, Read, Write};
 use std::mem::{transmute, zeroed};
@@ -13,7 +15,10 @@ use std::vec::IntoIter;
 use rand::random;
 
 use idna;
-use record::{Class, Record, RecordType};
+use name::{self, Name};
+use record::{fmt_generic_rdata, fmt_hex};
+use record::{AnyRecord, Class, Record, RecordType};
+use view::Labels;
 
 /// Maximum size of a DNS message, in bytes.
 pub const MESSAGE_LIMIT: usize = 0xffff;
@@ -24,6 +29,11 @@ pub const LABEL_LIMIT: usize = 63;
 /// Maximum total length of a name, in encoded format.
 pub const NAME_LIMIT: usize = 255;
 
+/// UDP payload size advertised by default in EDNS(0) queries.
+///
+/// This value avoids IP fragmentation on nearly all networks.
+pub const EDNS_PAYLOAD_SIZE: u16 = 1232;
+
 /// An error response code received in a response message.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct DnsError(pub RCode);
@@ -34,6 +44,8 @@ impl fmt::Display for DnsError {
     }
 }
 
+impl Error for DnsError {}
+
 /// Represents an error in decoding a DNS message.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum DecodeError {
@@ -58,6 +70,8 @@ impl fmt::Display for DecodeError {
     }
 }
 
+impl Error for DecodeError {}
+
 /// Represents an error in encoding a DNS message.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum EncodeError {
@@ -65,6 +79,10 @@ pub enum EncodeError {
     InvalidName,
     /// Message exceeded given buffer or `MESSAGE_LIMIT` bytes
     TooLong,
+    /// An extended response code was given for a message without EDNS
+    InvalidRCode,
+    /// Resource data could not be decoded for its record type
+    InvalidRecord,
 }
 
 impl fmt::Display for EncodeError {
@@ -72,10 +90,14 @@ impl fmt::Display for EncodeError {
         match *self {
             EncodeError::InvalidName => f.write_str("invalid name value"),
             EncodeError::TooLong => f.write_str("message too long"),
+            EncodeError::InvalidRCode => f.write_str("extended response code requires EDNS"),
+            EncodeError::InvalidRecord => f.write_str("invalid record data"),
         }
     }
 }
 
+impl Error for EncodeError {}
+
 /// Reads a single DNS message from a series of bytes.
 pub struct MsgReader<'a> {
     data: Cursor<&'a [u8]>,
@@ -95,6 +117,21 @@ impl<'a> MsgReader<'a> {
         MsgReader{data: cur}
     }
 
+    /// Returns the full message being read.
+    pub(crate) fn data(&self) -> &'a [u8] {
+        *self.data.get_ref()
+    }
+
+    /// Returns the current read position within the message.
+    pub(crate) fn position(&self) -> usize {
+        self.data.position() as usize
+    }
+
+    /// Sets the current read position within the message.
+    pub(crate) fn set_position(&mut self, pos: usize) {
+        self.data.set_position(pos as u64);
+    }
+
     /// Returns the number of bytes remaining in the message.
     pub fn remaining(&self) -> usize {
         self.data.get_ref().len() - self.data.position() as usize
@@ -162,114 +199,52 @@ impl<'a> MsgReader<'a> {
     }
 
     /// Reads a name from the message.
+    ///
+    /// Labels which are valid hostname labels are converted from IDNA format
+    /// to Unicode. Other labels are returned in presentation format, using
+    /// `\DDD` and `\X` escapes, and will be encoded unchanged by
+    /// `MsgWriter::write_name`.
     pub fn read_name(&mut self) -> Result<String, DecodeError> {
-        // Start position, used to check against pointer references
-        let start_pos = self.data.position();
-        // Offset to return to if we've finished parsing a pointer reference
-        let mut restore = None;
-
-        let mut res = String::new();
-        let mut total_read = 0;
-
-        loop {
-            let len = try!(self.read_byte());
-
-            if len == 0 {
-                if total_read + 1 > NAME_LIMIT {
-                    return Err(DecodeError::InvalidName);
-                }
-                break;
-            }
-
-            // If the length flag starts with "11", it will be followed by a
-            // pointer reference. For more information see RFC 1035 section
-            // 4.1.4 (Message compression).
-            // Prefix "00" means "no compression". Prefixes 0x01 and 0x10
-            // are reserved for future use.
-            let compressed = match len >> 6 {
-                0b11 => true,
-                0b00 => false,
-                _ => return Err(DecodeError::InvalidMessage),
-            };
+        let labels = try!(self.read_labels());
 
-            if compressed {
-                // The beginning of a pointer reference. 14 bit denote the
-                // offset from the start of the message.
-                let hi = (len & 0b00111111) as u64;
-                let lo = try!(self.read_byte()) as u64;
-                let offset = (hi << 8) | lo;
-
-                // To prevent an infinite loop, we require the pointer to
-                // point before the start of this name.
-                if offset >= start_pos {
-                    return Err(DecodeError::InvalidName);
-                }
-
-                if restore.is_none() {
-                    restore = Some(self.data.position());
-                }
-
-                self.data.set_position(offset);
-                continue;
-            }
-
-            if total_read + 1 + len as usize > NAME_LIMIT {
-                return Err(DecodeError::InvalidName);
-            }
-            total_read += 1 + len as usize;
-
-            try!(self.read_segment(&mut res, len as usize));
+        if labels.is_empty() {
+            return Ok(".".to_owned());
         }
 
-        if res.is_empty() {
-            res.push('.');
-        } else {
-            res.shrink_to_fit();
-        }
+        let mut res = String::new();
 
-        if let Some(pos) = restore {
-            self.data.set_position(pos);
+        for label in &labels {
+            push_label(&mut res, label);
+            res.push('.');
         }
 
+        res.shrink_to_fit();
         Ok(res)
     }
 
-    fn read_segment(&mut self, buf: &mut String, len: usize) -> Result<(), DecodeError> {
-        let mut bytes = [0; 64];
-
-        try!(self.read(&mut bytes[..len]));
-
-        let seg = &bytes[..len];
-
-        if !seg.is_ascii() {
-            return Err(DecodeError::InvalidName);
-        }
-
-        // We just verified this was ASCII, so it's safe.
-        let s = unsafe { from_utf8_unchecked(seg) };
+    /// Reads a name from the message, preserving the raw bytes of its labels.
+    pub fn read_raw_name(&mut self) -> Result<Name, DecodeError> {
+        self.read_labels().map(Name::from_raw_labels)
+    }
 
-        if !is_valid_segment(s) {
-            return Err(DecodeError::InvalidName);
-        }
+    fn read_labels(&mut self) -> Result<Vec<Vec<u8>>, DecodeError> {
+        let mut labels = Labels::new(self.data(), self.position());
+        let res = labels.by_ref().map(|l| l.to_vec()).collect();
 
-        let label = match idna::to_unicode(s) {
-            Ok(s) => s,
-            Err(_) => return Err(DecodeError::InvalidName)
-        };
+        let end = try!(labels.finish());
+        self.set_position(end);
 
-        buf.push_str(&label);
-        buf.push('.');
-        Ok(())
+        Ok(res)
     }
 
-    fn consume(&mut self, n: u64) {
+    pub(crate) fn consume(&mut self, n: u64) {
         let p = self.data.position();
         self.data.set_position(p + n);
     }
 
     /// Called at the end of message parsing. Returns `Err(ExtraneousData)`
     /// if there are any unread bytes remaining.
-    fn finish(self) -> Result<(), DecodeError> {
+    pub(crate) fn finish(self) -> Result<(), DecodeError> {
         if self.remaining() == 0 {
             Ok(())
         } else {
@@ -278,7 +253,7 @@ impl<'a> MsgReader<'a> {
     }
 
     /// Reads a message header
-    fn read_header(&mut self) -> Result<FullHeader, DecodeError> {
+    pub(crate) fn read_header(&mut self) -> Result<FullHeader, DecodeError> {
         let mut buf = [0; 12];
 
         try!(self.read(&mut buf));
@@ -379,14 +354,42 @@ impl<'a> MsgReader<'a> {
 }
 
 /// Writes a single DNS message as a series of bytes.
+///
+/// If compression is enabled, names are compressed as described in
+/// RFC 1035, section 4.1.4: when the suffix of a name has already been
+/// written, a pointer to the earlier occurrence is written in its place.
+/// Suffixes are matched exactly, so the case of each name is preserved.
 pub struct MsgWriter<'a> {
     data: Cursor<&'a mut [u8]>,
+    /// Whether names are to be compressed
+    compress: bool,
+    /// Offsets of name suffixes written so far
+    names: HashMap<Vec<u8>, u16>,
 }
 
 impl<'a> MsgWriter<'a> {
     /// Constructs a new message writer that will write into the given byte slice.
+    ///
+    /// Name compression is initially disabled.
     pub fn new(data: &mut [u8]) -> MsgWriter {
-        MsgWriter{data: Cursor::new(data)}
+        MsgWriter{
+            data: Cursor::new(data),
+            compress: false,
+            names: HashMap::new(),
+        }
+    }
+
+    /// Returns whether name compression is enabled.
+    pub fn compression(&self) -> bool {
+        self.compress
+    }
+
+    /// Enables or disables name compression for names subsequently written.
+    ///
+    /// Names written while compression is disabled may still be the target
+    /// of compression pointers in names written later.
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
     }
 
     /// Returns the number of bytes written so far.
@@ -426,48 +429,85 @@ impl<'a> MsgWriter<'a> {
     }
 
     /// Writes a name to the message.
+    ///
+    /// Unicode labels are converted to IDNA format. Labels containing
+    /// `\DDD` or `\X` escapes are written as the raw bytes they represent.
     pub fn write_name(&mut self, name: &str) -> Result<(), EncodeError> {
+        if name.contains('\\') {
+            return match Name::from_idna(name) {
+                Ok(name) => self.write_raw_name(&name),
+                Err(_) => Err(EncodeError::InvalidName)
+            };
+        }
+
         if !is_valid_name(name) {
-            Err(EncodeError::InvalidName)
+            return Err(EncodeError::InvalidName);
         } else if name == "." {
-            self.write_byte(0)
-        } else {
-            let mut total_len = 0;
+            return self.write_byte(0);
+        }
 
-            for seg in name.split('.') {
-                let seg = match idna::to_ascii(seg) {
-                    Ok(seg) => seg,
-                    Err(_) => return Err(EncodeError::InvalidName)
-                };
+        let mut labels = Vec::new();
+        let mut total_len = 0;
 
-                if !is_valid_segment(&seg) {
-                    return Err(EncodeError::InvalidName);
-                }
+        for seg in name.trim_end_matches('.').split('.') {
+            let seg = match idna::to_ascii(seg) {
+                Ok(seg) => seg,
+                Err(_) => return Err(EncodeError::InvalidName)
+            };
 
-                if seg.len() > LABEL_LIMIT {
-                    return Err(EncodeError::InvalidName);
-                }
+            if !is_valid_segment(&seg) {
+                return Err(EncodeError::InvalidName);
+            }
 
-                // Add the size octet and the segment length
-                total_len += 1 + seg.len();
+            if seg.len() > LABEL_LIMIT {
+                return Err(EncodeError::InvalidName);
+            }
 
-                if total_len > NAME_LIMIT {
-                    return Err(EncodeError::InvalidName);
-                }
+            // Add the size octet and the segment length
+            total_len += 1 + seg.len();
+            labels.push(seg.into_owned().into_bytes());
+        }
+
+        // Add the terminating zero length octet
+        if total_len + 1 > NAME_LIMIT {
+            return Err(EncodeError::InvalidName);
+        }
+
+        self.write_labels(&labels)
+    }
 
-                try!(self.write_byte(seg.len() as u8));
-                try!(self.write(seg.as_bytes()));
+    /// Writes a name to the message, preserving the raw bytes of its labels.
+    pub fn write_raw_name(&mut self, name: &Name) -> Result<(), EncodeError> {
+        self.write_labels(name.labels())
+    }
+
+    fn write_labels(&mut self, labels: &[Vec<u8>]) -> Result<(), EncodeError> {
+        for i in 0..labels.len() {
+            // Suffixes are keyed by their exact wire format
+            let mut suffix = Vec::new();
+
+            for label in &labels[i..] {
+                suffix.push(label.len() as u8);
+                suffix.extend_from_slice(label);
             }
 
-            if !name.ends_with('.') {
-                if total_len + 1 > NAME_LIMIT {
-                    return Err(EncodeError::InvalidName);
+            if self.compress {
+                if let Some(&offset) = self.names.get(&suffix) {
+                    return self.write_u16(0xc000 | offset);
                 }
-                try!(self.write_byte(0));
             }
 
-            Ok(())
+            // Only offsets of 14 bits may be used in a pointer
+            let pos = self.written();
+            if pos < 0x4000 {
+                self.names.entry(suffix).or_insert(pos as u16);
+            }
+
+            try!(self.write_byte(labels[i].len() as u8));
+            try!(self.write(&labels[i]));
         }
+
+        self.write_byte(0)
     }
 
     /// Writes a single byte to the message.
@@ -510,7 +550,7 @@ impl<'a> MsgWriter<'a> {
         // 3 bits: reserved for future use
         // .flags1 |= (0 as u8 & 0b111) << 4;
         // 4 bits: response code
-        hdr.flags1 |= header.rcode.to_u8() & 0b1111;
+        hdr.flags1 |= (header.rcode.to_u16() & 0b1111) as u8;
 
         hdr.qd_count = header.qd_count.to_be();
         hdr.an_count = header.an_count.to_be();
@@ -542,17 +582,100 @@ impl<'a> MsgWriter<'a> {
 
         let mut rd: ResourceData = unsafe { zeroed() };
 
-        let rdata = resource.get_rdata();
-
         rd.r_type = resource.r_type.to_u16().to_be();
         rd.r_class = resource.r_class.to_u16().to_be();
         rd.ttl = resource.ttl.to_be();
+
+        // Length is written once rdata has been written
+        let len_pos = self.written() + 8;
+        let buf: [u8; 10] = unsafe { transmute(rd) };
+
+        try!(self.write(&buf));
+
+        let start = self.written();
+
+        if !try!(self.write_rdata_names(resource)) {
+            try!(self.write(resource.get_rdata()));
+        }
+
+        let len = try!(to_u16(self.written() - start));
+
+        self.data.get_mut()[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+        Ok(())
+    }
+
+    /// Writes the rdata of record types which contain names, decompressing
+    /// each name from its raw labels. Returns `Ok(false)` if the record is of
+    /// any other type or its rdata may be copied unchanged.
+    ///
+    /// Only names in the record types defined in RFC 1035 are compressed,
+    /// as described in RFC 3597, section 4. The rdata of other types is
+    /// rewritten only if it contains compression pointers, as that of a
+    /// decoded message may, so that it remains valid in a new message.
+    fn write_rdata_names(&mut self, resource: &Resource) -> Result<bool, EncodeError> {
+        let parts = match resource.read_rdata_names() {
+            Ok(Some(parts)) => parts,
+            Ok(None) => return Ok(false),
+            Err(_) => return Err(EncodeError::InvalidRecord)
+        };
+
+        let rfc1035 = matches!(resource.r_type, RecordType::CName | RecordType::Mx |
+            RecordType::Ns | RecordType::Ptr | RecordType::Soa);
+
+        if !rfc1035 && !parts.compressed {
+            return Ok(false);
+        }
+
+        let compress = self.compress;
+
+        self.compress = compress && rfc1035;
+
+        let res = self.write_rdata_parts(&parts);
+
+        self.compress = compress;
+        res.map(|_| true)
+    }
+
+    fn write_rdata_parts(&mut self, parts: &RdataNames) -> Result<(), EncodeError> {
+        try!(self.write(&parts.head));
+
+        for name in &parts.names {
+            try!(self.write_raw_name(name));
+        }
+
+        self.write(&parts.tail)
+    }
+
+    /// Writes an `OPT` pseudo-record carrying EDNS data and the upper bits
+    /// of the given response code
+    fn write_edns(&mut self, edns: &Edns, rcode: RCode) -> Result<(), EncodeError> {
+        let mut rdata = Vec::new();
+
+        for opt in &edns.options {
+            rdata.extend_from_slice(&opt.code.to_be_bytes());
+            rdata.extend_from_slice(&try!(to_u16(opt.data.len())).to_be_bytes());
+            rdata.extend_from_slice(&opt.data);
+        }
+
+        let mut ttl = ((rcode.to_u16() >> 4) as u32) << 24;
+        ttl |= (edns.version as u32) << 16;
+        if edns.dnssec_ok {
+            ttl |= 0x8000;
+        }
+
+        try!(self.write_name("."));
+
+        let mut rd: ResourceData = unsafe { zeroed() };
+
+        rd.r_type = RecordType::Opt.to_u16().to_be();
+        rd.r_class = edns.udp_payload_size.to_be();
+        rd.ttl = ttl.to_be();
         rd.length = try!(to_u16(rdata.len())).to_be();
 
         let buf: [u8; 10] = unsafe { transmute(rd) };
 
         try!(self.write(&buf));
-        self.write(rdata)
+        self.write(&rdata)
     }
 }
 
@@ -568,6 +691,23 @@ pub fn generate_id() -> u16 {
     })
 }
 
+/// Appends a label read from a message to a name string.
+pub(crate) fn push_label(buf: &mut String, label: &[u8]) {
+    if label.is_ascii() {
+        // We just verified this was ASCII, so it's safe.
+        let s = unsafe { from_utf8_unchecked(label) };
+
+        if is_valid_segment(s) && !s.contains('\\') {
+            if let Ok(s) = idna::to_unicode(s) {
+                buf.push_str(&s);
+                return;
+            }
+        }
+    }
+
+    buf.push_str(&name::escape_label(label));
+}
+
 /// Returns whether the given string appears to be a valid hostname.
 /// The contents of the name (i.e. characters in labels) are not checked here;
 /// only the structure of the name is validated.
@@ -580,9 +720,57 @@ fn is_valid_name(name: &str) -> bool {
 /// This check is not as strict as internet DNS servers will be. It only checks
 /// for basic sanity of input. If an invalid name is given, a DNS server will
 /// respond that it doesn't exist, anyway.
+///
+/// Any label read from a message, once escaped as by `push_label`, is valid.
 fn is_valid_segment(s: &str) -> bool {
-    !(s.starts_with('-') || s.ends_with('-')) &&
-        s.chars().all(|c| !(c == '.' || c.is_whitespace() || c.is_control()))
+    s.chars().all(|c| !(c == '.' || c.is_whitespace() || c.is_control()))
+}
+
+/// Controls the use of name compression when encoding a message.
+///
+/// By default, names are compressed in all sections and record types.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Compression {
+    /// Whether to compress names in the question section
+    pub question: bool,
+    /// Whether to compress names in the answer section
+    pub answer: bool,
+    /// Whether to compress names in the authority section
+    pub authority: bool,
+    /// Whether to compress names in the additional section
+    pub additional: bool,
+    /// Record types whose owner names and rdata are not compressed
+    pub exclude: Vec<RecordType>,
+}
+
+impl Compression {
+    /// Returns a `Compression` which compresses names in all sections.
+    pub fn all() -> Compression {
+        Compression{
+            question: true,
+            answer: true,
+            authority: true,
+            additional: true,
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Returns a `Compression` which does not compress any names.
+    pub fn none() -> Compression {
+        Compression{
+            question: false,
+            answer: false,
+            authority: false,
+            additional: false,
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::all()
+    }
 }
 
 /// Represents a DNS message.
@@ -598,7 +786,12 @@ pub struct Message<'a> {
     pub authority: Vec<Resource<'a>>,
     /// Resource records that relate to the query, but are not strictly
     /// answers for the question.
+    ///
+    /// An `OPT` pseudo-record is not included here, but in `edns`.
     pub additional: Vec<Resource<'a>>,
+    /// EDNS(0) data, carried in the additional section as an `OPT`
+    /// pseudo-record.
+    pub edns: Option<Edns>,
 }
 
 impl<'a> Message<'a> {
@@ -630,6 +823,7 @@ impl<'a> Message<'a> {
             answer:     Vec::with_capacity(header.an_count as usize),
             authority:  Vec::with_capacity(header.ns_count as usize),
             additional: Vec::with_capacity(header.ar_count as usize),
+            edns: None,
         };
 
         for _ in 0..header.qd_count {
@@ -645,7 +839,23 @@ impl<'a> Message<'a> {
         }
 
         for _ in 0..header.ar_count {
-            msg.additional.push(try!(r.read_resource()));
+            let rr = try!(r.read_resource());
+
+            if rr.r_type == RecordType::Opt {
+                // A message may contain at most one OPT record,
+                // which must be owned by the root domain.
+                if msg.edns.is_some() || rr.name != "." {
+                    return Err(DecodeError::InvalidMessage);
+                }
+
+                let ext_rcode = (rr.ttl >> 24) as u16;
+                msg.header.rcode = RCode::from_u16(
+                    (ext_rcode << 4) | msg.header.rcode.to_u16());
+                msg.edns = Some(try!(Edns::decode(rr.r_class, rr.ttl,
+                    MsgReader::with_offset(&rr.data, rr.offset))));
+            } else {
+                msg.additional.push(rr);
+            }
         }
 
         try!(r.finish());
@@ -654,7 +864,17 @@ impl<'a> Message<'a> {
 
     /// Encodes a message to a series of bytes. On success, returns a subslice
     /// of the given buffer containing only the encoded message bytes.
+    ///
+    /// Names are compressed in all sections.
     pub fn encode<'buf>(&self, buf: &'buf mut [u8]) -> Result<&'buf [u8], EncodeError> {
+        self.encode_with(buf, &Compression::all())
+    }
+
+    /// Encodes a message to a series of bytes, compressing names as directed
+    /// by `compression`. On success, returns a subslice of the given buffer
+    /// containing only the encoded message bytes.
+    pub fn encode_with<'buf>(&self, buf: &'buf mut [u8], compression: &Compression)
+            -> Result<&'buf [u8], EncodeError> {
         let mut w = MsgWriter::new(buf);
         let hdr = &self.header;
 
@@ -670,27 +890,55 @@ impl<'a> Message<'a> {
             qd_count: try!(to_u16(self.question.len())),
             an_count: try!(to_u16(self.answer.len())),
             ns_count: try!(to_u16(self.authority.len())),
-            ar_count: try!(to_u16(self.additional.len())),
+            ar_count: try!(to_u16(self.additional.len() +
+                if self.edns.is_some() { 1 } else { 0 })),
         };
 
+        if self.edns.is_none() && hdr.rcode.to_u16() > 0xf {
+            return Err(EncodeError::InvalidRCode);
+        }
+
         try!(w.write_header(&header));
 
         for q in &self.question {
+            w.set_compression(compression.question &&
+                !compression.exclude.contains(&q.q_type));
             try!(w.write_question(q));
         }
         for r in &self.answer {
+            w.set_compression(compression.answer &&
+                !compression.exclude.contains(&r.r_type));
             try!(w.write_resource(r));
         }
         for r in &self.authority {
+            w.set_compression(compression.authority &&
+                !compression.exclude.contains(&r.r_type));
             try!(w.write_resource(r));
         }
         for r in &self.additional {
+            w.set_compression(compression.additional &&
+                !compression.exclude.contains(&r.r_type));
             try!(w.write_resource(r));
         }
+        if let Some(ref edns) = self.edns {
+            try!(w.write_edns(edns, hdr.rcode));
+        }
 
         Ok(w.into_bytes())
     }
 
+    /// Returns a `Message` which owns all of its resource data.
+    pub fn into_owned(self) -> Message<'static> {
+        Message{
+            header: self.header,
+            question: self.question,
+            answer: self.answer.into_iter().map(Resource::into_owned).collect(),
+            authority: self.authority.into_iter().map(Resource::into_owned).collect(),
+            additional: self.additional.into_iter().map(Resource::into_owned).collect(),
+            edns: self.edns,
+        }
+    }
+
     /// Returns a `DnsError` if the message response code is an error.
     pub fn get_error(&self) -> Result<(), DnsError> {
         if self.header.rcode == RCode::NoError {
@@ -723,6 +971,61 @@ impl<'a> Message<'a> {
     }
 }
 
+impl<'a> fmt::Display for Message<'a> {
+    /// Formats the message in the style of `dig` output.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hdr = &self.header;
+        let n_additional = self.additional.len() +
+            if self.edns.is_some() { 1 } else { 0 };
+
+        try!(writeln!(f, ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            hdr.op, hdr.rcode, hdr.id));
+        try!(f.write_str(";; flags:"));
+
+        for &(set, flag) in &[
+                (hdr.qr == Qr::Response, " qr"),
+                (hdr.authoritative, " aa"),
+                (hdr.truncated, " tc"),
+                (hdr.recursion_desired, " rd"),
+                (hdr.recursion_available, " ra")] {
+            if set {
+                try!(f.write_str(flag));
+            }
+        }
+
+        try!(writeln!(f, "; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            self.question.len(), self.answer.len(), self.authority.len(),
+            n_additional));
+
+        if let Some(ref edns) = self.edns {
+            try!(write!(f, "\n;; OPT PSEUDOSECTION:\n{}", edns));
+        }
+
+        if !self.question.is_empty() {
+            try!(f.write_str("\n;; QUESTION SECTION:\n"));
+
+            for q in &self.question {
+                try!(writeln!(f, "{}", q));
+            }
+        }
+
+        for &(title, section) in &[
+                ("ANSWER", &self.answer),
+                ("AUTHORITY", &self.authority),
+                ("ADDITIONAL", &self.additional)] {
+            if !section.is_empty() {
+                try!(write!(f, "\n;; {} SECTION:\n", title));
+
+                for rr in section {
+                    try!(writeln!(f, "{}", rr));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 /// Yields `&Resource` items from a Message.
 pub struct RecordIter<'a> {
     iters: [Iter<'a, Resource<'a>>; 3],
@@ -812,7 +1115,7 @@ impl Default for Header {
 
 /// Contains all header data decoded from a message.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-struct FullHeader {
+pub(crate) struct FullHeader {
     pub id: u16,
     pub qr: Qr,
     pub op: OpCode,
@@ -828,7 +1131,7 @@ struct FullHeader {
 }
 
 impl FullHeader {
-    fn to_header(&self) -> Header {
+    pub(crate) fn to_header(&self) -> Header {
         Header{
             id: self.id,
             qr: self.qr,
@@ -883,6 +1186,13 @@ impl Question {
     }
 }
 
+impl fmt::Display for Question {
+    /// Formats the question as a commented master file entry.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ";{}\t\t{}\t{}", self.name, self.q_class, self.q_type)
+    }
+}
+
 /// Represents a resource record item.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Resource<'a> {
@@ -914,6 +1224,32 @@ impl<'a> Resource<'a> {
         }
     }
 
+    /// Constructs a `Resource` borrowing message data, up to and including
+    /// resource data beginning at `offset`.
+    pub(crate) fn borrowed(name: String, r_type: RecordType, r_class: Class,
+            ttl: u32, data: &'a [u8], offset: usize) -> Resource<'a> {
+        Resource{
+            name: name,
+            r_type: r_type,
+            r_class: r_class,
+            ttl: ttl,
+            data: Borrowed(data),
+            offset: offset,
+        }
+    }
+
+    /// Returns a `Resource` which owns its resource data.
+    pub fn into_owned(self) -> Resource<'static> {
+        Resource{
+            name: self.name,
+            r_type: self.r_type,
+            r_class: self.r_class,
+            ttl: self.ttl,
+            data: Owned(self.data.into_owned()),
+            offset: self.offset,
+        }
+    }
+
     /// Returns resource data.
     pub fn get_rdata(&self) -> &[u8] {
         &self.data[self.offset..]
@@ -927,15 +1263,195 @@ impl<'a> Resource<'a> {
         Ok(res)
     }
 
+    /// Decodes resource data of any type, as given by `r_type`.
+    pub fn read_any(&self) -> Result<AnyRecord, DecodeError> {
+        let mut r = MsgReader::with_offset(&self.data, self.offset);
+        let res = try!(AnyRecord::decode(self.r_type, &mut r));
+        try!(r.finish());
+        Ok(res)
+    }
+
+    /// Returns resource data with any compressed names expanded.
+    #[cfg(feature = "dnssec")]
+    pub(crate) fn uncompressed_rdata<'b>(&'b self) -> Result<Cow<'b, [u8]>, DecodeError> {
+        match try!(self.read_rdata_names()) {
+            Some(ref parts) if parts.compressed => {
+                let mut buf = [0; MESSAGE_LIMIT];
+                let mut w = MsgWriter::new(&mut buf);
+
+                w.set_compression(false);
+
+                match w.write_rdata_parts(parts) {
+                    Ok(()) => Ok(Owned(w.into_bytes().to_vec())),
+                    Err(_) => Err(DecodeError::InvalidMessage)
+                }
+            }
+            _ => Ok(Borrowed(self.get_rdata()))
+        }
+    }
+
+    /// Reads the raw labels of names within the resource data of record
+    /// types which contain names. Returns `Ok(None)` for other types.
+    fn read_rdata_names(&self) -> Result<Option<RdataNames>, DecodeError> {
+        // Number of octets preceding the first name and number of names
+        let (prefix, count) = match self.r_type {
+            RecordType::CName | RecordType::DName | RecordType::Ns |
+                RecordType::Nsec | RecordType::Ptr => (0, 1),
+            RecordType::Https | RecordType::Mx | RecordType::Svcb => (2, 1),
+            RecordType::Soa => (0, 2),
+            RecordType::Srv => (6, 1),
+            RecordType::Rrsig => (18, 1),
+            _ => return Ok(None)
+        };
+
+        let mut r = MsgReader::with_offset(&self.data, self.offset);
+        let mut head = Vec::new();
+        let mut names = Vec::with_capacity(count);
+        let mut compressed = false;
+
+        try!(r.read_into(&mut head, prefix));
+
+        for _ in 0..count {
+            let start = r.position();
+            let name = try!(r.read_raw_name());
+            // A name containing a pointer occupies fewer or more octets
+            // than its uncompressed length
+            compressed |= r.position() - start != name.wire_len();
+            names.push(name);
+        }
+
+        Ok(Some(RdataNames{
+            head: head,
+            names: names,
+            tail: try!(r.read_to_end()),
+            compressed: compressed,
+        }))
+    }
+
     /// Encodes resource data from the given `Record` type.
     pub fn write_rdata<R: Record>(&mut self, record: &R) -> Result<(), EncodeError> {
+        self.write_with(|w| record.encode(w))
+    }
+
+    /// Encodes resource data of any type.
+    ///
+    /// `r_type` is not changed and should match the type of `record`.
+    pub fn write_any(&mut self, record: &AnyRecord) -> Result<(), EncodeError> {
+        self.write_with(|w| record.encode(w))
+    }
+
+    fn write_with<F>(&mut self, f: F) -> Result<(), EncodeError>
+            where F: FnOnce(&mut MsgWriter) -> Result<(), EncodeError> {
         let mut buf = [0; MESSAGE_LIMIT];
         let mut w = MsgWriter::new(&mut buf[..]);
-        try!(record.encode(&mut w));
+        try!(f(&mut w));
         self.data = Owned(w.into_bytes().to_vec());
         self.offset = 0;
         Ok(())
     }
+
+    /// Formats resource data in master file format, if its data is valid
+    /// for its type; otherwise, in the generic format of RFC 3597.
+    fn fmt_rdata(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.read_any() {
+            Ok(rec) => write!(f, "{}", rec),
+            Err(_) => fmt_generic_rdata(f, self.get_rdata())
+        }
+    }
+}
+
+impl<'a> fmt::Display for Resource<'a> {
+    /// Formats the resource as a master file entry.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}\t{}\t{}\t{}\t",
+            self.name, self.ttl, self.r_class, self.r_type));
+        self.fmt_rdata(f)
+    }
+}
+
+/// EDNS(0) data, as defined in RFC 6891.
+///
+/// The upper 8 bits of an extended response code are carried in the `OPT`
+/// pseudo-record. When a message is decoded, these are combined with the
+/// lower 4 bits in the message header to form `Header::rcode`; when encoding,
+/// they are taken from `Header::rcode`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Edns {
+    /// Maximum UDP payload size accepted by the sender
+    pub udp_payload_size: u16,
+    /// EDNS version
+    pub version: u8,
+    /// DNSSEC OK flag; indicates that the sender accepts DNSSEC records
+    pub dnssec_ok: bool,
+    /// EDNS options
+    pub options: Vec<EdnsOption>,
+}
+
+impl Edns {
+    /// Constructs a new `Edns` for version 0, advertising a UDP payload
+    /// size of `EDNS_PAYLOAD_SIZE`.
+    pub fn new() -> Edns {
+        Edns{
+            udp_payload_size: EDNS_PAYLOAD_SIZE,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+
+    /// Decodes EDNS data from the class, TTL, and rdata of an `OPT`
+    /// pseudo-record.
+    pub(crate) fn decode(r_class: Class, ttl: u32, mut r: MsgReader)
+            -> Result<Edns, DecodeError> {
+        let mut options = Vec::new();
+
+        while r.remaining() != 0 {
+            let code = try!(r.read_u16());
+            let len = try!(r.read_u16());
+            let mut data = Vec::new();
+
+            try!(r.read_into(&mut data, len as usize));
+            options.push(EdnsOption{code: code, data: data});
+        }
+
+        Ok(Edns{
+            udp_payload_size: r_class.to_u16(),
+            version: (ttl >> 16) as u8,
+            dnssec_ok: ttl & 0x8000 != 0,
+            options: options,
+        })
+    }
+}
+
+impl fmt::Display for Edns {
+    /// Formats EDNS data in the style of the `dig` OPT pseudosection.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "; EDNS: version: {}, flags:{}; udp: {}",
+            self.version, if self.dnssec_ok { " do" } else { "" },
+            self.udp_payload_size));
+
+        for opt in &self.options {
+            try!(write!(f, "\n; OPT={}: ", opt.code));
+            try!(fmt_hex(f, &opt.data));
+        }
+
+        writeln!(f)
+    }
+}
+
+impl Default for Edns {
+    fn default() -> Edns {
+        Edns::new()
+    }
+}
+
+/// An option carried in EDNS(0) data
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EdnsOption {
+    /// Option code
+    pub code: u16,
+    /// Option data
+    pub data: Vec<u8>,
 }
 
 /// Indicates a message is either a query or response.
@@ -963,6 +1479,19 @@ pub enum OpCode {
     Other(u8),
 }
 
+impl fmt::Display for OpCode {
+    /// Formats the opcode as its mnemonic.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpCode::Query => f.write_str("QUERY"),
+            OpCode::Status => f.write_str("STATUS"),
+            OpCode::Notify => f.write_str("NOTIFY"),
+            OpCode::Update => f.write_str("UPDATE"),
+            OpCode::Other(n) => write!(f, "RESERVED{}", n),
+        }
+    }
+}
+
 impl OpCode {
     /// Converts a `u8` to an `OpCode`.
     pub fn from_u8(u: u8) -> OpCode {
@@ -1004,8 +1533,14 @@ pub enum RCode {
     /// The name server refuses to perform the specified operation for policy
     /// reasons.
     Refused,
+    /// The EDNS version of the query is not supported by the name server.
+    /// This is an extended response code, which requires EDNS.
+    BadVers,
     /// Unknown response code.
-    Other(u8),
+    ///
+    /// This holds the full 12-bit extended response code.
+    /// In `resolve` 0.2, it held a `u8`.
+    Other(u16),
 }
 
 impl RCode {
@@ -1018,12 +1553,24 @@ impl RCode {
             RCode::NameError => "no such name",
             RCode::NotImplemented => "not implemented",
             RCode::Refused => "refused",
+            RCode::BadVers => "bad EDNS version",
             RCode::Other(_) => "unknown response code",
         }
     }
 
     /// Converts a `u8` to an `RCode`.
     pub fn from_u8(u: u8) -> RCode {
+        RCode::from_u16(u as u16)
+    }
+
+    /// Converts an `RCode` to a `u8`, discarding any bits beyond the lower 8.
+    #[deprecated(note = "truncates extended response codes; use `to_u16`")]
+    pub fn to_u8(&self) -> u8 {
+        self.to_u16() as u8
+    }
+
+    /// Converts a `u16` to an `RCode`.
+    pub fn from_u16(u: u16) -> RCode {
         match u {
             0 => RCode::NoError,
             1 => RCode::FormatError,
@@ -1031,12 +1578,13 @@ impl RCode {
             3 => RCode::NameError,
             4 => RCode::NotImplemented,
             5 => RCode::Refused,
+            16 => RCode::BadVers,
             n => RCode::Other(n),
         }
     }
 
-    /// Converts an `RCode` to a `u8`.
-    pub fn to_u8(&self) -> u8 {
+    /// Converts an `RCode` to a `u16`.
+    pub fn to_u16(&self) -> u16 {
         match *self {
             RCode::NoError => 0,
             RCode::FormatError => 1,
@@ -1044,11 +1592,28 @@ impl RCode {
             RCode::NameError => 3,
             RCode::NotImplemented => 4,
             RCode::Refused => 5,
+            RCode::BadVers => 16,
             RCode::Other(n) => n,
         }
     }
 }
 
+impl fmt::Display for RCode {
+    /// Formats the response code as its mnemonic.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RCode::NoError => f.write_str("NOERROR"),
+            RCode::FormatError => f.write_str("FORMERR"),
+            RCode::ServerFailure => f.write_str("SERVFAIL"),
+            RCode::NameError => f.write_str("NXDOMAIN"),
+            RCode::NotImplemented => f.write_str("NOTIMP"),
+            RCode::Refused => f.write_str("REFUSED"),
+            RCode::BadVers => f.write_str("BADVERS"),
+            RCode::Other(n) => write!(f, "RESERVED{}", n),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
 struct HeaderData {
@@ -1079,6 +1644,15 @@ struct ResourceData {
     length: u16,
 }
 
+/// Resource data divided around the names it contains
+struct RdataNames {
+    head: Vec<u8>,
+    names: Vec<Name>,
+    tail: Vec<u8>,
+    /// Whether any name was compressed
+    compressed: bool,
+}
+
 fn to_u16(n: usize) -> Result<u16, EncodeError> {
     if n > u16::max_value() as usize {
         Err(EncodeError::TooLong)
@@ -1090,9 +1664,10 @@ fn to_u16(n: usize) -> Result<u16, EncodeError> {
 #[cfg(test)]
 mod test {
     use super::{is_valid_name, EncodeError, MESSAGE_LIMIT};
-    use super::{Header, Message, Question, Qr, OpCode, RCode};
+    use super::{Edns, EdnsOption, Header, Message, Question, Qr, OpCode, RCode, Resource};
+    use super::{Compression, DecodeError};
     use super::{MsgReader, MsgWriter};
-    use record::{Class, RecordType};
+    use record::{Class, Mx, RecordType};
 
     #[test]
     fn test_idna_name() {
@@ -1115,6 +1690,63 @@ mod test {
         assert_eq!(r.read_name().as_ref().map(|s| &s[..]), Ok("ουτοπία.δπθ.gr."));
     }
 
+    #[test]
+    fn test_display() {
+        let mut msg = Message::with_id(1234);
+        msg.header.qr = Qr::Response;
+        msg.header.recursion_desired = true;
+        msg.header.recursion_available = true;
+        msg.question.push(Question::new("example.com.".to_owned(),
+            RecordType::Mx, Class::Internet));
+
+        let mut rr = Resource::new("example.com.".to_owned(),
+            RecordType::Mx, Class::Internet, 300);
+        rr.write_rdata(&Mx{preference: 10, exchange: "mail.example.com.".to_owned()})
+            .unwrap();
+        msg.answer.push(rr);
+
+        let mut rr = Resource::new("example.com.".to_owned(),
+            RecordType::Other(65280), Class::Internet, 60);
+        rr.write_rdata(&Mx{preference: 0xabcd, exchange: ".".to_owned()}).unwrap();
+        msg.additional.push(rr);
+
+        let mut edns = Edns::new();
+        edns.dnssec_ok = true;
+        msg.edns = Some(edns);
+
+        assert_eq!(msg.to_string(), "\
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 1234
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 2
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags: do; udp: 1232
+
+;; QUESTION SECTION:
+;example.com.\t\tIN\tMX
+
+;; ANSWER SECTION:
+example.com.\t300\tIN\tMX\t10 mail.example.com.
+
+;; ADDITIONAL SECTION:
+example.com.\t60\tIN\tTYPE65280\t\\# 3 ABCD00
+");
+    }
+
+    #[test]
+    fn test_binary_name() {
+        let bytes = b"\x03A\x00b\x01-\x05a.b c\x07Example\x00";
+        let mut r = MsgReader::new(bytes);
+
+        let name = r.read_name().unwrap();
+        assert_eq!(name, "A\\000b.-.a\\.b\\032c.Example.");
+
+        let mut buf = [0; 64];
+        let mut w = MsgWriter::new(&mut buf);
+
+        w.write_name(&name).unwrap();
+        assert_eq!(w.into_bytes(), &bytes[..]);
+    }
+
     #[test]
     fn test_message() {
         let msg = Message{
@@ -1135,6 +1767,7 @@ mod test {
             answer: Vec::new(),
             authority: Vec::new(),
             additional: Vec::new(),
+            edns: None,
         };
 
         let mut buf = [0; 64];
@@ -1154,6 +1787,120 @@ mod test {
         assert_eq!(msg, msg2);
     }
 
+    #[test]
+    fn test_edns() {
+        let mut msg = Message::with_id(0xabcd);
+        msg.header.qr = Qr::Response;
+        msg.header.rcode = RCode::BadVers;
+        msg.edns = Some(Edns{
+            udp_payload_size: 4096,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![EdnsOption{code: 10, data: vec![1, 2, 3, 4, 5, 6, 7, 8]}],
+        });
+
+        let mut buf = [0; 64];
+        let bytes = msg.encode(&mut buf).unwrap().to_vec();
+
+        assert_eq!(&bytes[..12],
+            &[0xab, 0xcd, 0b10000000, 0b00000000, 0, 0, 0, 0, 0, 0, 0, 1][..]);
+        assert_eq!(&bytes[12..],
+            &[0, 0, 41, 0x10, 0, 1, 0, 0x80, 0, 0, 12,
+                0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8][..]);
+
+        let msg2 = Message::decode(&bytes).unwrap();
+
+        assert_eq!(msg2.header.rcode, RCode::BadVers);
+        assert!(msg2.additional.is_empty());
+        assert_eq!(msg, msg2);
+
+        // Extended response codes cannot be encoded without EDNS
+        msg.edns = None;
+        assert_eq!(msg.encode(&mut buf), Err(EncodeError::InvalidRCode));
+
+        // A message may not contain more than one OPT record
+        let mut bytes = bytes;
+        bytes[11] = 2;
+        let opt = bytes[12..].to_vec();
+        bytes.extend(opt);
+
+        assert_eq!(Message::decode(&bytes), Err(DecodeError::InvalidMessage));
+    }
+
+    #[test]
+    fn test_compression() {
+        let mut msg = Message::with_id(0xabcd);
+        msg.question.push(Question::new("example.com.".to_owned(),
+            RecordType::Mx, Class::Internet));
+
+        for &pref in &[10, 20] {
+            let mut rr = Resource::new("example.com.".to_owned(),
+                RecordType::Mx, Class::Internet, 60);
+            rr.write_rdata(&Mx{preference: pref, exchange: "mail.example.com.".to_owned()})
+                .unwrap();
+            msg.answer.push(rr);
+        }
+
+        let mut buf = [0; 512];
+        let bytes = msg.encode(&mut buf).unwrap().to_vec();
+
+        // Question name is written once at offset 12
+        assert_eq!(&bytes[12..29], &b"\x07example\x03com\x00\x00\x0f\x00\x01"[..]);
+        // Answer owner name and exchange suffix are pointers
+        assert_eq!(&bytes[29..31], &[0xc0, 12][..]);
+        assert_eq!(&bytes[39..50], &b"\x00\x09\x00\x0a\x04mail\xc0\x0c"[..]);
+        // Second exchange name is a pointer to the first
+        assert_eq!(&bytes[bytes.len() - 2..], &[0xc0, 43][..]);
+
+        let msg2 = Message::decode(&bytes).unwrap();
+
+        for rr in &msg2.answer {
+            assert_eq!(rr.read_rdata::<Mx>().unwrap().exchange, "mail.example.com.");
+        }
+
+        // Rdata of a decoded message is rewritten without pointers
+        let mut buf = [0; 512];
+        let plain = msg2.encode_with(&mut buf, &Compression::none()).unwrap().to_vec();
+        let msg3 = Message::decode(&plain).unwrap();
+
+        assert_eq!(plain.len(), 29 + 2 * (13 + 10 + 2 + 18));
+        assert_eq!(msg3.answer[1].read_rdata::<Mx>().unwrap(),
+            Mx{preference: 20, exchange: "mail.example.com.".to_owned()});
+
+        let mut compression = Compression::all();
+        compression.exclude.push(RecordType::Mx);
+
+        let mut buf = [0; 512];
+        assert_eq!(msg.encode_with(&mut buf, &compression).unwrap().len(), plain.len());
+    }
+
+    #[test]
+    fn test_rdata_names() {
+        // NSEC record for `example.` whose next name is `XN--BCHER-KVA.example.`
+        let bytes = b"\
+            \xab\xcd\x84\x00\x00\x00\x00\x01\x00\x00\x00\x00\
+            \x07example\x00\x00\x2f\x00\x01\x00\x00\x00\x3c\x00\x1a\
+            \x0dXN--BCHER-KVA\x07example\x00\x00\x01\x40";
+
+        let msg = Message::decode(bytes).unwrap();
+        let mut buf = [0; 512];
+
+        // Rdata without pointers is copied unchanged
+        assert_eq!(msg.encode(&mut buf).unwrap(), &bytes[..]);
+
+        let mut compressed = bytes[..45].to_vec();
+        compressed[30] = 0x13;
+        compressed.extend_from_slice(b"\xc0\x0c\x00\x01\x40");
+
+        // Pointers are expanded to the raw labels they refer to
+        let msg = Message::decode(&compressed).unwrap();
+        assert_eq!(msg.encode(&mut buf).unwrap(), &bytes[..]);
+
+        compressed[46] = 0x40;
+        let msg = Message::decode(&compressed).unwrap();
+        assert_eq!(msg.encode(&mut buf), Err(EncodeError::InvalidRecord));
+    }
+
     #[test]
     fn test_primitives() {
         let mut buf = [0; 64];
@@ -0,0 +1,462 @@
+//! Domain names represented as sequences of raw labels
+//!
+//! Unlike the `String` names used elsewhere in this crate, a `Name` may
+//! contain any bytes in its labels. Names compare and hash without regard
+//! to ASCII case, as described in RFC 4343, while preserving the case
+//! of their labels.
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use idna;
+use message::{LABEL_LIMIT, NAME_LIMIT};
+
+/// Represents an error in parsing or constructing a `Name`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NameError {
+    /// Empty string given as a name
+    EmptyName,
+    /// Name contains an empty label
+    EmptyLabel,
+    /// Label exceeds `LABEL_LIMIT`
+    LabelTooLong,
+    /// Encoded name exceeds `NAME_LIMIT`
+    NameTooLong,
+    /// Invalid escape sequence in presentation format
+    InvalidEscape,
+    /// Label could not be converted to or from IDNA format
+    InvalidIdna,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            NameError::EmptyName => "empty name",
+            NameError::EmptyLabel => "empty label in name",
+            NameError::LabelTooLong => "name label too long",
+            NameError::NameTooLong => "name too long",
+            NameError::InvalidEscape => "invalid escape sequence in name",
+            NameError::InvalidIdna => "invalid internationalized name",
+        })
+    }
+}
+
+impl Error for NameError {}
+
+/// An absolute domain name, stored as a sequence of raw labels.
+///
+/// Equality, hashing, and ordering ignore ASCII case. Ordering follows
+/// the canonical order of RFC 4034, section 6.1.
+#[derive(Clone, Debug)]
+pub struct Name {
+    labels: Vec<Vec<u8>>,
+}
+
+impl Name {
+    /// Returns the root name, `.`.
+    pub fn root() -> Name {
+        Name{labels: Vec::new()}
+    }
+
+    /// Constructs a name from a series of raw labels, beginning with the
+    /// leftmost label. The root label is implied and should not be given.
+    pub fn from_labels<I>(labels: I) -> Result<Name, NameError>
+            where I: IntoIterator, I::Item: Into<Vec<u8>> {
+        let labels = labels.into_iter().map(Into::into).collect::<Vec<_>>();
+
+        for label in &labels {
+            try!(check_label(label));
+        }
+
+        let name = Name{labels: labels};
+
+        if name.wire_len() > NAME_LIMIT {
+            return Err(NameError::NameTooLong);
+        }
+
+        Ok(name)
+    }
+
+    /// Constructs a name from labels which are known to be valid.
+    pub(crate) fn from_raw_labels(labels: Vec<Vec<u8>>) -> Name {
+        Name{labels: labels}
+    }
+
+    /// Parses a name which may contain Unicode labels, converting each
+    /// such label to its ASCII format.
+    ///
+    /// Labels containing escape sequences are not converted.
+    pub fn from_idna(s: &str) -> Result<Name, NameError> {
+        let mut labels = Vec::new();
+
+        for label in try!(split_labels(s)) {
+            if label.contains('\\') || label.is_ascii() {
+                labels.push(try!(unescape_label(label)));
+            } else {
+                match idna::to_ascii(label) {
+                    Ok(label) => labels.push(label.into_owned().into_bytes()),
+                    Err(_) => return Err(NameError::InvalidIdna)
+                }
+            }
+        }
+
+        Name::from_labels(labels)
+    }
+
+    /// Returns the name in presentation format, converting ASCII-encoded
+    /// internationalized labels to Unicode.
+    pub fn to_idna(&self) -> Result<String, NameError> {
+        if self.is_root() {
+            return Ok(".".to_owned());
+        }
+
+        let mut res = String::new();
+
+        for label in &self.labels {
+            let escaped = escape_label(label);
+
+            if escaped.as_bytes() == &label[..] {
+                match idna::to_unicode(&escaped) {
+                    Ok(s) => res.push_str(&s),
+                    Err(_) => return Err(NameError::InvalidIdna)
+                }
+            } else {
+                res.push_str(&escaped);
+            }
+
+            res.push('.');
+        }
+
+        Ok(res)
+    }
+
+    /// Returns the labels of the name, beginning with the leftmost label.
+    /// The root label is not included.
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    /// Returns the number of labels in the name, not including the root.
+    pub fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Returns whether this is the root name.
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Returns the length of the name in uncompressed wire format.
+    pub fn wire_len(&self) -> usize {
+        self.labels.iter().map(|l| 1 + l.len()).sum::<usize>() + 1
+    }
+
+    /// Returns the name with its leftmost label removed.
+    ///
+    /// Returns `None` if this is the root name.
+    pub fn parent(&self) -> Option<Name> {
+        if self.is_root() {
+            None
+        } else {
+            Some(Name{labels: self.labels[1..].to_vec()})
+        }
+    }
+
+    /// Returns whether this name is equal to or a subdomain of `other`.
+    pub fn is_subdomain_of(&self, other: &Name) -> bool {
+        self.labels.len() >= other.labels.len() &&
+            self.labels.iter().rev().zip(other.labels.iter().rev())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// Returns a copy of the name with all ASCII letters in lowercase.
+    pub fn to_lowercase(&self) -> Name {
+        Name{labels: self.labels.iter().map(|l| l.to_ascii_lowercase()).collect()}
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return f.write_str(".");
+        }
+
+        for label in &self.labels {
+            try!(f.write_str(&escape_label(label)));
+            try!(f.write_str("."));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Name {
+    type Err = NameError;
+
+    /// Parses a name in presentation format. Names are always absolute;
+    /// the trailing `.` is optional.
+    fn from_str(s: &str) -> Result<Name, NameError> {
+        let labels = try!(split_labels(s)).into_iter()
+            .map(unescape_label).collect::<Result<Vec<_>, _>>();
+
+        Name::from_labels(try!(labels))
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.labels.len() == other.labels.len() &&
+            self.labels.iter().zip(&other.labels)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for label in &self.labels {
+            state.write_u8(label.len() as u8);
+
+            for b in label {
+                state.write_u8(b.to_ascii_lowercase());
+            }
+        }
+        state.write_u8(0);
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Name) -> Ordering {
+        for (a, b) in self.labels.iter().rev().zip(other.labels.iter().rev()) {
+            let ord = a.iter().map(|b| b.to_ascii_lowercase())
+                .cmp(b.iter().map(|b| b.to_ascii_lowercase()));
+
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+
+        self.labels.len().cmp(&other.labels.len())
+    }
+}
+
+fn check_label(label: &[u8]) -> Result<(), NameError> {
+    if label.is_empty() {
+        Err(NameError::EmptyLabel)
+    } else if label.len() > LABEL_LIMIT {
+        Err(NameError::LabelTooLong)
+    } else {
+        Ok(())
+    }
+}
+
+/// Splits a name in presentation format into its labels, without
+/// interpreting escape sequences. `.` is returned as an empty list.
+pub(crate) fn split_labels(s: &str) -> Result<Vec<&str>, NameError> {
+    if s.is_empty() {
+        return Err(NameError::EmptyName);
+    } else if s == "." {
+        return Ok(Vec::new());
+    }
+
+    let mut labels = Vec::new();
+    let mut start = 0;
+    let mut escape = false;
+
+    for (i, ch) in s.char_indices() {
+        if escape {
+            escape = false;
+        } else if ch == '\\' {
+            escape = true;
+        } else if ch == '.' {
+            if i == start {
+                return Err(NameError::EmptyLabel);
+            }
+            labels.push(&s[start..i]);
+            start = i + 1;
+        }
+    }
+
+    if escape {
+        return Err(NameError::InvalidEscape);
+    }
+
+    if start != s.len() {
+        labels.push(&s[start..]);
+    }
+
+    Ok(labels)
+}
+
+/// Decodes a single label in presentation format into raw bytes.
+pub(crate) fn unescape_label(s: &str) -> Result<Vec<u8>, NameError> {
+    let res = try!(unescape(s));
+    try!(check_label(&res));
+    Ok(res)
+}
+
+/// Decodes `\X` and `\DDD` escapes in presentation format text.
+pub(crate) fn unescape(s: &str) -> Result<Vec<u8>, NameError> {
+    let mut res = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            res.push(b);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(d) if d.is_ascii_digit() => {
+                let mut n = (d - b'0') as u32;
+
+                for _ in 0..2 {
+                    match bytes.next() {
+                        Some(d) if d.is_ascii_digit() => n = n * 10 + (d - b'0') as u32,
+                        _ => return Err(NameError::InvalidEscape)
+                    }
+                }
+
+                if n > 255 {
+                    return Err(NameError::InvalidEscape);
+                }
+
+                res.push(n as u8);
+            }
+            Some(b) => res.push(b),
+            None => return Err(NameError::InvalidEscape)
+        }
+    }
+
+    Ok(res)
+}
+
+/// Encodes a raw label in presentation format, escaping special
+/// characters with `\X` and non-printable bytes with `\DDD`.
+pub(crate) fn escape_label(label: &[u8]) -> String {
+    let mut res = String::with_capacity(label.len());
+
+    for &b in label {
+        match b {
+            b'.' | b'\\' | b'"' | b';' | b'(' | b')' | b'@' | b'$' => {
+                res.push('\\');
+                res.push(b as char);
+            }
+            0x21 ..= 0x7e => res.push(b as char),
+            _ => res.push_str(&format!("\\{:03}", b)),
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use message::{MsgReader, MsgWriter};
+    use super::{Name, NameError};
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_presentation() {
+        let n = name("a\\.b\\000\\255.Example.COM");
+
+        assert_eq!(n.labels(), &[b"a.b\x00\xff".to_vec(),
+            b"Example".to_vec(), b"COM".to_vec()][..]);
+        assert_eq!(n.to_string(), "a\\.b\\000\\255.Example.COM.");
+        assert_eq!(name(&n.to_string()).labels(), n.labels());
+
+        assert_eq!(name(".").to_string(), ".");
+        assert_eq!(name("sp\\ ace\\@").to_string(), "sp\\032ace\\@.");
+
+        assert_eq!("".parse::<Name>(), Err(NameError::EmptyName));
+        assert_eq!("a..b".parse::<Name>(), Err(NameError::EmptyLabel));
+        assert_eq!("a\\256".parse::<Name>(), Err(NameError::InvalidEscape));
+        assert_eq!("a\\1b".parse::<Name>(), Err(NameError::InvalidEscape));
+        assert_eq!("a\\".parse::<Name>(), Err(NameError::InvalidEscape));
+        assert_eq!(format!("{}.com", "a".repeat(64)).parse::<Name>(),
+            Err(NameError::LabelTooLong));
+    }
+
+    #[test]
+    fn test_case() {
+        let a = name("WWW.Example.com");
+        let b = name("www.example.COM.");
+
+        assert_eq!(a, b);
+        assert_eq!(a.to_string(), "WWW.Example.com.");
+        assert!(a.is_subdomain_of(&name("EXAMPLE.com")));
+        assert!(!a.is_subdomain_of(&name("ample.com")));
+        assert_eq!(a.to_lowercase().to_string(), "www.example.com.");
+
+        let mut set = HashSet::new();
+        set.insert(a);
+        assert!(set.contains(&b));
+        assert!(!set.contains(&name("www.example.org")));
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        // Example from RFC 4034, section 6.1
+        let names = [
+            "example", "a.example", "yljkjljk.a.example", "Z.a.example",
+            "zABC.a.EXAMPLE", "z.example", "\\001.z.example", "*.z.example",
+            "\\200.z.example",
+        ];
+
+        let mut sorted = names.iter().map(|s| name(s)).collect::<Vec<_>>();
+        sorted.reverse();
+        sorted.sort();
+
+        for (n, s) in sorted.iter().zip(names.iter()) {
+            assert_eq!(n, &name(s));
+        }
+    }
+
+    #[test]
+    fn test_idna() {
+        let n = Name::from_idna("bücher.de").unwrap();
+
+        assert_eq!(n.to_string(), "xn--bcher-kva.de.");
+        assert_eq!(n.to_idna().unwrap(), "bücher.de.");
+
+        let n = Name::from_idna("b\\195\\188cher.de").unwrap();
+
+        assert_eq!(n.to_string(), "b\\195\\188cher.de.");
+        assert_eq!(n.to_idna().unwrap(), "b\\195\\188cher.de.");
+    }
+
+    #[test]
+    fn test_wire() {
+        let n = name("A\\000b.Example.");
+        let mut buf = [0; 64];
+        let mut w = MsgWriter::new(&mut buf);
+
+        w.write_raw_name(&n).unwrap();
+        w.write_raw_name(&name("c.example")).unwrap();
+
+        let bytes = w.into_bytes();
+        assert_eq!(bytes.len(), n.wire_len() + 11);
+
+        let mut r = MsgReader::new(bytes);
+
+        let a = r.read_raw_name().unwrap();
+        assert_eq!(a.labels(), n.labels());
+        assert_eq!(a.to_string(), "A\\000b.Example.");
+        assert_eq!(r.read_raw_name().unwrap(), name("C.EXAMPLE"));
+    }
+}
@@ -1,12 +1,73 @@
 //! DNS resource record types
 
+use std::error::Error;
+use std::fmt;
 use std::mem::transmute;
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::vec;
 
 use message::{DecodeError, EncodeError, MsgReader, MsgWriter};
+use name::{self, Name};
 
-/// Represents the class of data in a message.
+/// Represents an error in parsing a value from presentation format
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// Unrecognized record type mnemonic
+    UnknownType,
+    /// Unrecognized class mnemonic
+    UnknownClass,
+    /// Too few fields in record data
+    MissingField,
+    /// Too many fields in record data
+    ExtraField,
+    /// Invalid numeric field
+    InvalidNumber,
+    /// Invalid address field
+    InvalidAddress,
+    /// Invalid name field
+    InvalidName,
+    /// Invalid or unterminated character string
+    InvalidString,
+    /// Invalid record data in the generic format of RFC 3597
+    InvalidGeneric,
+    /// Invalid base64, base32, or hexadecimal data
+    InvalidEncoding,
+    /// Invalid, duplicate, or unknown service binding parameter
+    InvalidParam,
+    /// Invalid `CAA` property tag
+    InvalidTag,
+    /// Record data exceeds the maximum encoded length
+    TooLong,
+    /// Record data could not be encoded for its record type
+    InvalidRecord,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ParseError::UnknownType => "unknown record type",
+            ParseError::UnknownClass => "unknown class",
+            ParseError::MissingField => "missing field in record data",
+            ParseError::ExtraField => "extra field in record data",
+            ParseError::InvalidNumber => "invalid number",
+            ParseError::InvalidAddress => "invalid address",
+            ParseError::InvalidName => "invalid name",
+            ParseError::InvalidString => "invalid character string",
+            ParseError::InvalidGeneric => "invalid generic record data",
+            ParseError::InvalidEncoding => "invalid encoded data",
+            ParseError::InvalidParam => "invalid service parameter",
+            ParseError::InvalidTag => "invalid property tag",
+            ParseError::TooLong => "record data too long",
+            ParseError::InvalidRecord => "invalid record data",
+        })
+    }
+}
+
+impl Error for ParseError {}
+
+/// Represents the class of data in a message.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Class {
     /// Internet (`IN`)
     Internet,
@@ -36,25 +97,74 @@ impl Class {
     }
 }
 
+impl FromStr for Class {
+    type Err = ParseError;
+
+    /// Parses a class mnemonic or the `CLASSnnn` form of RFC 3597.
+    fn from_str(s: &str) -> Result<Class, ParseError> {
+        if s.eq_ignore_ascii_case("IN") {
+            Ok(Class::Internet)
+        } else if s.eq_ignore_ascii_case("ANY") {
+            Ok(Class::Any)
+        } else {
+            parse_generic(s, "CLASS").map(Class::from_u16)
+                .ok_or(ParseError::UnknownClass)
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    /// Formats the class as its mnemonic, or as `CLASSnnn` if it has none.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Class::Internet => f.write_str("IN"),
+            Class::Any => f.write_str("ANY"),
+            Class::Other(n) => write!(f, "CLASS{}", n),
+        }
+    }
+}
+
 /// Represents the type of data in a message.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum RecordType {
     /// An IPv4 host address
     A,
     /// An IPv6 host address
     AAAA,
+    /// Certification authority authorization
+    Caa,
     /// Canonical name for an alias
     CName,
+    /// Delegation name; an alias for a subtree of names
+    DName,
+    /// DNS public key
+    Dnskey,
+    /// Delegation signer
+    Ds,
+    /// Service binding for HTTPS origins
+    Https,
     /// Mail exchange
     Mx,
     /// Authoritative name server
     Ns,
+    /// Next secure record
+    Nsec,
+    /// Hashed next secure record
+    Nsec3,
+    /// Parameters of hashed next secure records
+    Nsec3Param,
+    /// EDNS(0) option pseudo-record; carried in `Message::edns`
+    Opt,
     /// Domain name pointer
     Ptr,
+    /// Resource record signature
+    Rrsig,
     /// Start of authority
     Soa,
     /// Service record
     Srv,
+    /// General purpose service binding
+    Svcb,
     /// Text string
     Txt,
     /// Unrecognized record type
@@ -62,7 +172,7 @@ pub enum RecordType {
 }
 
 macro_rules! record_types {
-    ( $( $name:ident => $code:expr , )+ ) => {
+    ( $( $name:ident => $code:expr , $mnemonic:expr ; )+ ) => {
         impl RecordType {
             /// Converts a `u16` to a `RecordType`.
             pub fn from_u16(u: u16) -> RecordType {
@@ -80,19 +190,54 @@ macro_rules! record_types {
                 }
             }
         }
+
+        impl FromStr for RecordType {
+            type Err = ParseError;
+
+            /// Parses a type mnemonic or the `TYPEnnn` form of RFC 3597.
+            fn from_str(s: &str) -> Result<RecordType, ParseError> {
+                $( if s.eq_ignore_ascii_case($mnemonic) {
+                    return Ok(RecordType::$name);
+                } )+
+
+                parse_generic(s, "TYPE").map(RecordType::from_u16)
+                    .ok_or(ParseError::UnknownType)
+            }
+        }
+
+        impl fmt::Display for RecordType {
+            /// Formats the type as its mnemonic, or as `TYPEnnn` if it has none.
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match *self {
+                    $( RecordType::$name => f.write_str($mnemonic) , )+
+                    RecordType::Other(n) => write!(f, "TYPE{}", n),
+                }
+            }
+        }
     }
 }
 
 record_types!{
-    A => 1,
-    AAAA => 28,
-    CName => 5,
-    Mx => 15,
-    Ns => 2,
-    Ptr => 12,
-    Soa => 6,
-    Srv => 33,
-    Txt => 16,
+    A => 1, "A";
+    AAAA => 28, "AAAA";
+    Caa => 257, "CAA";
+    CName => 5, "CNAME";
+    DName => 39, "DNAME";
+    Dnskey => 48, "DNSKEY";
+    Ds => 43, "DS";
+    Https => 65, "HTTPS";
+    Mx => 15, "MX";
+    Ns => 2, "NS";
+    Nsec => 47, "NSEC";
+    Nsec3 => 50, "NSEC3";
+    Nsec3Param => 51, "NSEC3PARAM";
+    Opt => 41, "OPT";
+    Ptr => 12, "PTR";
+    Rrsig => 46, "RRSIG";
+    Soa => 6, "SOA";
+    Srv => 33, "SRV";
+    Svcb => 64, "SVCB";
+    Txt => 16, "TXT";
 }
 
 /// Represents resource record data.
@@ -128,6 +273,18 @@ impl Record for A {
     fn record_type() -> RecordType { RecordType::A }
 }
 
+impl ParseRecord for A {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(A{address: try!(f.address())})
+    }
+}
+
+impl fmt::Display for A {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.address)
+    }
+}
+
 /// An IPv6 host address
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct AAAA {
@@ -157,6 +314,94 @@ impl Record for AAAA {
     fn record_type() -> RecordType { RecordType::AAAA }
 }
 
+impl ParseRecord for AAAA {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(AAAA{address: try!(f.address())})
+    }
+}
+
+impl fmt::Display for AAAA {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.address)
+    }
+}
+
+/// Certification authority authorization record, as described in RFC 8659
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Caa {
+    /// Property flags
+    pub flags: u8,
+    /// Property tag, e.g. `issue`; compared without regard to ASCII case
+    pub tag: String,
+    /// Property value
+    pub value: Vec<u8>,
+}
+
+impl Caa {
+    /// Flag indicating that a certification authority must not issue
+    /// certificates unless it understands the property
+    pub const ISSUER_CRITICAL: u8 = 0x80;
+
+    /// Returns whether the issuer critical flag is set.
+    pub fn is_critical(&self) -> bool {
+        self.flags & Caa::ISSUER_CRITICAL != 0
+    }
+}
+
+impl Record for Caa {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        let flags = try!(data.read_byte());
+        let tag = try!(data.read_character_string());
+
+        if !is_valid_tag(&tag) {
+            return Err(DecodeError::InvalidMessage);
+        }
+
+        Ok(Caa{
+            flags: flags,
+            tag: String::from_utf8(tag).unwrap(),
+            value: try!(data.read_to_end()),
+        })
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        try!(data.write_byte(self.flags));
+        try!(data.write_character_string(self.tag.as_bytes()));
+        data.write(&self.value)
+    }
+
+    fn record_type() -> RecordType { RecordType::Caa }
+}
+
+impl ParseRecord for Caa {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        let flags = try!(f.number());
+        let tag = try!(f.next()).text;
+
+        if !is_valid_tag(tag.as_bytes()) {
+            return Err(ParseError::InvalidTag);
+        }
+
+        let value = try!(f.next().and_then(|tok| name::unescape(tok.text)
+            .map_err(|_| ParseError::InvalidString)));
+
+        Ok(Caa{flags: flags, tag: tag.to_owned(), value: value})
+    }
+}
+
+impl fmt::Display for Caa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} {} ", self.flags, self.tag));
+        fmt_character_string(f, &self.value)
+    }
+}
+
+/// Returns whether a `CAA` property tag consists of 1 to 15 ASCII
+/// letters and digits.
+fn is_valid_tag(tag: &[u8]) -> bool {
+    !tag.is_empty() && tag.len() <= 15 && tag.iter().all(|b| b.is_ascii_alphanumeric())
+}
+
 /// Canonical name for an alias
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct CName {
@@ -176,6 +421,49 @@ impl Record for CName {
     fn record_type() -> RecordType { RecordType::CName }
 }
 
+impl ParseRecord for CName {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(CName{name: try!(f.name())})
+    }
+}
+
+impl fmt::Display for CName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Delegation name, as described in RFC 6672
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DName {
+    /// Name replacing the owner name as a suffix of names beneath it
+    pub name: String,
+}
+
+impl Record for DName {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        Ok(DName{name: try!(data.read_name())})
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        data.write_name(&self.name)
+    }
+
+    fn record_type() -> RecordType { RecordType::DName }
+}
+
+impl ParseRecord for DName {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(DName{name: try!(f.name())})
+    }
+}
+
+impl fmt::Display for DName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
 /// Mail exchange data
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Mx {
@@ -202,6 +490,21 @@ impl Record for Mx {
     fn record_type() -> RecordType { RecordType::Mx }
 }
 
+impl ParseRecord for Mx {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Mx{
+            preference: try!(f.number()),
+            exchange: try!(f.name()),
+        })
+    }
+}
+
+impl fmt::Display for Mx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.preference, self.exchange)
+    }
+}
+
 /// Authoritative name server
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Ns {
@@ -221,6 +524,18 @@ impl Record for Ns {
     fn record_type() -> RecordType { RecordType::Ns }
 }
 
+impl ParseRecord for Ns {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Ns{name: try!(f.name())})
+    }
+}
+
+impl fmt::Display for Ns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
 /// Domain name pointer
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Ptr {
@@ -240,6 +555,18 @@ impl Record for Ptr {
     fn record_type() -> RecordType { RecordType::Ptr }
 }
 
+impl ParseRecord for Ptr {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Ptr{name: try!(f.name())})
+    }
+}
+
+impl fmt::Display for Ptr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
 /// Start of authority
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Soa {
@@ -290,6 +617,27 @@ impl Record for Soa {
     fn record_type() -> RecordType { RecordType::Soa }
 }
 
+impl ParseRecord for Soa {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Soa{
+            mname: try!(f.name()),
+            rname: try!(f.name()),
+            serial: try!(f.number()),
+            refresh: try!(f.ttl()),
+            retry: try!(f.ttl()),
+            expire: try!(f.ttl()),
+            minimum: try!(f.ttl()),
+        })
+    }
+}
+
+impl fmt::Display for Soa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {} {} {} {}", self.mname, self.rname,
+            self.serial, self.refresh, self.retry, self.expire, self.minimum)
+    }
+}
+
 /// Service record
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Srv {
@@ -324,21 +672,2176 @@ impl Record for Srv {
     fn record_type() -> RecordType { RecordType::Srv }
 }
 
+impl ParseRecord for Srv {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Srv{
+            priority: try!(f.number()),
+            weight: try!(f.number()),
+            port: try!(f.number()),
+            target: try!(f.name()),
+        })
+    }
+}
+
+impl fmt::Display for Srv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.priority, self.weight, self.port, self.target)
+    }
+}
+
 /// Text record
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Txt {
     /// One or more character strings
-    pub data: Vec<u8>,
+    ///
+    /// When encoded, strings longer than 255 bytes are split
+    /// into several character strings.
+    pub strings: Vec<Vec<u8>>,
+}
+
+impl Txt {
+    /// Constructs a `Txt` record containing the given data.
+    pub fn new(data: &[u8]) -> Txt {
+        Txt{strings: vec![data.to_vec()]}
+    }
+
+    /// Returns the contents of all character strings, joined together.
+    pub fn join(&self) -> Vec<u8> {
+        self.strings.concat()
+    }
 }
 
 impl Record for Txt {
     fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
-        Ok(Txt{data: try!(data.read_character_string())})
+        let mut strings = vec![try!(data.read_character_string())];
+
+        while data.remaining() != 0 {
+            strings.push(try!(data.read_character_string()));
+        }
+
+        Ok(Txt{strings: strings})
     }
 
     fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
-        data.write_character_string(&self.data)
+        if self.strings.is_empty() {
+            return data.write_character_string(&[]);
+        }
+
+        for s in &self.strings {
+            if s.is_empty() {
+                try!(data.write_character_string(s));
+            }
+
+            for chunk in s.chunks(255) {
+                try!(data.write_character_string(chunk));
+            }
+        }
+
+        Ok(())
     }
 
     fn record_type() -> RecordType { RecordType::Txt }
 }
+
+impl ParseRecord for Txt {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        let mut strings = vec![try!(f.character_string())];
+
+        while !f.is_empty() {
+            strings.push(try!(f.character_string()));
+        }
+
+        Ok(Txt{strings: strings})
+    }
+}
+
+impl fmt::Display for Txt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, s) in self.strings.iter().enumerate() {
+            if i != 0 {
+                try!(f.write_str(" "));
+            }
+            try!(fmt_character_string(f, s));
+        }
+
+        Ok(())
+    }
+}
+
+/// DNS public key, used to verify the signatures of `Rrsig` records
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Dnskey {
+    /// Key flags
+    pub flags: u16,
+    /// Protocol value; always `3`
+    pub protocol: u8,
+    /// Algorithm number of the key
+    pub algorithm: u8,
+    /// Public key material, in an algorithm-specific format
+    pub public_key: Vec<u8>,
+}
+
+impl Dnskey {
+    /// Value of the protocol field required by RFC 4034, section 2.1.2
+    pub const PROTOCOL: u8 = 3;
+    /// Flag indicating that the key may be used to verify zone data
+    pub const ZONE_KEY: u16 = 0x0100;
+    /// Flag indicating that the key has been revoked, as described in RFC 5011
+    pub const REVOKE: u16 = 0x0080;
+    /// Flag indicating that the key is intended to be referred to by a `Ds` record
+    pub const SECURE_ENTRY_POINT: u16 = 0x0001;
+
+    /// Returns the key tag of the key, as described in RFC 4034, Appendix B.
+    pub fn key_tag(&self) -> u16 {
+        let mut rdata = self.flags.to_be_bytes().to_vec();
+        rdata.push(self.protocol);
+        rdata.push(self.algorithm);
+        rdata.extend_from_slice(&self.public_key);
+
+        if self.algorithm == 1 {
+            // RSA/MD5 keys use bits 16 to 31 of the key's modulus
+            let n = rdata.len();
+            return u16::from_be_bytes([rdata[n - 3], rdata[n - 2]]);
+        }
+
+        let mut acc = 0u32;
+
+        for (i, &b) in rdata.iter().enumerate() {
+            acc += if i % 2 == 0 { (b as u32) << 8 } else { b as u32 };
+        }
+
+        acc += acc >> 16;
+        acc as u16
+    }
+}
+
+impl Record for Dnskey {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        Ok(Dnskey{
+            flags: try!(data.read_u16()),
+            protocol: try!(data.read_byte()),
+            algorithm: try!(data.read_byte()),
+            public_key: try!(data.read_to_end()),
+        })
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        try!(data.write_u16(self.flags));
+        try!(data.write_byte(self.protocol));
+        try!(data.write_byte(self.algorithm));
+        data.write(&self.public_key)
+    }
+
+    fn record_type() -> RecordType { RecordType::Dnskey }
+}
+
+impl ParseRecord for Dnskey {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Dnskey{
+            flags: try!(f.number()),
+            protocol: try!(f.number()),
+            algorithm: try!(f.number()),
+            public_key: try!(f.base64()),
+        })
+    }
+}
+
+impl fmt::Display for Dnskey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.flags, self.protocol, self.algorithm,
+            encode_base64(&self.public_key))
+    }
+}
+
+/// Delegation signer; identifies a `Dnskey` of a delegated zone by its digest
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Ds {
+    /// Key tag of the referenced key
+    pub key_tag: u16,
+    /// Algorithm number of the referenced key
+    pub algorithm: u8,
+    /// Algorithm used to produce `digest`
+    pub digest_type: u8,
+    /// Digest of the owner name and rdata of the referenced key
+    pub digest: Vec<u8>,
+}
+
+impl Record for Ds {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        Ok(Ds{
+            key_tag: try!(data.read_u16()),
+            algorithm: try!(data.read_byte()),
+            digest_type: try!(data.read_byte()),
+            digest: try!(data.read_to_end()),
+        })
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        try!(data.write_u16(self.key_tag));
+        try!(data.write_byte(self.algorithm));
+        try!(data.write_byte(self.digest_type));
+        data.write(&self.digest)
+    }
+
+    fn record_type() -> RecordType { RecordType::Ds }
+}
+
+impl ParseRecord for Ds {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Ds{
+            key_tag: try!(f.number()),
+            algorithm: try!(f.number()),
+            digest_type: try!(f.number()),
+            digest: try!(f.hex()),
+        })
+    }
+}
+
+impl fmt::Display for Ds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} {} {} ", self.key_tag, self.algorithm, self.digest_type));
+        fmt_hex(f, &self.digest)
+    }
+}
+
+/// Signature over a set of records of the same owner, class, and type
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rrsig {
+    /// Type of the records covered by the signature
+    pub type_covered: RecordType,
+    /// Algorithm number of the signing key
+    pub algorithm: u8,
+    /// Number of labels in the owner name of the covered records,
+    /// excluding the root label and any wildcard label
+    pub labels: u8,
+    /// TTL of the covered records, as it appears in the zone
+    pub original_ttl: u32,
+    /// Time after which the signature is not valid, in seconds since the epoch
+    pub expiration: u32,
+    /// Time before which the signature is not valid, in seconds since the epoch
+    pub inception: u32,
+    /// Key tag of the signing key
+    pub key_tag: u16,
+    /// Owner name of the signing key
+    pub signer_name: String,
+    /// Cryptographic signature
+    pub signature: Vec<u8>,
+}
+
+impl Record for Rrsig {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        Ok(Rrsig{
+            type_covered: RecordType::from_u16(try!(data.read_u16())),
+            algorithm: try!(data.read_byte()),
+            labels: try!(data.read_byte()),
+            original_ttl: try!(data.read_u32()),
+            expiration: try!(data.read_u32()),
+            inception: try!(data.read_u32()),
+            key_tag: try!(data.read_u16()),
+            signer_name: try!(data.read_name()),
+            signature: try!(data.read_to_end()),
+        })
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        try!(data.write_u16(self.type_covered.to_u16()));
+        try!(data.write_byte(self.algorithm));
+        try!(data.write_byte(self.labels));
+        try!(data.write_u32(self.original_ttl));
+        try!(data.write_u32(self.expiration));
+        try!(data.write_u32(self.inception));
+        try!(data.write_u16(self.key_tag));
+        try!(write_uncompressed(data, &self.signer_name));
+        data.write(&self.signature)
+    }
+
+    fn record_type() -> RecordType { RecordType::Rrsig }
+}
+
+impl ParseRecord for Rrsig {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Rrsig{
+            type_covered: try!(try!(f.next()).text.parse()),
+            algorithm: try!(f.number()),
+            labels: try!(f.number()),
+            original_ttl: try!(f.ttl()),
+            expiration: try!(f.time()),
+            inception: try!(f.time()),
+            key_tag: try!(f.number()),
+            signer_name: try!(f.name()),
+            signature: try!(f.base64()),
+        })
+    }
+}
+
+impl fmt::Display for Rrsig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} {} {} {} ", self.type_covered, self.algorithm,
+            self.labels, self.original_ttl));
+        try!(fmt_time(f, self.expiration));
+        try!(f.write_str(" "));
+        try!(fmt_time(f, self.inception));
+        write!(f, " {} {} {}", self.key_tag, self.signer_name,
+            encode_base64(&self.signature))
+    }
+}
+
+/// Next secure record; proves the nonexistence of names and types
+/// between its owner name and the next name in canonical order
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Nsec {
+    /// Next owner name in the zone, in canonical order
+    pub next_name: String,
+    /// Types present at the owner name
+    pub types: Vec<RecordType>,
+}
+
+impl Record for Nsec {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        Ok(Nsec{
+            next_name: try!(data.read_name()),
+            types: try!(read_type_bitmap(data)),
+        })
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        try!(write_uncompressed(data, &self.next_name));
+        write_type_bitmap(data, &self.types)
+    }
+
+    fn record_type() -> RecordType { RecordType::Nsec }
+}
+
+impl ParseRecord for Nsec {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Nsec{
+            next_name: try!(f.name()),
+            types: try!(f.types()),
+        })
+    }
+}
+
+impl fmt::Display for Nsec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(f.write_str(&self.next_name));
+        fmt_types(f, &self.types)
+    }
+}
+
+/// Hashed next secure record, as described in RFC 5155
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Nsec3 {
+    /// Algorithm used to hash owner names
+    pub hash_algorithm: u8,
+    /// Record flags
+    pub flags: u8,
+    /// Number of additional times the hash function is applied
+    pub iterations: u16,
+    /// Salt appended to names before hashing
+    pub salt: Vec<u8>,
+    /// Next hashed owner name in the zone, in hash order
+    pub next_hashed: Vec<u8>,
+    /// Types present at the original owner name
+    pub types: Vec<RecordType>,
+}
+
+impl Nsec3 {
+    /// Flag indicating that the record may cover unsigned delegations
+    pub const OPT_OUT: u8 = 0x01;
+}
+
+impl Record for Nsec3 {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        Ok(Nsec3{
+            hash_algorithm: try!(data.read_byte()),
+            flags: try!(data.read_byte()),
+            iterations: try!(data.read_u16()),
+            salt: try!(data.read_character_string()),
+            next_hashed: try!(data.read_character_string()),
+            types: try!(read_type_bitmap(data)),
+        })
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        try!(data.write_byte(self.hash_algorithm));
+        try!(data.write_byte(self.flags));
+        try!(data.write_u16(self.iterations));
+        try!(data.write_character_string(&self.salt));
+        try!(data.write_character_string(&self.next_hashed));
+        write_type_bitmap(data, &self.types)
+    }
+
+    fn record_type() -> RecordType { RecordType::Nsec3 }
+}
+
+impl ParseRecord for Nsec3 {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Nsec3{
+            hash_algorithm: try!(f.number()),
+            flags: try!(f.number()),
+            iterations: try!(f.number()),
+            salt: try!(f.salt()),
+            next_hashed: try!(f.next().and_then(|tok| decode_base32hex(tok.text)
+                .filter(|hash| !hash.is_empty() && hash.len() <= 255)
+                .ok_or(ParseError::InvalidEncoding))),
+            types: try!(f.types()),
+        })
+    }
+}
+
+impl fmt::Display for Nsec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} {} {} ", self.hash_algorithm, self.flags, self.iterations));
+        try!(fmt_salt(f, &self.salt));
+        try!(write!(f, " {}", encode_base32hex(&self.next_hashed)));
+        fmt_types(f, &self.types)
+    }
+}
+
+/// Parameters used by authoritative servers to compute `Nsec3` hashes
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Nsec3Param {
+    /// Algorithm used to hash owner names
+    pub hash_algorithm: u8,
+    /// Record flags; always zero
+    pub flags: u8,
+    /// Number of additional times the hash function is applied
+    pub iterations: u16,
+    /// Salt appended to names before hashing
+    pub salt: Vec<u8>,
+}
+
+impl Record for Nsec3Param {
+    fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+        Ok(Nsec3Param{
+            hash_algorithm: try!(data.read_byte()),
+            flags: try!(data.read_byte()),
+            iterations: try!(data.read_u16()),
+            salt: try!(data.read_character_string()),
+        })
+    }
+
+    fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        try!(data.write_byte(self.hash_algorithm));
+        try!(data.write_byte(self.flags));
+        try!(data.write_u16(self.iterations));
+        data.write_character_string(&self.salt)
+    }
+
+    fn record_type() -> RecordType { RecordType::Nsec3Param }
+}
+
+impl ParseRecord for Nsec3Param {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        Ok(Nsec3Param{
+            hash_algorithm: try!(f.number()),
+            flags: try!(f.number()),
+            iterations: try!(f.number()),
+            salt: try!(f.salt()),
+        })
+    }
+}
+
+impl fmt::Display for Nsec3Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} {} {} ", self.hash_algorithm, self.flags, self.iterations));
+        fmt_salt(f, &self.salt)
+    }
+}
+
+macro_rules! service_binding {
+    ( $( #[$attr:meta] $name:ident ; )+ ) => { $(
+        #[$attr]
+        #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+        pub struct $name {
+            /// Record priority; `0` indicates AliasMode
+            pub priority: u16,
+            /// Target name
+            ///
+            /// In ServiceMode, `.` refers to the owner name. In AliasMode,
+            /// `.` indicates that the service is not available.
+            pub target: String,
+            /// Service parameters
+            ///
+            /// When parsed or encoded, parameters are sorted by key.
+            pub params: Vec<SvcParam>,
+        }
+
+        impl $name {
+            /// Returns whether the record is in AliasMode; that is, whether
+            /// it refers clients to the records of the target name.
+            pub fn is_alias(&self) -> bool {
+                self.priority == 0
+            }
+
+            /// Returns the parameter with the given key, if present.
+            pub fn param(&self, key: SvcParamKey) -> Option<&SvcParam> {
+                self.params.iter().find(|p| p.key() == key)
+            }
+        }
+
+        impl Record for $name {
+            fn decode(data: &mut MsgReader) -> Result<Self, DecodeError> {
+                Ok($name{
+                    priority: try!(data.read_u16()),
+                    target: try!(data.read_name()),
+                    params: try!(read_params(data)),
+                })
+            }
+
+            fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+                try!(data.write_u16(self.priority));
+                try!(write_uncompressed(data, &self.target));
+                write_params(data, &self.params)
+            }
+
+            fn record_type() -> RecordType { RecordType::$name }
+        }
+
+        impl ParseRecord for $name {
+            fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+                Ok($name{
+                    priority: try!(f.number()),
+                    target: try!(f.name()),
+                    params: try!(f.params()),
+                })
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                try!(write!(f, "{} {}", self.priority, self.target));
+
+                for param in &self.params {
+                    try!(write!(f, " {}", param));
+                }
+
+                Ok(())
+            }
+        }
+    )+ }
+}
+
+service_binding!{
+    /// General purpose service binding record, as described in RFC 9460
+    Svcb;
+    /// Service binding record for HTTPS origins, as described in RFC 9460
+    Https;
+}
+
+/// Key of a service binding parameter
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum SvcParamKey {
+    /// Keys which clients must support (`mandatory`)
+    Mandatory,
+    /// Supported application protocols (`alpn`)
+    Alpn,
+    /// No support for the default protocol (`no-default-alpn`)
+    NoDefaultAlpn,
+    /// Alternative port (`port`)
+    Port,
+    /// IPv4 address hints (`ipv4hint`)
+    Ipv4Hint,
+    /// Encrypted ClientHello configuration (`ech`)
+    Ech,
+    /// IPv6 address hints (`ipv6hint`)
+    Ipv6Hint,
+    /// Unrecognized key
+    Other(u16),
+}
+
+impl SvcParamKey {
+    /// Converts a `u16` to a `SvcParamKey`.
+    pub fn from_u16(u: u16) -> SvcParamKey {
+        match u {
+            0 => SvcParamKey::Mandatory,
+            1 => SvcParamKey::Alpn,
+            2 => SvcParamKey::NoDefaultAlpn,
+            3 => SvcParamKey::Port,
+            4 => SvcParamKey::Ipv4Hint,
+            5 => SvcParamKey::Ech,
+            6 => SvcParamKey::Ipv6Hint,
+            n => SvcParamKey::Other(n),
+        }
+    }
+
+    /// Converts a `SvcParamKey` to a `u16`.
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            SvcParamKey::Mandatory => 0,
+            SvcParamKey::Alpn => 1,
+            SvcParamKey::NoDefaultAlpn => 2,
+            SvcParamKey::Port => 3,
+            SvcParamKey::Ipv4Hint => 4,
+            SvcParamKey::Ech => 5,
+            SvcParamKey::Ipv6Hint => 6,
+            SvcParamKey::Other(n) => n,
+        }
+    }
+}
+
+impl FromStr for SvcParamKey {
+    type Err = ParseError;
+
+    /// Parses a key name or the generic `keyNNNNN` form.
+    fn from_str(s: &str) -> Result<SvcParamKey, ParseError> {
+        Ok(match s {
+            "mandatory" => SvcParamKey::Mandatory,
+            "alpn" => SvcParamKey::Alpn,
+            "no-default-alpn" => SvcParamKey::NoDefaultAlpn,
+            "port" => SvcParamKey::Port,
+            "ipv4hint" => SvcParamKey::Ipv4Hint,
+            "ech" => SvcParamKey::Ech,
+            "ipv6hint" => SvcParamKey::Ipv6Hint,
+            _ => match parse_generic(s, "key") {
+                Some(65535) | None => return Err(ParseError::InvalidParam),
+                Some(n) => SvcParamKey::from_u16(n)
+            }
+        })
+    }
+}
+
+impl fmt::Display for SvcParamKey {
+    /// Formats the key as its name, or as `keyNNNNN` if it has none.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SvcParamKey::Mandatory => f.write_str("mandatory"),
+            SvcParamKey::Alpn => f.write_str("alpn"),
+            SvcParamKey::NoDefaultAlpn => f.write_str("no-default-alpn"),
+            SvcParamKey::Port => f.write_str("port"),
+            SvcParamKey::Ipv4Hint => f.write_str("ipv4hint"),
+            SvcParamKey::Ech => f.write_str("ech"),
+            SvcParamKey::Ipv6Hint => f.write_str("ipv6hint"),
+            SvcParamKey::Other(n) => write!(f, "key{}", n),
+        }
+    }
+}
+
+/// Service binding parameter
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SvcParam {
+    /// Keys of parameters which clients must support to use the record
+    Mandatory(Vec<SvcParamKey>),
+    /// Application protocol identifiers supported by the service
+    Alpn(Vec<Vec<u8>>),
+    /// Indicates that the default application protocol is not supported
+    NoDefaultAlpn,
+    /// Port on which the service is provided
+    Port(u16),
+    /// IPv4 addresses of the target
+    Ipv4Hint(Vec<Ipv4Addr>),
+    /// Encrypted ClientHello configuration list
+    Ech(Vec<u8>),
+    /// IPv6 addresses of the target
+    Ipv6Hint(Vec<Ipv6Addr>),
+    /// Parameter of an unrecognized key, holding its value in wire format
+    Other(u16, Vec<u8>),
+}
+
+impl SvcParam {
+    /// Returns the key of the parameter.
+    pub fn key(&self) -> SvcParamKey {
+        match *self {
+            SvcParam::Mandatory(_) => SvcParamKey::Mandatory,
+            SvcParam::Alpn(_) => SvcParamKey::Alpn,
+            SvcParam::NoDefaultAlpn => SvcParamKey::NoDefaultAlpn,
+            SvcParam::Port(_) => SvcParamKey::Port,
+            SvcParam::Ipv4Hint(_) => SvcParamKey::Ipv4Hint,
+            SvcParam::Ech(_) => SvcParamKey::Ech,
+            SvcParam::Ipv6Hint(_) => SvcParamKey::Ipv6Hint,
+            SvcParam::Other(n, _) => SvcParamKey::from_u16(n),
+        }
+    }
+
+    fn decode(key: u16, value: &[u8]) -> Result<SvcParam, DecodeError> {
+        let mut r = MsgReader::new(value);
+
+        let param = match SvcParamKey::from_u16(key) {
+            SvcParamKey::Mandatory => {
+                let mut keys = Vec::new();
+
+                while r.remaining() != 0 {
+                    keys.push(SvcParamKey::from_u16(try!(r.read_u16())));
+                }
+
+                SvcParam::Mandatory(keys)
+            }
+            SvcParamKey::Alpn => {
+                let mut ids = Vec::new();
+
+                while r.remaining() != 0 {
+                    ids.push(try!(r.read_character_string()));
+                }
+
+                SvcParam::Alpn(ids)
+            }
+            SvcParamKey::NoDefaultAlpn => SvcParam::NoDefaultAlpn,
+            SvcParamKey::Port => SvcParam::Port(try!(r.read_u16())),
+            SvcParamKey::Ipv4Hint => {
+                let mut addrs = Vec::new();
+
+                while r.remaining() != 0 {
+                    let mut buf = [0; 4];
+                    try!(r.read(&mut buf));
+                    addrs.push(Ipv4Addr::from(buf));
+                }
+
+                SvcParam::Ipv4Hint(addrs)
+            }
+            SvcParamKey::Ech => SvcParam::Ech(try!(r.read_to_end())),
+            SvcParamKey::Ipv6Hint => {
+                let mut addrs = Vec::new();
+
+                while r.remaining() != 0 {
+                    let mut buf = [0; 16];
+                    try!(r.read(&mut buf));
+                    addrs.push(Ipv6Addr::from(buf));
+                }
+
+                SvcParam::Ipv6Hint(addrs)
+            }
+            SvcParamKey::Other(n) => SvcParam::Other(n, try!(r.read_to_end())),
+        };
+
+        try!(r.finish());
+
+        if param.is_empty_list() {
+            return Err(DecodeError::InvalidMessage);
+        }
+
+        Ok(param)
+    }
+
+    /// Returns the value of the parameter in wire format.
+    fn value(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut value = Vec::new();
+
+        match *self {
+            SvcParam::Mandatory(ref keys) => for key in keys {
+                value.extend_from_slice(&key.to_u16().to_be_bytes());
+            },
+            SvcParam::Alpn(ref ids) => for id in ids {
+                if id.len() > 255 {
+                    return Err(EncodeError::TooLong);
+                }
+                value.push(id.len() as u8);
+                value.extend_from_slice(id);
+            },
+            SvcParam::NoDefaultAlpn => (),
+            SvcParam::Port(port) => value.extend_from_slice(&port.to_be_bytes()),
+            SvcParam::Ipv4Hint(ref addrs) => for addr in addrs {
+                value.extend_from_slice(&addr.octets());
+            },
+            SvcParam::Ech(ref data) | SvcParam::Other(_, ref data) =>
+                value.extend_from_slice(data),
+            SvcParam::Ipv6Hint(ref addrs) => for addr in addrs {
+                value.extend_from_slice(&addr.octets());
+            },
+        }
+
+        Ok(value)
+    }
+
+    /// Returns whether the parameter holds a list which must not be empty,
+    /// but is.
+    fn is_empty_list(&self) -> bool {
+        match *self {
+            SvcParam::Mandatory(ref keys) => keys.is_empty(),
+            SvcParam::Alpn(ref ids) => ids.is_empty() || ids.iter().any(|id| id.is_empty()),
+            SvcParam::Ipv4Hint(ref addrs) => addrs.is_empty(),
+            SvcParam::Ipv6Hint(ref addrs) => addrs.is_empty(),
+            _ => false
+        }
+    }
+
+    /// Parses a parameter from presentation format, e.g. `alpn=h2,h3`.
+    fn parse(s: &str) -> Result<SvcParam, ParseError> {
+        let (key, value) = match s.find('=') {
+            Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+            None => (s, None)
+        };
+
+        let key = try!(key.parse());
+
+        let value = match value {
+            Some(v) => {
+                let v = if v.len() >= 2 && v.starts_with('"') && v.ends_with('"') {
+                    &v[1..v.len() - 1]
+                } else {
+                    v
+                };
+                Some(try!(name::unescape(v).map_err(|_| ParseError::InvalidParam)))
+            }
+            None => None
+        };
+
+        let param = match (key, value) {
+            (SvcParamKey::NoDefaultAlpn, None) => SvcParam::NoDefaultAlpn,
+            (SvcParamKey::Other(n), None) => SvcParam::Other(n, Vec::new()),
+            (SvcParamKey::Other(n), Some(value)) => SvcParam::Other(n, value),
+            (_, None) | (SvcParamKey::NoDefaultAlpn, Some(_)) =>
+                return Err(ParseError::InvalidParam),
+            (SvcParamKey::Mandatory, Some(value)) => {
+                let mut keys: Vec<SvcParamKey> = try!(parse_list(&value, |item| item.parse()));
+                keys.sort_by_key(|key| key.to_u16());
+
+                if keys.windows(2).any(|w| w[0] == w[1]) {
+                    return Err(ParseError::InvalidParam);
+                }
+
+                SvcParam::Mandatory(keys)
+            }
+            (SvcParamKey::Alpn, Some(value)) => SvcParam::Alpn(split_list(&value)),
+            (SvcParamKey::Port, Some(value)) => SvcParam::Port(
+                try!(parse_text(&value, |s| s.parse()
+                    .map_err(|_| ParseError::InvalidNumber)))),
+            (SvcParamKey::Ipv4Hint, Some(value)) => SvcParam::Ipv4Hint(
+                try!(parse_list(&value, |item| item.parse()
+                    .map_err(|_| ParseError::InvalidAddress)))),
+            (SvcParamKey::Ech, Some(value)) => SvcParam::Ech(
+                try!(parse_text(&value, |s| decode_base64(s)
+                    .ok_or(ParseError::InvalidEncoding)))),
+            (SvcParamKey::Ipv6Hint, Some(value)) => SvcParam::Ipv6Hint(
+                try!(parse_list(&value, |item| item.parse()
+                    .map_err(|_| ParseError::InvalidAddress)))),
+        };
+
+        let too_long = match param {
+            SvcParam::Alpn(ref ids) => ids.iter().any(|id| id.len() > 255),
+            _ => false
+        };
+
+        if param.is_empty_list() || too_long {
+            return Err(ParseError::InvalidParam);
+        }
+
+        Ok(param)
+    }
+}
+
+impl fmt::Display for SvcParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SvcParam::Mandatory(ref keys) => {
+                try!(f.write_str("mandatory="));
+                fmt_list(f, keys)
+            }
+            SvcParam::Alpn(ref ids) => {
+                // Commas and backslashes within an item are escaped before
+                // the list is formatted as a character string
+                let mut list = Vec::new();
+
+                for (i, id) in ids.iter().enumerate() {
+                    if i != 0 {
+                        list.push(b',');
+                    }
+                    for &b in id {
+                        if b == b',' || b == b'\\' {
+                            list.push(b'\\');
+                        }
+                        list.push(b);
+                    }
+                }
+
+                try!(f.write_str("alpn="));
+                fmt_character_string(f, &list)
+            }
+            SvcParam::NoDefaultAlpn => f.write_str("no-default-alpn"),
+            SvcParam::Port(port) => write!(f, "port={}", port),
+            SvcParam::Ipv4Hint(ref addrs) => {
+                try!(f.write_str("ipv4hint="));
+                fmt_list(f, addrs)
+            }
+            SvcParam::Ech(ref data) => write!(f, "ech={}", encode_base64(data)),
+            SvcParam::Ipv6Hint(ref addrs) => {
+                try!(f.write_str("ipv6hint="));
+                fmt_list(f, addrs)
+            }
+            SvcParam::Other(n, ref value) if value.is_empty() => write!(f, "key{}", n),
+            SvcParam::Other(n, ref value) => {
+                try!(write!(f, "key{}=", n));
+                fmt_character_string(f, value)
+            }
+        }
+    }
+}
+
+/// Reads service binding parameters, which must be in strictly
+/// increasing order of key.
+fn read_params(data: &mut MsgReader) -> Result<Vec<SvcParam>, DecodeError> {
+    let mut params = Vec::new();
+    let mut last = None;
+
+    while data.remaining() != 0 {
+        let key = try!(data.read_u16());
+
+        if last.is_some_and(|last| key <= last) {
+            return Err(DecodeError::InvalidMessage);
+        }
+        last = Some(key);
+
+        let len = try!(data.read_u16()) as usize;
+        let mut value = Vec::new();
+        try!(data.read_into(&mut value, len));
+
+        params.push(try!(SvcParam::decode(key, &value)));
+    }
+
+    Ok(params)
+}
+
+fn write_params(data: &mut MsgWriter, params: &[SvcParam]) -> Result<(), EncodeError> {
+    let mut params = params.iter().collect::<Vec<_>>();
+    params.sort_by_key(|p| p.key().to_u16());
+
+    for param in params {
+        let value = try!(param.value());
+
+        if value.len() > 0xffff {
+            return Err(EncodeError::TooLong);
+        }
+
+        try!(data.write_u16(param.key().to_u16()));
+        try!(data.write_u16(value.len() as u16));
+        try!(data.write(&value));
+    }
+
+    Ok(())
+}
+
+/// Splits a list of values at commas, except those escaped with `\`.
+fn split_list(value: &[u8]) -> Vec<Vec<u8>> {
+    let mut items = vec![Vec::new()];
+    let mut bytes = value.iter();
+
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\\' => if let Some(&b) = bytes.next() {
+                items.last_mut().unwrap().push(b);
+            },
+            b',' => items.push(Vec::new()),
+            _ => items.last_mut().unwrap().push(b)
+        }
+    }
+
+    items
+}
+
+fn parse_list<T, F>(value: &[u8], mut f: F) -> Result<Vec<T>, ParseError>
+        where F: FnMut(&str) -> Result<T, ParseError> {
+    split_list(value).iter().map(|item| parse_text(item, &mut f)).collect()
+}
+
+fn parse_text<T, F>(value: &[u8], f: F) -> Result<T, ParseError>
+        where F: FnOnce(&str) -> Result<T, ParseError> {
+    match ::std::str::from_utf8(value) {
+        Ok(s) => f(s),
+        Err(_) => Err(ParseError::InvalidParam)
+    }
+}
+
+fn fmt_list<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            try!(f.write_str(","));
+        }
+        try!(write!(f, "{}", item));
+    }
+
+    Ok(())
+}
+
+/// Writes a name which must not be compressed, regardless of whether
+/// compression is enabled.
+fn write_uncompressed(data: &mut MsgWriter, name: &str) -> Result<(), EncodeError> {
+    let compress = data.compression();
+    data.set_compression(false);
+    let res = data.write_name(name);
+    data.set_compression(compress);
+    res
+}
+
+/// Decodes a type bitmap, as described in RFC 4034, section 4.1.2,
+/// from the remaining record data.
+fn read_type_bitmap(data: &mut MsgReader) -> Result<Vec<RecordType>, DecodeError> {
+    let mut types = Vec::new();
+    let mut last_window = None;
+
+    while data.remaining() != 0 {
+        let window = try!(data.read_byte());
+        let len = try!(data.read_byte()) as usize;
+
+        // Windows must appear in increasing order
+        if len == 0 || len > 32 || last_window.is_some_and(|last| window <= last) {
+            return Err(DecodeError::InvalidMessage);
+        }
+        last_window = Some(window);
+
+        let mut bits = [0; 32];
+        try!(data.read(&mut bits[..len]));
+
+        for (i, &b) in bits[..len].iter().enumerate() {
+            for bit in 0..8 {
+                if b & (0x80 >> bit) != 0 {
+                    let low = (i * 8 + bit) as u16;
+                    types.push(RecordType::from_u16((window as u16) << 8 | low));
+                }
+            }
+        }
+    }
+
+    Ok(types)
+}
+
+/// Encodes a type bitmap, as described in RFC 4034, section 4.1.2.
+fn write_type_bitmap(data: &mut MsgWriter, types: &[RecordType]) -> Result<(), EncodeError> {
+    let mut codes = types.iter().map(|t| t.to_u16()).collect::<Vec<_>>();
+    codes.sort();
+    codes.dedup();
+
+    let mut i = 0;
+
+    while i < codes.len() {
+        let window = codes[i] >> 8;
+        let mut bits = [0; 32];
+        let mut len = 0;
+
+        while i < codes.len() && codes[i] >> 8 == window {
+            let low = (codes[i] & 0xff) as usize;
+            bits[low / 8] |= 0x80 >> (low % 8);
+            len = low / 8 + 1;
+            i += 1;
+        }
+
+        try!(data.write_byte(window as u8));
+        try!(data.write_byte(len as u8));
+        try!(data.write(&bits[..len]));
+    }
+
+    Ok(())
+}
+
+/// Formats a list of types, each preceded by a space.
+fn fmt_types(f: &mut fmt::Formatter, types: &[RecordType]) -> fmt::Result {
+    for t in types {
+        try!(write!(f, " {}", t));
+    }
+    Ok(())
+}
+
+/// Formats an `Nsec3` salt as hexadecimal digits, or `-` if it is empty.
+fn fmt_salt(f: &mut fmt::Formatter, salt: &[u8]) -> fmt::Result {
+    if salt.is_empty() {
+        f.write_str("-")
+    } else {
+        fmt_hex(f, salt)
+    }
+}
+
+/// Formats a signature time, as UTC in the form `YYYYMMDDHHmmSS`.
+fn fmt_time(f: &mut fmt::Formatter, t: u32) -> fmt::Result {
+    let (year, month, day) = civil_from_days((t / 86400) as i64);
+    let secs = t % 86400;
+
+    write!(f, "{:04}{:02}{:02}{:02}{:02}{:02}", year, month, day,
+        secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Parses a signature time, either as UTC in the form `YYYYMMDDHHmmSS`
+/// or as a number of seconds since the epoch.
+fn parse_time(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    if s.len() != 14 {
+        return s.parse().ok();
+    }
+
+    let n = |i: usize, len: usize| s[i..i + len].parse::<i64>().unwrap_or(0);
+    let (year, month, day) = (n(0, 4), n(4, 2), n(6, 2));
+    let (hour, min, sec) = (n(8, 2), n(10, 2), n(12, 2));
+
+    let days = days_from_civil(year, month, day);
+
+    if year < 1970 || hour > 23 || min > 59 || sec > 59 ||
+            civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    let t = days * 86400 + hour * 3600 + min * 60 + sec;
+
+    if t > u32::max_value() as i64 {
+        None
+    } else {
+        Some(t as u32)
+    }
+}
+
+/// Returns the year, month, and day of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // Days are counted from 0000-03-01, so that leap days fall at year end
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Returns the number of days since 1970-01-01 of a year, month, and day.
+///
+/// Out of range months or days produce a date which does not
+/// convert back to the same values.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+/// Record data of a type which is not otherwise supported, as described
+/// in RFC 3597
+///
+/// The type of the data is given by the containing resource record.
+/// In presentation format, it is written as `\# <length> <hex data>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Unknown {
+    /// Uninterpreted record data
+    pub data: Vec<u8>,
+}
+
+impl Unknown {
+    /// Decodes all remaining record data.
+    pub fn decode(data: &mut MsgReader) -> Result<Unknown, DecodeError> {
+        Ok(Unknown{data: try!(data.read_to_end())})
+    }
+
+    /// Encodes record data.
+    pub fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+        data.write(&self.data)
+    }
+}
+
+impl ParseRecord for Unknown {
+    fn parse(f: &mut Fields) -> Result<Self, ParseError> {
+        if try!(f.next()).text != "\\#" {
+            return Err(ParseError::InvalidGeneric);
+        }
+
+        let len: usize = try!(f.number());
+        let mut data = Vec::with_capacity(len);
+
+        while !f.is_empty() {
+            match parse_hex(try!(f.next()).text) {
+                Some(ref hex) if data.len() + hex.len() <= len => data.extend(hex),
+                _ => return Err(ParseError::InvalidGeneric)
+            }
+        }
+
+        if data.len() != len {
+            return Err(ParseError::InvalidGeneric);
+        }
+
+        Ok(Unknown{data: data})
+    }
+}
+
+impl fmt::Display for Unknown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_generic_rdata(f, &self.data)
+    }
+}
+
+macro_rules! any_record {
+    ( $( $name:ident )+ ) => {
+        /// Record data of any type
+        ///
+        /// Data of types without a `Record` implementation in this crate,
+        /// or of a type unknown to the program, are held as `Unknown`.
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub enum AnyRecord {
+            $(
+                #[doc = concat!("`", stringify!($name), "` record data")]
+                $name($name),
+            )+
+            /// Data of an unsupported type
+            Unknown(RecordType, Unknown),
+        }
+
+        impl AnyRecord {
+            /// Returns the type of the record data.
+            pub fn record_type(&self) -> RecordType {
+                match *self {
+                    $( AnyRecord::$name(_) => RecordType::$name , )+
+                    AnyRecord::Unknown(r_type, _) => r_type,
+                }
+            }
+
+            /// Decodes record data of the given type.
+            pub fn decode(r_type: RecordType, data: &mut MsgReader)
+                    -> Result<AnyRecord, DecodeError> {
+                match r_type {
+                    $( RecordType::$name => $name::decode(data).map(AnyRecord::$name) , )+
+                    _ => Unknown::decode(data).map(|rec| AnyRecord::Unknown(r_type, rec))
+                }
+            }
+
+            /// Encodes record data.
+            pub fn encode(&self, data: &mut MsgWriter) -> Result<(), EncodeError> {
+                match *self {
+                    $( AnyRecord::$name(ref rec) => rec.encode(data) , )+
+                    AnyRecord::Unknown(_, ref rec) => rec.encode(data),
+                }
+            }
+
+            fn parse_typed(r_type: RecordType, f: &mut Fields)
+                    -> Result<AnyRecord, ParseError> {
+                match r_type {
+                    $( RecordType::$name => $name::parse(f).map(AnyRecord::$name) , )+
+                    _ => Unknown::parse(f).map(|rec| AnyRecord::Unknown(r_type, rec))
+                }
+            }
+        }
+
+        impl fmt::Display for AnyRecord {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match *self {
+                    $( AnyRecord::$name(ref rec) => rec.fmt(f) , )+
+                    AnyRecord::Unknown(_, ref rec) => rec.fmt(f),
+                }
+            }
+        }
+    }
+}
+
+any_record!{
+    A AAAA Caa CName DName Dnskey Ds Https Mx Ns Nsec Nsec3 Nsec3Param Ptr Rrsig Soa Srv
+    Svcb Txt
+}
+
+impl AnyRecord {
+    /// Parses record data of the given type from presentation format.
+    ///
+    /// Data of any type may be given in the generic format of RFC 3597.
+    pub fn parse(r_type: RecordType, s: &str) -> Result<AnyRecord, ParseError> {
+        let mut f = Fields::new(try!(tokenize(s)), None);
+        let rec = try!(AnyRecord::parse_fields(r_type, &mut f));
+        try!(f.finish());
+        Ok(rec)
+    }
+
+    /// Parses record data of the given type from a series of fields.
+    pub(crate) fn parse_fields(r_type: RecordType, f: &mut Fields)
+            -> Result<AnyRecord, ParseError> {
+        if f.peek().is_some_and(|tok| tok.text == "\\#") {
+            let rec = try!(Unknown::parse(f));
+            let mut r = MsgReader::new(&rec.data);
+
+            // Known types given in generic format are decoded as usual
+            match AnyRecord::decode(r_type, &mut r) {
+                Ok(rec) => match r.finish() {
+                    Ok(()) => Ok(rec),
+                    Err(_) => Err(ParseError::InvalidGeneric)
+                },
+                Err(_) => Err(ParseError::InvalidGeneric)
+            }
+        } else {
+            AnyRecord::parse_typed(r_type, f)
+        }
+    }
+}
+
+/// Parses record data from presentation format, given its fields.
+pub(crate) trait ParseRecord: Sized {
+    /// Parses the record from the given fields, leaving any excess fields.
+    fn parse(f: &mut Fields) -> Result<Self, ParseError>;
+}
+
+macro_rules! record_from_str {
+    ( $( $name:ident )+ ) => {
+        $( impl FromStr for $name {
+            type Err = ParseError;
+
+            /// Parses record data from presentation format.
+            fn from_str(s: &str) -> Result<$name, ParseError> {
+                let mut f = Fields::new(try!(tokenize(s)), None);
+                let rec = try!(ParseRecord::parse(&mut f));
+                try!(f.finish());
+                Ok(rec)
+            }
+        } )+
+    }
+}
+
+record_from_str!{
+    A AAAA Caa CName DName Dnskey Ds Https Mx Ns Nsec Nsec3 Nsec3Param Ptr Rrsig Soa Srv
+    Svcb Txt Unknown
+}
+
+/// A single field of record data in presentation format
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Token<'a> {
+    /// Field text, excluding any quotes; escapes are not yet decoded
+    pub text: &'a str,
+    /// Whether the field was enclosed in quotes
+    pub quoted: bool,
+}
+
+/// Splits record data into whitespace-separated fields. Quoted fields may
+/// contain whitespace, and any character may be escaped with `\`.
+pub(crate) fn tokenize<'a>(s: &'a str) -> Result<Vec<Token<'a>>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((start, ch)) = chars.next() {
+        if ch.is_whitespace() {
+            continue;
+        }
+
+        let quoted = ch == '"';
+        let start = if quoted { start + 1 } else { start };
+        let mut end = s.len();
+        let mut closed = !quoted;
+
+        while let Some(&(i, ch)) = chars.peek() {
+            if !quoted && ch.is_whitespace() {
+                end = i;
+                break;
+            }
+
+            chars.next();
+
+            if ch == '\\' {
+                chars.next();
+            } else if quoted && ch == '"' {
+                end = i;
+                closed = true;
+                break;
+            }
+        }
+
+        if !closed {
+            return Err(ParseError::InvalidString);
+        }
+
+        tokens.push(Token{text: &s[start..end], quoted: quoted});
+    }
+
+    Ok(tokens)
+}
+
+/// Yields the fields of record data, interpreting each as a value.
+pub(crate) struct Fields<'a> {
+    tokens: vec::IntoIter<Token<'a>>,
+    /// Origin to which relative names are appended, if any
+    origin: Option<&'a str>,
+}
+
+impl<'a> Fields<'a> {
+    /// Constructs a series of fields. If `origin` is given, relative names
+    /// are made absolute by appending `origin`, and `@` refers to `origin`.
+    pub(crate) fn new(tokens: Vec<Token<'a>>, origin: Option<&'a str>) -> Fields<'a> {
+        Fields{
+            tokens: tokens.into_iter(),
+            origin: origin,
+        }
+    }
+
+    /// Returns the next field.
+    pub(crate) fn next(&mut self) -> Result<Token<'a>, ParseError> {
+        self.tokens.next().ok_or(ParseError::MissingField)
+    }
+
+    /// Returns the next field without consuming it.
+    pub(crate) fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.as_slice().first().cloned()
+    }
+
+    /// Returns whether no fields remain.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tokens.as_slice().is_empty()
+    }
+
+    /// Returns `Err(ExtraField)` if any fields remain.
+    pub(crate) fn finish(mut self) -> Result<(), ParseError> {
+        match self.tokens.next() {
+            Some(_) => Err(ParseError::ExtraField),
+            None => Ok(())
+        }
+    }
+
+    /// Parses a decimal integer field.
+    pub(crate) fn number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let tok = try!(self.next());
+
+        if tok.quoted || !tok.text.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::InvalidNumber);
+        }
+
+        tok.text.parse().map_err(|_| ParseError::InvalidNumber)
+    }
+
+    /// Parses a time interval field, in seconds or with units, e.g. `1h30m`.
+    pub(crate) fn ttl(&mut self) -> Result<u32, ParseError> {
+        self.next().and_then(|tok| parse_ttl(tok.text).ok_or(ParseError::InvalidNumber))
+    }
+
+    /// Parses an IPv4 or IPv6 address field.
+    pub(crate) fn address<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        self.next().and_then(|tok| tok.text.parse().map_err(|_| ParseError::InvalidAddress))
+    }
+
+    /// Parses a name field.
+    pub(crate) fn name(&mut self) -> Result<String, ParseError> {
+        let tok = try!(self.next());
+        let text = tok.text;
+
+        if let Some(origin) = self.origin {
+            if text == "@" {
+                return Ok(origin.to_owned());
+            }
+
+            if !is_absolute(text) {
+                let name = if origin == "." {
+                    format!("{}.", text)
+                } else {
+                    format!("{}.{}", text, origin)
+                };
+
+                return check_name(name);
+            }
+        }
+
+        check_name(text.to_owned())
+    }
+
+    /// Parses a signature time field, either in the form `YYYYMMDDHHmmSS`
+    /// or as a number of seconds.
+    pub(crate) fn time(&mut self) -> Result<u32, ParseError> {
+        self.next().and_then(|tok| parse_time(tok.text).ok_or(ParseError::InvalidNumber))
+    }
+
+    /// Parses all remaining fields as record type mnemonics.
+    pub(crate) fn types(&mut self) -> Result<Vec<RecordType>, ParseError> {
+        let mut types = Vec::new();
+
+        while !self.is_empty() {
+            types.push(try!(try!(self.next()).text.parse()));
+        }
+
+        Ok(types)
+    }
+
+    /// Parses an `Nsec3` salt field of hexadecimal digits, or `-` if empty.
+    pub(crate) fn salt(&mut self) -> Result<Vec<u8>, ParseError> {
+        let tok = try!(self.next());
+
+        if tok.text == "-" {
+            return Ok(Vec::new());
+        }
+
+        match parse_hex(tok.text) {
+            Some(ref salt) if salt.is_empty() || salt.len() > 255 =>
+                Err(ParseError::InvalidEncoding),
+            Some(salt) => Ok(salt),
+            None => Err(ParseError::InvalidEncoding)
+        }
+    }
+
+    /// Parses all remaining fields as service binding parameters.
+    pub(crate) fn params(&mut self) -> Result<Vec<SvcParam>, ParseError> {
+        let mut params: Vec<SvcParam> = Vec::new();
+
+        while !self.is_empty() {
+            let param = try!(SvcParam::parse(try!(self.next()).text));
+
+            if params.iter().any(|p| p.key() == param.key()) {
+                return Err(ParseError::InvalidParam);
+            }
+
+            params.push(param);
+        }
+
+        params.sort_by_key(|p| p.key().to_u16());
+
+        // Mandatory keys must be present and must not include `mandatory`
+        for p in &params {
+            if let SvcParam::Mandatory(ref keys) = *p {
+                if keys.iter().any(|&key| key == SvcParamKey::Mandatory ||
+                        !params.iter().any(|p| p.key() == key)) {
+                    return Err(ParseError::InvalidParam);
+                }
+            }
+        }
+
+        Ok(params)
+    }
+
+    /// Parses all remaining fields as a single string of base64 data.
+    pub(crate) fn base64(&mut self) -> Result<Vec<u8>, ParseError> {
+        let s = try!(self.rest());
+        decode_base64(&s).ok_or(ParseError::InvalidEncoding)
+    }
+
+    /// Parses all remaining fields as a single string of hexadecimal digits.
+    pub(crate) fn hex(&mut self) -> Result<Vec<u8>, ParseError> {
+        let s = try!(self.rest());
+        parse_hex(&s).ok_or(ParseError::InvalidEncoding)
+    }
+
+    /// Joins the text of all remaining fields, of which there must be
+    /// at least one.
+    fn rest(&mut self) -> Result<String, ParseError> {
+        if self.is_empty() {
+            return Err(ParseError::MissingField);
+        }
+
+        Ok(self.tokens.by_ref().map(|tok| tok.text).collect())
+    }
+
+    /// Parses a character string field, which may or may not be quoted.
+    pub(crate) fn character_string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let tok = try!(self.next());
+
+        match name::unescape(tok.text) {
+            Ok(ref s) if s.len() > 255 => Err(ParseError::InvalidString),
+            Ok(s) => Ok(s),
+            Err(_) => Err(ParseError::InvalidString)
+        }
+    }
+}
+
+fn check_name(name: String) -> Result<String, ParseError> {
+    match Name::from_idna(&name) {
+        Ok(_) => Ok(name),
+        Err(_) => Err(ParseError::InvalidName)
+    }
+}
+
+/// Returns whether a name in presentation format ends with an unescaped `.`.
+fn is_absolute(name: &str) -> bool {
+    let mut escape = false;
+    let mut absolute = false;
+
+    for ch in name.chars() {
+        absolute = !escape && ch == '.';
+        escape = !escape && ch == '\\';
+    }
+
+    absolute
+}
+
+/// Parses a TTL value, either as a number of seconds or as a series of
+/// values with units `w`, `d`, `h`, `m`, or `s`, e.g. `1h30m`.
+pub(crate) fn parse_ttl(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse().ok();
+    }
+
+    let mut total = 0u32;
+    let mut n = 0u32;
+    let mut has_digits = false;
+
+    for b in s.bytes() {
+        if b.is_ascii_digit() {
+            n = match n.checked_mul(10).and_then(|n| n.checked_add((b - b'0') as u32)) {
+                Some(n) => n,
+                None => return None
+            };
+            has_digits = true;
+            continue;
+        }
+
+        if !has_digits {
+            return None;
+        }
+
+        let unit = match b.to_ascii_lowercase() {
+            b'w' => 604800,
+            b'd' => 86400,
+            b'h' => 3600,
+            b'm' => 60,
+            b's' => 1,
+            _ => return None
+        };
+
+        total = match n.checked_mul(unit).and_then(|n| total.checked_add(n)) {
+            Some(total) => total,
+            None => return None
+        };
+        n = 0;
+        has_digits = false;
+    }
+
+    if has_digits {
+        return None;
+    }
+
+    Some(total)
+}
+
+/// Parses the RFC 3597 form of an unknown type or class, e.g. `TYPE65534`.
+fn parse_generic(s: &str, prefix: &str) -> Option<u16> {
+    if s.len() > prefix.len() && s[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        let n = &s[prefix.len()..];
+
+        if n.bytes().all(|b| b.is_ascii_digit()) {
+            return n.parse().ok();
+        }
+    }
+
+    None
+}
+
+/// Formats a character string as a quoted string, escaping `"` and `\`
+/// with `\X` and non-printable bytes with `\DDD`.
+pub(crate) fn fmt_character_string(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    try!(f.write_str("\""));
+
+    for &b in data {
+        match b {
+            b'"' | b'\\' => try!(write!(f, "\\{}", b as char)),
+            0x20 ..= 0x7e => try!(write!(f, "{}", b as char)),
+            _ => try!(write!(f, "\\{:03}", b)),
+        }
+    }
+
+    f.write_str("\"")
+}
+
+/// Formats rdata in the generic form of RFC 3597: `\\#`, followed by
+/// the rdata length and the rdata in hexadecimal.
+pub(crate) fn fmt_generic_rdata(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    try!(write!(f, "\\# {}", data.len()));
+
+    if !data.is_empty() {
+        try!(f.write_str(" "));
+        try!(fmt_hex(f, data));
+    }
+
+    Ok(())
+}
+
+/// Parses a string of hexadecimal digits.
+pub(crate) fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return None;
+    }
+
+    (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE32HEX: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+/// Encodes data as base64, with padding.
+pub(crate) fn encode_base64(data: &[u8]) -> String {
+    let mut s = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+
+    s
+}
+
+/// Decodes base64 data, with padding.
+pub(crate) fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+
+    if s.len() % 4 != 0 {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity(s.len() / 4 * 3);
+
+    for (i, chunk) in s.chunks(4).enumerate() {
+        let pad = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+
+        if pad > 2 || (pad != 0 && (i + 1) * 4 != s.len()) {
+            return None;
+        }
+
+        let mut n = 0u32;
+
+        for &b in &chunk[..4 - pad] {
+            let v = match b {
+                b'A' ..= b'Z' => b - b'A',
+                b'a' ..= b'z' => b - b'a' + 26,
+                b'0' ..= b'9' => b - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None
+            };
+            n = n << 6 | v as u32;
+        }
+
+        n <<= 6 * pad;
+        data.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][..3 - pad]);
+    }
+
+    Some(data)
+}
+
+/// Encodes data as base32 with the extended hex alphabet, without padding,
+/// as used in `Nsec3` records.
+pub(crate) fn encode_base32hex(data: &[u8]) -> String {
+    let mut s = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for &b in data {
+        acc = (acc << 8 | b as u32) & 0xfff;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            s.push(BASE32HEX[(acc >> bits) as usize & 31] as char);
+        }
+    }
+
+    if bits != 0 {
+        s.push(BASE32HEX[(acc << (5 - bits)) as usize & 31] as char);
+    }
+
+    s
+}
+
+/// Decodes base32 data with the extended hex alphabet, without padding.
+/// Letters may be of either case.
+pub(crate) fn decode_base32hex(s: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(s.len() * 5 / 8);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for b in s.bytes() {
+        let v = match b.to_ascii_uppercase() {
+            b @ b'0' ..= b'9' => b - b'0',
+            b @ b'A' ..= b'V' => b - b'A' + 10,
+            _ => return None
+        };
+
+        acc = (acc << 5 | v as u32) & 0xfff;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            data.push((acc >> bits) as u8);
+        }
+    }
+
+    // Trailing bits must be fewer than one character and all zero
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+
+    Some(data)
+}
+
+/// Formats data as uppercase hexadecimal digits.
+pub(crate) fn fmt_hex(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    for b in data {
+        try!(write!(f, "{:02X}", b));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use message::Resource;
+    use message::{MsgReader, MsgWriter};
+    use super::{A, AAAA, AnyRecord, Class, CName, Mx, ParseError, RecordType, Soa, Srv, Txt};
+    use super::{Dnskey, Ds, Nsec, Nsec3, Nsec3Param, Record, Rrsig};
+    use super::{decode_base32hex, decode_base64, encode_base32hex, encode_base64};
+    use super::{parse_time, parse_ttl, tokenize, Fields, Unknown};
+    use super::{Caa, Https, SvcParam, SvcParamKey, Svcb};
+
+    #[test]
+    fn test_display() {
+        assert_eq!(RecordType::CName.to_string(), "CNAME");
+        assert_eq!(RecordType::Other(65280).to_string(), "TYPE65280");
+        assert_eq!(Class::Internet.to_string(), "IN");
+        assert_eq!(Class::Other(3).to_string(), "CLASS3");
+
+        assert_eq!(A{address: Ipv4Addr::new(192, 0, 2, 1)}.to_string(), "192.0.2.1");
+        assert_eq!(Mx{preference: 10, exchange: "mail.example.com.".to_owned()}
+            .to_string(), "10 mail.example.com.");
+        assert_eq!(Soa{
+            mname: "ns.example.com.".to_owned(),
+            rname: "hostmaster.example.com.".to_owned(),
+            serial: 2018010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        }.to_string(), "ns.example.com. hostmaster.example.com. \
+            2018010101 7200 3600 1209600 300");
+        assert_eq!(Txt::new(b"say \"hi\"\\\x00").to_string(),
+            "\"say \\\"hi\\\"\\\\\\000\"");
+        assert_eq!(Txt{strings: vec![b"a".to_vec(), b"b c".to_vec()]}.to_string(),
+            "\"a\" \"b c\"");
+    }
+
+    #[test]
+    fn test_txt() {
+        let mut rr = Resource::new("example.com.".to_owned(),
+            RecordType::Txt, Class::Internet, 0);
+
+        rr.write_rdata(&Txt{strings: vec![b"v=DKIM1;".to_vec(), b" p=abc".to_vec()]})
+            .unwrap();
+        assert_eq!(rr.get_rdata(), b"\x08v=DKIM1;\x06 p=abc");
+
+        let txt = rr.read_rdata::<Txt>().unwrap();
+        assert_eq!(txt.strings.len(), 2);
+        assert_eq!(txt.join(), b"v=DKIM1; p=abc");
+
+        let long = vec![b'x'; 600];
+        rr.write_rdata(&Txt::new(&long)).unwrap();
+        assert_eq!(rr.get_rdata().len(), 603);
+
+        let txt = rr.read_rdata::<Txt>().unwrap();
+        assert_eq!(txt.strings.iter().map(|s| s.len()).collect::<Vec<_>>(), [255, 255, 90]);
+        assert_eq!(txt.join(), long);
+
+        rr.write_rdata(&Txt{strings: Vec::new()}).unwrap();
+        assert_eq!(rr.read_rdata::<Txt>().unwrap(), Txt::new(b""));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("cname".parse(), Ok(RecordType::CName));
+        assert_eq!("TYPE65534".parse(), Ok(RecordType::Other(65534)));
+        assert_eq!("type1".parse(), Ok(RecordType::A));
+        assert_eq!("TYPE".parse::<RecordType>(), Err(ParseError::UnknownType));
+        assert_eq!("TYPE65536".parse::<RecordType>(), Err(ParseError::UnknownType));
+        assert_eq!("IN".parse(), Ok(Class::Internet));
+        assert_eq!("CLASS3".parse(), Ok(Class::Other(3)));
+        assert_eq!("CH".parse::<Class>(), Err(ParseError::UnknownClass));
+
+        assert_eq!("192.0.2.1".parse(), Ok(A{address: Ipv4Addr::new(192, 0, 2, 1)}));
+        assert_eq!("::1".parse::<AAAA>().map(|r| r.address), Ok("::1".parse().unwrap()));
+        assert_eq!("10 mail.example.com.".parse(),
+            Ok(Mx{preference: 10, exchange: "mail.example.com.".to_owned()}));
+        assert_eq!("10 20 5060 sip.example.com.".parse(), Ok(Srv{
+            priority: 10,
+            weight: 20,
+            port: 5060,
+            target: "sip.example.com.".to_owned(),
+        }));
+        assert_eq!("ns.example.com. hostmaster.example.com. ( 1 2 3 4 5 )"
+            .parse::<Soa>(), Err(ParseError::InvalidNumber));
+        assert_eq!("ns.example.com. hostmaster.example.com. 1 2 3 4 5"
+            .parse::<Soa>().map(|r| r.minimum), Ok(5));
+        assert_eq!("\"say \\\"hi\\\" \\000\"".parse(),
+            Ok(Txt::new(b"say \"hi\" \x00")));
+        assert_eq!("bare".parse(), Ok(Txt::new(b"bare")));
+        assert_eq!("\"v=DKIM1; k=rsa; \" p=MIGf".parse(), Ok(Txt{strings: vec![
+            b"v=DKIM1; k=rsa; ".to_vec(), b"p=MIGf".to_vec()]}));
+
+        assert_eq!("10".parse::<Mx>(), Err(ParseError::MissingField));
+        assert_eq!("10 a. b.".parse::<Mx>(), Err(ParseError::ExtraField));
+        assert_eq!("-1 a.".parse::<Mx>(), Err(ParseError::InvalidNumber));
+        assert_eq!("70000 a.".parse::<Mx>(), Err(ParseError::InvalidNumber));
+        assert_eq!("a..b".parse::<CName>(), Err(ParseError::InvalidName));
+        assert_eq!("1.2.3".parse::<A>(), Err(ParseError::InvalidAddress));
+        assert_eq!("\"open".parse::<Txt>(), Err(ParseError::InvalidString));
+
+        for s in &["10 mail.example.com.", "0 ."] {
+            assert_eq!(s.parse::<Mx>().unwrap().to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        let tokens = tokenize("10 mail @").unwrap();
+        let mut f = Fields::new(tokens, Some("example.com."));
+
+        assert_eq!(f.number::<u16>(), Ok(10));
+        assert_eq!(f.name(), Ok("mail.example.com.".to_owned()));
+        assert_eq!(f.name(), Ok("example.com.".to_owned()));
+        assert_eq!(f.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_ttl() {
+        assert_eq!(parse_ttl("3600"), Some(3600));
+        assert_eq!(parse_ttl("1h30m"), Some(5400));
+        assert_eq!(parse_ttl("1W"), Some(604800));
+        assert_eq!(parse_ttl("1h30"), None);
+        assert_eq!(parse_ttl("h"), None);
+        assert_eq!(parse_ttl("IN"), None);
+    }
+
+    fn round_trip<R: Record + PartialEq + ::std::fmt::Debug>(rec: &R) -> Vec<u8> {
+        let mut buf = [0; 512];
+        let mut w = MsgWriter::new(&mut buf);
+        w.set_compression(true);
+        rec.encode(&mut w).unwrap();
+        let data = w.into_bytes().to_vec();
+
+        let mut r = MsgReader::new(&data);
+        assert_eq!(&R::decode(&mut r).unwrap(), rec);
+        assert_eq!(r.remaining(), 0);
+        data
+    }
+
+    #[test]
+    fn test_dnssec() {
+        // Examples from RFC 4034, sections 2.3, 3.3, 4.3, and 5.4
+        let key: Dnskey = "256 3 5 ( AQOeiiR0GOMYkDshWoSKz9Xz fwJr1AYtsmx3TGkJaNXVbfi/
+            2pHm822aJ5iI9BMzNXxeYCmZ DRD99WYwYqUSdjMmmAphXdvx egXd/M5+X7OrzKBaMbCVdFLU
+            Uh6DhweJBjEVv5f2wwjM9Xzc nOf+EPbtG9DMBmADjFDc2w/r ljwvFw== )"
+            .replace(|c| c == '(' || c == ')', "").parse().unwrap();
+        assert_eq!(key.flags, Dnskey::ZONE_KEY);
+        assert_eq!(key.public_key.len(), 130);
+        assert_eq!(key.key_tag(), 60485);
+        assert!(key.to_string().starts_with("256 3 5 AQOeiiR0"));
+        assert!(key.to_string().ends_with("ljwvFw=="));
+        round_trip(&key);
+
+        let ds: Ds = "60485 5 1 2BB183AF5F22588179A5 3B0A98631FAD1A292118".parse().unwrap();
+        assert_eq!(ds.digest.len(), 20);
+        assert_eq!(ds.to_string(), "60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118");
+        round_trip(&ds);
+
+        let sig: Rrsig = "A 5 3 86400 20030322173103 20030220173103 2642 example.com. \
+            oJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTrPYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6o\
+            B9wfuh3DTJXUAfI/M0zmO/zz8bW0Rznl8O3tGNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkG\
+            J5D6fwFm8nN+6pBzeDQfsS3Ap3o=".parse().unwrap();
+        assert_eq!(sig.type_covered, RecordType::A);
+        assert_eq!(sig.expiration, 1048354263);
+        assert_eq!(sig.inception, 1045762263);
+        assert_eq!(sig.signer_name, "example.com.");
+        assert!(sig.to_string().starts_with(
+            "A 5 3 86400 20030322173103 20030220173103 2642 example.com. oJB1"));
+        assert_eq!(sig.to_string().parse::<Rrsig>(), Ok(sig.clone()));
+        round_trip(&sig);
+
+        // The signer name is not compressed, even following the same name
+        let mut buf = [0; 512];
+        let mut w = MsgWriter::new(&mut buf);
+        w.set_compression(true);
+        w.write_name("example.com.").unwrap();
+        sig.encode(&mut w).unwrap();
+        assert_eq!(&w.into_bytes()[13 + 18..13 + 18 + 13], b"\x07example\x03com\x00");
+
+        let nsec: Nsec = "host.example.com. A MX RRSIG NSEC TYPE1234".parse().unwrap();
+        assert_eq!(nsec.types, [RecordType::A, RecordType::Mx, RecordType::Rrsig,
+            RecordType::Nsec, RecordType::Other(1234)]);
+        assert_eq!(nsec.to_string(), "host.example.com. A MX RRSIG NSEC TYPE1234");
+        let data = round_trip(&nsec);
+        assert_eq!(&data[18..28], b"\x00\x06\x40\x01\x00\x00\x00\x03\x04\x1b");
+        assert_eq!(&data[28..54], &[0; 26][..]);
+        assert_eq!(&data[54..], b"\x20");
+
+        let nsec3: Nsec3 = "1 1 12 aabbccdd 2t7b4g4vsa5smi47k61mv5bv1a22bojr NS SOA RRSIG"
+            .parse().unwrap();
+        assert_eq!(nsec3.flags, Nsec3::OPT_OUT);
+        assert_eq!(nsec3.salt, [0xaa, 0xbb, 0xcc, 0xdd]);
+        assert_eq!(nsec3.next_hashed.len(), 20);
+        assert_eq!(nsec3.to_string(),
+            "1 1 12 AABBCCDD 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR NS SOA RRSIG");
+        round_trip(&nsec3);
+
+        let param: Nsec3Param = "1 0 0 -".parse().unwrap();
+        assert_eq!(param.salt, []);
+        assert_eq!(param.to_string(), "1 0 0 -");
+        round_trip(&param);
+
+        assert_eq!("256 3 5 AQO=x".parse::<Dnskey>(), Err(ParseError::InvalidEncoding));
+        assert_eq!("1 0 0 A".parse::<Nsec3Param>(), Err(ParseError::InvalidEncoding));
+        assert_eq!("a. A BOGUS".parse::<Nsec>(), Err(ParseError::UnknownType));
+
+        // Windows out of order
+        let data = b"\x00\x04\x01\x00\x00\x01\x00\x01\x40";
+        assert!(Nsec::decode(&mut MsgReader::new(data)).is_err());
+    }
+
+    #[test]
+    fn test_encodings() {
+        for data in &[&b""[..], b"f", b"fo", b"foo", b"foob", b"fooba", b"foobar"] {
+            assert_eq!(decode_base64(&encode_base64(data)).unwrap(), *data);
+            assert_eq!(decode_base32hex(&encode_base32hex(data)).unwrap(), *data);
+        }
+
+        assert_eq!(encode_base64(b"foob"), "Zm9vYg==");
+        assert_eq!(encode_base32hex(b"foobar"), "CPNMUOJ1E8");
+        assert_eq!(decode_base32hex("cpnmuoj1e8").unwrap(), b"foobar");
+        assert_eq!(decode_base64("Zm9=vYg="), None);
+        assert_eq!(decode_base64("Zm9vY"), None);
+        assert_eq!(decode_base32hex("CPNMUOJ1E"), None);
+        assert_eq!(decode_base32hex("W0"), None);
+
+        assert_eq!(parse_time("20240229120000"), Some(1709208000));
+        assert_eq!(parse_time("20230229120000"), None);
+        assert_eq!(parse_time("1709208000"), Some(1709208000));
+        assert_eq!(parse_time("21060207062816"), None);
+    }
+
+    #[test]
+    fn test_unknown() {
+        let unk: Unknown = "\\# 4 0A00 0001".parse().unwrap();
+        assert_eq!(unk.data, [10, 0, 0, 1]);
+        assert_eq!(unk.to_string(), "\\# 4 0A000001");
+        assert_eq!("\\# 0".parse::<Unknown>().unwrap().data, []);
+
+        assert_eq!("\\# 3 0A0000".parse::<Unknown>().unwrap().to_string(),
+            "\\# 3 0A0000");
+        assert_eq!("\\# 2 0A0000".parse::<Unknown>(), Err(ParseError::InvalidGeneric));
+        assert_eq!("\\# 2 0A0".parse::<Unknown>(), Err(ParseError::InvalidGeneric));
+        assert_eq!("\\# 1 zz".parse::<Unknown>(), Err(ParseError::InvalidGeneric));
+        assert_eq!("0A00".parse::<Unknown>(), Err(ParseError::InvalidGeneric));
+
+        let rec = AnyRecord::parse(RecordType::Other(65534), "\\# 2 ABCD").unwrap();
+        assert_eq!(rec.record_type(), RecordType::Other(65534));
+        assert_eq!(rec, AnyRecord::Unknown(RecordType::Other(65534),
+            Unknown{data: vec![0xab, 0xcd]}));
+
+        // Known types given in generic format are decoded normally
+        let rec = AnyRecord::parse(RecordType::A, "\\# 4 C0000201").unwrap();
+        assert_eq!(rec, AnyRecord::A(A{address: Ipv4Addr::new(192, 0, 2, 1)}));
+        assert_eq!(rec.to_string(), "192.0.2.1");
+        assert_eq!(AnyRecord::parse(RecordType::A, "\\# 3 C00002"),
+            Err(ParseError::InvalidGeneric));
+        assert_eq!(AnyRecord::parse(RecordType::Mx, "10 mx.example.com."),
+            Ok(AnyRecord::Mx(Mx{preference: 10, exchange: "mx.example.com.".to_owned()})));
+
+        let mut rr = Resource::new("example.com.".to_owned(),
+            RecordType::Other(65534), Class::Internet, 0);
+        rr.write_any(&AnyRecord::parse(rr.r_type, "\\# 2 ABCD").unwrap()).unwrap();
+        assert_eq!(rr.get_rdata(), [0xab, 0xcd]);
+        assert_eq!(rr.to_string(), "example.com.\t0\tIN\tTYPE65534\t\\# 2 ABCD");
+
+        rr.r_type = RecordType::A;
+        rr.write_any(&AnyRecord::A(A{address: Ipv4Addr::new(192, 0, 2, 1)})).unwrap();
+        assert_eq!(rr.read_any(), Ok(AnyRecord::A(A{address: Ipv4Addr::new(192, 0, 2, 1)})));
+    }
+
+    #[test]
+    fn test_svcb() {
+        // Examples from RFC 9460, Appendix D
+        let alias: Https = "0 foo.example.com.".parse().unwrap();
+        assert!(alias.is_alias());
+        assert_eq!(round_trip(&alias), b"\0\0\x03foo\x07example\x03com\0");
+
+        let svc: Svcb = "1 .".parse().unwrap();
+        assert!(!svc.is_alias());
+        assert_eq!(round_trip(&svc), [0, 1, 0]);
+
+        let svc: Svcb = "16 foo.example.com. port=53".parse().unwrap();
+        assert_eq!(svc.param(SvcParamKey::Port), Some(&SvcParam::Port(53)));
+        assert_eq!(&round_trip(&svc)[19..], [0, 3, 0, 2, 0, 53]);
+
+        let svc: Svcb = "1 foo.example.com. key667=\"hello\\210qoo\"".parse().unwrap();
+        assert_eq!(svc.params, [SvcParam::Other(667, b"hello\xd2qoo".to_vec())]);
+        assert_eq!(&round_trip(&svc)[19..23], [0x02, 0x9b, 0, 9]);
+        assert_eq!(svc.to_string(), "1 foo.example.com. key667=\"hello\\210qoo\"");
+
+        let svc: Svcb = "1 foo.example.com. ipv6hint=\"2001:db8::1,2001:db8::53:1\""
+            .parse().unwrap();
+        assert_eq!(svc.to_string(), "1 foo.example.com. ipv6hint=2001:db8::1,2001:db8::53:1");
+        assert_eq!(round_trip(&svc).len(), 19 + 4 + 32);
+
+        // Parameters and mandatory keys are sorted
+        let svc: Svcb = "16 foo.example.org. alpn=h2,h3-19 mandatory=ipv4hint,alpn \
+            ipv4hint=192.0.2.1".parse().unwrap();
+        assert_eq!(&round_trip(&svc)[19..], &b"\0\0\0\x04\0\x01\0\x04\
+            \0\x01\0\x09\x02h2\x05h3-19\
+            \0\x04\0\x04\xc0\0\x02\x01"[..]);
+        assert_eq!(svc.to_string(), "16 foo.example.org. mandatory=alpn,ipv4hint \
+            alpn=\"h2,h3-19\" ipv4hint=192.0.2.1");
+
+        // Escaped commas and backslashes within alpn values
+        let svc: Svcb = r#"16 foo.example.org. alpn="f\\\\oo\\,bar,h2""#.parse().unwrap();
+        assert_eq!(svc.params, [SvcParam::Alpn(vec![b"f\\oo,bar".to_vec(), b"h2".to_vec()])]);
+        assert_eq!(svc.to_string(), r#"16 foo.example.org. alpn="f\\\\oo\\,bar,h2""#);
+
+        let svc: Https = "1 . alpn=h2 no-default-alpn ech=AEP+DQA/ key65000".parse().unwrap();
+        assert_eq!(svc.params, [
+            SvcParam::Alpn(vec![b"h2".to_vec()]),
+            SvcParam::NoDefaultAlpn,
+            SvcParam::Ech(vec![0x00, 0x43, 0xfe, 0x0d, 0x00, 0x3f]),
+            SvcParam::Other(65000, Vec::new()),
+        ]);
+        assert_eq!(svc.to_string(), "1 . alpn=\"h2\" no-default-alpn ech=AEP+DQA/ key65000");
+        round_trip(&svc);
+
+        // Failure cases from RFC 9460, Appendix D.3
+        for s in &["1 foo.example.com. key123=abc key123=def",
+                "1 foo.example.com. mandatory",
+                "1 foo.example.com. alpn",
+                "1 foo.example.com. port",
+                "1 foo.example.com. ipv4hint",
+                "1 foo.example.com. ipv6hint",
+                "1 foo.example.com. no-default-alpn=abc",
+                "1 foo.example.com. mandatory=key123",
+                "1 foo.example.com. mandatory=mandatory",
+                "1 foo.example.com. ipv6hint=\"2001:db8::1\" mandatory=ipv6hint,ipv6hint",
+                "1 foo.example.com. key65535=x",
+                "1 foo.example.com. unknown=x"] {
+            assert_eq!(s.parse::<Svcb>(), Err(ParseError::InvalidParam), "{}", s);
+        }
+
+        // Keys out of order
+        let data = [0, 1, 0, 0, 3, 0, 2, 0, 53, 0, 1, 0, 3, 2, b'h', b'2'];
+        assert!(Svcb::decode(&mut MsgReader::new(&data)).is_err());
+
+        assert_eq!(AnyRecord::parse(RecordType::Https, "1 . port=8443"),
+            Ok(AnyRecord::Https(Https{
+                priority: 1,
+                target: ".".to_owned(),
+                params: vec![SvcParam::Port(8443)],
+            })));
+    }
+
+    #[test]
+    fn test_caa() {
+        // Examples from RFC 8659, section 4
+        let caa: Caa = "0 issue \"ca.example.net; account=230123\"".parse().unwrap();
+        assert_eq!(caa.tag, "issue");
+        assert_eq!(caa.value, b"ca.example.net; account=230123");
+        assert!(!caa.is_critical());
+        assert_eq!(&round_trip(&caa)[..7], b"\0\x05issue");
+        assert_eq!(caa.to_string(), "0 issue \"ca.example.net; account=230123\"");
+
+        let caa: Caa = "128 tbs Unknown".parse().unwrap();
+        assert!(caa.is_critical());
+        assert_eq!(caa.to_string(), "128 tbs \"Unknown\"");
+
+        let caa: Caa = "0 issuewild \";\"".parse().unwrap();
+        assert_eq!(caa.value, b";");
+
+        assert_eq!("0 issue".parse::<Caa>(), Err(ParseError::MissingField));
+        assert_eq!("0 is-sue x".parse::<Caa>(), Err(ParseError::InvalidTag));
+        assert_eq!("0 abcdefghijklmnop x".parse::<Caa>(), Err(ParseError::InvalidTag));
+
+        assert!(Caa::decode(&mut MsgReader::new(b"\0\0")).is_err());
+    }
+}
@@ -8,6 +8,7 @@ use std::net::{IpAddr, SocketAddr};
 
 use config::DnsConfig;
 use hostname::get_hostname;
+use sort::SortlistEntry;
 
 /// port for DNS communication
 const DNS_PORT: u16 = 53;
@@ -15,6 +16,9 @@ const DNS_PORT: u16 = 53;
 /// Maximum number of name servers loaded from `resolv.conf`
 pub const MAX_NAME_SERVERS: usize = 3;
 
+/// Maximum number of `sortlist` entries loaded from `resolv.conf`
+pub const MAX_SORTLIST: usize = 10;
+
 /// Default value of `"options attempts:n"`
 pub const DEFAULT_ATTEMPTS: u32 = 2;
 
@@ -47,6 +51,11 @@ fn default_config() -> DnsConfig {
 
         rotate: false,
         use_inet6: false,
+        single_request: false,
+        edns0: false,
+
+        sort_addresses: false,
+        sortlist: Vec::new(),
     }
 }
 
@@ -96,6 +105,10 @@ fn parse<R: BufRead>(r: R) -> io::Result<DnsConfig> {
             "search" => {
                 cfg.search = words.map(|s| s.to_owned()).collect();
             }
+            "sortlist" => {
+                cfg.sortlist = words.filter_map(SortlistEntry::parse)
+                    .take(MAX_SORTLIST).collect();
+            }
             "options" => {
                 for opt in words {
                     let (opt, value) = match opt.find(':') {
@@ -122,6 +135,9 @@ fn parse<R: BufRead>(r: R) -> io::Result<DnsConfig> {
                         }
                         "rotate" => cfg.rotate = true,
                         "inet6" => cfg.use_inet6 = true,
+                        "edns0" => cfg.edns0 = true,
+                        "single-request" | "single-request-reopen" =>
+                            cfg.single_request = true,
                         _ => ()
                     }
                 }
@@ -154,7 +170,8 @@ mod test {
     const TEST_CONFIG: &'static str = "\
         nameserver 127.0.0.1
         search foo.com bar.com
-        options timeout:99 ndots:2 rotate";
+        sortlist 130.155.160.0/255.255.240.0 130.155.0.0
+        options timeout:99 ndots:2 rotate single-request edns0";
 
     #[test]
     fn test_parse() {
@@ -163,8 +180,11 @@ mod test {
 
         assert_eq!(cfg.name_servers, ["127.0.0.1:53".parse().unwrap()]);
         assert_eq!(cfg.search, ["foo.com", "bar.com"]);
+        assert_eq!(cfg.sortlist.len(), 2);
         assert_eq!(cfg.timeout.as_secs(), MAX_TIMEOUT);
         assert_eq!(cfg.n_dots, 2);
         assert_eq!(cfg.rotate, true);
+        assert_eq!(cfg.single_request, true);
+        assert_eq!(cfg.edns0, true);
     }
 }
@@ -1,24 +1,49 @@
 //! High-level resolver operations
 
-use std::cell::Cell;
+use std::error::Error as StdError;
+use std::fmt;
 use std::io;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::vec::IntoIter;
 
 use address::address_name;
+use cache::{CachedAnswer, DnsCache};
 use config::DnsConfig;
-use message::{Message, Qr, Question, MESSAGE_LIMIT};
-use record::{A, AAAA, Class, Ptr, Record, RecordType};
+#[cfg(feature = "dnssec")] use denial::Denial;
+#[cfg(feature = "dnssec")] use dnssec::{Validation, Validator};
+use message::{DecodeError, DnsError, Edns, EncodeError, Message, Question, RCode,
+    Resource, MESSAGE_LIMIT};
+use name::Name;
+use record::{A, AAAA, Caa, CName, Class, DName, Https, Ptr, Record, RecordType};
+use record::{SvcParam, SvcParamKey};
 use socket::{DnsSocket, Error};
+use sort::{apply_sortlist, sort_addresses, sort_with_config};
+use transport::{PairResult, TcpTransport, Transport, UdpTransport};
+
+/// Maximum number of `CNAME` or `DNAME` aliases followed in a single lookup
+pub const MAX_ALIAS_CHAIN: usize = 8;
+
+/// Canonical name and addresses found by an `A` or `AAAA` lookup
+pub(crate) type HostResult<T> = Result<(String, Vec<T>), ResolveError>;
+
+/// Results of the `A` and `AAAA` lookups of a host, if performed
+pub(crate) type HostResults = (Option<HostResult<Ipv4Addr>>, Option<HostResult<Ipv6Addr>>);
 
 /// Performs resolution operations
+///
+/// A `DnsResolver` may be shared between threads, allowing several queries
+/// to be in progress at once.
 pub struct DnsResolver {
-    sock: DnsSocket,
+    udp: Box<dyn Transport>,
+    tcp: Box<dyn Transport>,
     config: DnsConfig,
     /// Index of `config.name_servers` to use in next DNS request;
     /// ignored if `config.rotate` is `false`.
-    next_ns: Cell<usize>,
+    next_ns: AtomicUsize,
+    cache: Option<DnsCache>,
+    #[cfg(feature = "dnssec")]
+    validator: Option<Validator>,
 }
 
 impl DnsResolver {
@@ -37,209 +62,643 @@ impl DnsResolver {
     }
 
     fn with_sock(sock: DnsSocket, config: DnsConfig) -> io::Result<DnsResolver> {
-        Ok(DnsResolver{
-            sock: sock,
+        Ok(DnsResolver::with_transport(config,
+            Box::new(UdpTransport::new(sock)), Box::new(TcpTransport::new())))
+    }
+
+    /// Constructs a `DnsResolver` using the given configuration and transports.
+    ///
+    /// Queries are sent using `udp`; if a truncated response is received,
+    /// the query is repeated using `tcp`.
+    pub fn with_transport(config: DnsConfig,
+            udp: Box<dyn Transport>, tcp: Box<dyn Transport>) -> DnsResolver {
+        DnsResolver{
+            udp: udp,
+            tcp: tcp,
             config: config,
-            next_ns: Cell::new(0),
-        })
+            next_ns: AtomicUsize::new(0),
+            cache: None,
+            #[cfg(feature = "dnssec")]
+            validator: None,
+        }
     }
 
-    /// Resolves an IPv4 or IPv6 address to a hostname.
-    pub fn resolve_addr(&self, addr: &IpAddr) -> io::Result<String> {
-        convert_error("failed to resolve address", || {
-            let mut out_msg = self.basic_message();
+    /// Sets the cache used to store query results.
+    /// If `None` is given, caching is disabled.
+    ///
+    /// Caching is disabled by default.
+    pub fn set_cache(&mut self, cache: Option<DnsCache>) {
+        self.cache = cache;
+    }
 
-            out_msg.question.push(Question::new(
-                address_name(addr), RecordType::Ptr, Class::Internet));
+    /// Returns the cache used to store query results, if caching is enabled.
+    pub fn cache(&self) -> Option<&DnsCache> {
+        self.cache.as_ref()
+    }
 
-            let mut buf = [0; MESSAGE_LIMIT];
-            let msg = try!(self.send_message(&out_msg, &mut buf));
-
-            for rr in msg.answer.into_iter() {
-                if rr.r_type == RecordType::Ptr {
-                    let ptr = try!(rr.read_rdata::<Ptr>());
-                    let mut name = ptr.name;
-                    if name.ends_with('.') {
-                        name.pop();
-                    }
-                    return Ok(name);
+    /// Sets the validator used to check the DNSSEC signatures of answers.
+    /// If `None` is given, validation is disabled.
+    ///
+    /// While validation is enabled, queries are sent with the `DO` bit set
+    /// and are not answered from the cache; the validator instead caches
+    /// the zone keys it has validated. Lookups whose answers are found
+    /// to be `Bogus` fail with `ResolveError::Bogus`. Lookups of names
+    /// securely proven not to exist fail with `ResolveError::Denied`,
+    /// rather than `ResolveError::NoSuchName`.
+    ///
+    /// Validation is disabled by default.
+    #[cfg(feature = "dnssec")]
+    pub fn set_validator(&mut self, validator: Option<Validator>) {
+        self.validator = validator;
+    }
+
+    /// Returns the validator used to check answers, if validation is enabled.
+    #[cfg(feature = "dnssec")]
+    pub fn validator(&self) -> Option<&Validator> {
+        self.validator.as_ref()
+    }
+
+    /// Resolves an IPv4 or IPv6 address to a hostname.
+    pub fn resolve_addr(&self, addr: &IpAddr) -> Result<String, ResolveError> {
+        let (_, answer) = try!(self.lookup(&address_name(addr), RecordType::Ptr));
+
+        match answer.first() {
+            Some(rr) => {
+                let ptr = try!(rr.read_rdata::<Ptr>());
+                let mut name = ptr.name;
+                if name.ends_with('.') {
+                    name.pop();
                 }
+                Ok(name)
             }
+            None => Err(ResolveError::NoData)
+        }
+    }
+
+    /// Resolves a hostname to a series of IPv4 or IPv6 addresses.
+    ///
+    /// `CNAME` and `DNAME` aliases are followed; the canonical name of the
+    /// host is available from the returned `ResolveHost`.
+    ///
+    /// `A` and `AAAA` queries for each name are sent in parallel, unless
+    /// `single_request` or `use_inet6` is set in the resolver configuration.
+    ///
+    /// Addresses are ordered according to the `sort_addresses` and
+    /// `sortlist` fields of the resolver configuration.
+    pub fn resolve_host(&self, host: &str) -> Result<ResolveHost, ResolveError> {
+        query_names(host, &self.config, |name| {
+            info!("attempting lookup of name \"{}\"", name);
+
+            let (v4, v6) = self.resolve_host_both(&name);
 
-            Err(Error::IoError(io::Error::new(io::ErrorKind::Other,
-                "failed to resolve address: name not found")))
+            host_addrs(v4, v6, &self.config)
         })
     }
 
-    /// Resolves a hostname to a series of IPv4 or IPv6 addresses.
-    pub fn resolve_host(&self, host: &str) -> io::Result<ResolveHost> {
-        convert_error("failed to resolve host", || {
-            query_names(host, &self.config, |name| {
-                let mut err;
-                let mut res = Vec::new();
+    /// Requests a type of record from the DNS server and returns the results.
+    ///
+    /// `CNAME` and `DNAME` aliases are followed, unless `CNAME` or `DNAME`
+    /// records are themselves requested.
+    ///
+    /// If the name exists, but has no records of the requested type,
+    /// `Err(ResolveError::NoData)` is returned.
+    pub fn resolve_record<Rec: Record>(&self, name: &str) -> Result<Vec<Rec>, ResolveError> {
+        self.resolve_record_canonical(name).map(|(_, rec)| rec)
+    }
 
-                info!("attempting lookup of name \"{}\"", name);
+    /// Requests a type of record from the DNS server and returns the results,
+    /// along with the canonical name found by following any aliases.
+    pub fn resolve_record_canonical<Rec: Record>(&self, name: &str)
+            -> Result<(String, Vec<Rec>), ResolveError> {
+        let r_ty = Rec::record_type();
+        let (canonical, answer) = try!(self.lookup(name, r_ty));
 
-                if self.config.use_inet6 {
-                    err = self.resolve_host_v6(&name,
-                        |ip| res.push(IpAddr::V6(ip))).err();
+        if answer.is_empty() {
+            return Err(ResolveError::NoData);
+        }
 
-                    if res.is_empty() {
-                        err = err.or(self.resolve_host_v4(&name,
-                            |ip| res.push(IpAddr::V6(ip.to_ipv6_mapped()))).err());
-                    }
-                } else {
-                    err = self.resolve_host_v4(&name, |ip| res.push(IpAddr::V4(ip))).err();
-                    err = err.or(self.resolve_host_v6(&name,
-                        |ip| res.push(IpAddr::V6(ip))).err());
-                }
+        let mut rec = Vec::new();
 
-                if !res.is_empty() {
-                    return Ok(ResolveHost(res.into_iter()));
+        for rr in answer {
+            rec.push(try!(rr.read_rdata::<Rec>()));
+        }
+
+        Ok((canonical, rec))
+    }
+
+    /// Finds the `CAA` records relevant to a domain, as described in
+    /// RFC 8659, section 3.
+    ///
+    /// The domain is queried first, then each of its parents in turn,
+    /// stopping at the first name with `CAA` records; aliases are followed.
+    /// The top-level domain is the last name queried.
+    ///
+    /// If no `CAA` records are found, `Ok(None)` is returned, and any
+    /// certification authority may issue certificates for the domain.
+    pub fn resolve_caa(&self, domain: &str) -> Result<Option<CaaPolicy>, ResolveError> {
+        let mut name = match Name::from_idna(domain) {
+            Ok(name) => name,
+            Err(_) => return Err(ResolveError::InvalidQuery(EncodeError::InvalidName))
+        };
+
+        while !name.is_root() {
+            let owner = name.to_string();
+
+            match self.resolve_record::<Caa>(&owner) {
+                Ok(rec) => return Ok(Some(CaaPolicy::new(owner, rec))),
+                Err(ResolveError::NoSuchName) | Err(ResolveError::NoData) => (),
+                #[cfg(feature = "dnssec")]
+                Err(ResolveError::Denied(_)) => (),
+                Err(e) => return Err(e)
+            }
+
+            name = name.parent().unwrap();
+        }
+
+        Ok(None)
+    }
+
+    /// Resolves the `HTTPS` records of a host, following AliasMode records
+    /// as described in RFC 9460.
+    ///
+    /// ServiceMode records are returned in order of priority. A target of
+    /// `.` is replaced with the name owning the record. Records whose
+    /// mandatory parameters are not all recognized are discarded.
+    ///
+    /// If no usable records are found, or an AliasMode record indicates
+    /// that the service is not available, `Err(ResolveError::NoData)`
+    /// is returned.
+    pub fn resolve_https(&self, host: &str) -> Result<Vec<Https>, ResolveError> {
+        let mut chain = vec![host.to_owned()];
+
+        loop {
+            let (owner, rec) = try!(self.resolve_record_canonical::<Https>(
+                chain.last().unwrap()));
+
+            // ServiceMode records are ignored if any AliasMode record is present
+            if let Some(alias) = rec.iter().find(|r| r.is_alias()) {
+                if alias.target == "." {
+                    return Err(ResolveError::NoData);
                 }
 
-                if let Some(e) = err {
-                    Err(e)
-                } else {
-                    Err(Error::IoError(io::Error::new(io::ErrorKind::Other,
-                        "failed to resolve host: name not found")))
+                info!("following HTTPS alias from \"{}\" to \"{}\"", owner, alias.target);
+
+                if chain.len() > MAX_ALIAS_CHAIN ||
+                        in_chain(&chain, &try!(lookup_name(&alias.target))) {
+                    return Err(ResolveError::AliasLoop);
                 }
-            })
-        })
+
+                chain.push(alias.target.clone());
+                continue;
+            }
+
+            let mut rec = rec.into_iter()
+                .filter(|r| match r.param(SvcParamKey::Mandatory) {
+                    Some(&SvcParam::Mandatory(ref keys)) => keys.iter()
+                        .all(|key| !matches!(*key, SvcParamKey::Other(_))),
+                    _ => true
+                })
+                .map(|mut r| {
+                    if r.target == "." {
+                        r.target = format!("{}.", owner.trim_end_matches('.'));
+                    }
+                    r
+                })
+                .collect::<Vec<_>>();
+
+            if rec.is_empty() {
+                return Err(ResolveError::NoData);
+            }
+
+            rec.sort_by_key(|r| r.priority);
+            return Ok(rec);
+        }
     }
 
-    /// Requests a type of record from the DNS server and returns the results.
-    pub fn resolve_record<Rec: Record>(&self, name: &str) -> io::Result<Vec<Rec>> {
-        convert_error("failed to resolve record", || {
-            let r_ty = Rec::record_type();
-            let mut msg = self.basic_message();
+    /// Requests a type of record from the DNS server and returns the results,
+    /// along with the security status of the answers.
+    ///
+    /// Aliases are followed as in `resolve_record`; the status is that of
+    /// the least secure answer in the chain. If validation is disabled,
+    /// the status is `Indeterminate`.
+    ///
+    /// If no records exist and this is securely proven,
+    /// `Err(ResolveError::Denied(_))` is returned.
+    #[cfg(feature = "dnssec")]
+    pub fn resolve_record_validated<Rec: Record>(&self, name: &str)
+            -> Result<(Vec<Rec>, Validation), ResolveError> {
+        let (_, answer, status) = try!(self.lookup_validated(name, Rec::record_type()));
 
-            msg.question.push(Question::new(name.to_owned(), r_ty, Class::Internet));
+        if answer.is_empty() {
+            return Err(if status == Validation::Secure {
+                ResolveError::Denied(Denial::NoData)
+            } else {
+                ResolveError::NoData
+            });
+        }
 
-            let mut buf = [0; MESSAGE_LIMIT];
-            let reply = try!(self.send_message(&msg, &mut buf));
+        let mut rec = Vec::new();
+
+        for rr in answer {
+            rec.push(try!(rr.read_rdata::<Rec>()));
+        }
+
+        Ok((rec, status))
+    }
 
-            let mut rec = Vec::new();
+    /// Performs `A` and `AAAA` lookups for a single name.
+    ///
+    /// Unless `single_request` or `use_inet6` is configured, both queries are
+    /// sent before either response is awaited. Otherwise, the preferred family
+    /// is queried first and, if `use_inet6` is set, `A` records are queried
+    /// only if no `AAAA` records are found. A lookup which is not performed
+    /// yields `None`.
+    ///
+    /// While DNSSEC validation is enabled, the two lookups are performed
+    /// in turn.
+    fn resolve_host_both(&self, host: &str) -> HostResults {
+        #[cfg(feature = "dnssec")]
+        let validating = self.validator.is_some();
+        #[cfg(not(feature = "dnssec"))]
+        let validating = false;
 
-            for rr in reply.answer.into_iter() {
-                if rr.r_type == r_ty {
-                    rec.push(try!(rr.read_rdata::<Rec>()));
-                }
+        if !self.config.single_request && !self.config.use_inet6 && !validating {
+            let (v4, v6) = self.query_pair(host);
+            let (mut v4, mut v6) = (Some(v4), Some(v6));
+
+            // Aliases whose targets are not in the first responses
+            // are followed by separate queries.
+            let v4 = self.follow_aliases(host, RecordType::A, |name| match v4.take() {
+                Some(res) => Ok(try!(res)),
+                None => Ok(try!(self.query(name, RecordType::A)))
+            });
+            let v6 = self.follow_aliases(host, RecordType::AAAA, |name| match v6.take() {
+                Some(res) => Ok(try!(res)),
+                None => Ok(try!(self.query(name, RecordType::AAAA)))
+            });
+
+            return (Some(v4_addrs(v4)), Some(v6_addrs(v6)));
+        }
+
+        if self.config.use_inet6 {
+            let v6 = self.resolve_host_v6(host);
+
+            match v6 {
+                Ok((_, ref addrs)) if !addrs.is_empty() => (None, Some(v6)),
+                _ => (Some(self.resolve_host_v4(host)), Some(v6))
             }
+        } else {
+            (Some(self.resolve_host_v4(host)), Some(self.resolve_host_v6(host)))
+        }
+    }
 
-            Ok(rec)
+    /// Resolves a hostname to addresses of a single family, given as
+    /// `RecordType::A` or `RecordType::AAAA`.
+    ///
+    /// Search domains are tried in order until a name is found to exist;
+    /// if that name has no records of the given type, an empty list is
+    /// returned.
+    ///
+    /// As RFC 8305, section 4 requires, addresses are always ordered
+    /// according to RFC 6724, whether or not `sort_addresses` is set;
+    /// they are then ordered by `sortlist`, if configured.
+    pub(crate) fn resolve_family(&self, host: &str, r_type: RecordType)
+            -> Result<Vec<IpAddr>, ResolveError> {
+        query_names(host, &self.config, |name| {
+            let mut res = match r_type {
+                RecordType::AAAA => try!(self.resolve_host_v6(&name)).1
+                    .into_iter().map(IpAddr::V6).collect::<Vec<_>>(),
+                _ => try!(self.resolve_host_v4(&name)).1
+                    .into_iter().map(IpAddr::V4).collect::<Vec<_>>(),
+            };
+
+            sort_addresses(&mut res);
+            apply_sortlist(&mut res, &self.config.sortlist);
+            Ok(res)
         })
     }
 
-    fn resolve_host_v4<F>(&self, host: &str, mut f: F) -> Result<(), Error>
-            where F: FnMut(Ipv4Addr) {
-        let mut out_msg = self.basic_message();
-
-        out_msg.question.push(Question::new(
-            host.to_owned(), RecordType::A, Class::Internet));
+    fn resolve_host_v4(&self, host: &str) -> HostResult<Ipv4Addr> {
+        v4_addrs(self.lookup(host, RecordType::A))
+    }
 
-        let mut buf = [0; MESSAGE_LIMIT];
-        let msg = try!(self.send_message(&out_msg, &mut buf));
+    fn resolve_host_v6(&self, host: &str) -> HostResult<Ipv6Addr> {
+        v6_addrs(self.lookup(host, RecordType::AAAA))
+    }
 
-        for rr in msg.answer.into_iter() {
-            if rr.r_type == RecordType::A {
-                let a = try!(rr.read_rdata::<A>());
-                f(a.address);
+    /// Queries the given name and record type, following any `CNAME` or
+    /// `DNAME` aliases. Returns the canonical name, without a trailing `.`,
+    /// and the records of the requested type owned by that name.
+    ///
+    /// Aliases are followed first within each response. If the target of an
+    /// alias is not answered in the same response, it is queried separately.
+    fn lookup(&self, name: &str, r_type: RecordType)
+            -> Result<(String, Vec<Resource<'static>>), ResolveError> {
+        #[cfg(feature = "dnssec")]
+        {
+            if self.validator.is_some() {
+                return self.lookup_validated(name, r_type)
+                    .map(|(canonical, answer, _)| (canonical, answer));
             }
         }
 
-        Ok(())
+        self.follow_aliases(name, r_type, |name| Ok(try!(self.query(name, r_type))))
     }
 
-    fn resolve_host_v6<F>(&self, host: &str, mut f: F) -> Result<(), Error>
-            where F: FnMut(Ipv6Addr) {
-        let mut out_msg = self.basic_message();
+    /// Performs a lookup as `lookup` does, validating each response,
+    /// including any proof that the name or type does not exist.
+    /// Returns the security status of the least secure response.
+    #[cfg(feature = "dnssec")]
+    fn lookup_validated(&self, name: &str, r_type: RecordType)
+            -> Result<(String, Vec<Resource<'static>>, Validation), ResolveError> {
+        let validator = match self.validator {
+            Some(ref v) => v,
+            None => {
+                let (canonical, answer) = try!(self.lookup(name, r_type));
+                return Ok((canonical, answer, Validation::Indeterminate));
+            }
+        };
 
-        out_msg.question.push(Question::new(
-            host.to_owned(), RecordType::AAAA, Class::Internet));
+        let mut status = Validation::Secure;
 
-        let mut buf = [0; MESSAGE_LIMIT];
-        let msg = try!(self.send_message(&out_msg, &mut buf));
+        let (canonical, answer) = try!(self.follow_aliases(name, r_type, |name| {
+            let msg = try!(self.query_dnssec(name, r_type));
+            let name_error = msg.header.rcode == RCode::NameError;
+
+            if !name_error {
+                try!(msg.get_error().map_err(Error::DnsError));
+            }
+
+            let (res, denial) = validator.validate_response(&msg,
+                |name, r_type| self.query_dnssec(name, r_type));
+
+            if res == Validation::Bogus {
+                info!("answer for \"{}\" failed validation", name);
+                return Err(ResolveError::Bogus);
+            }
+
+            status = status.and(res);
+
+            if name_error {
+                return Err(if res == Validation::Secure {
+                    ResolveError::Denied(denial.unwrap_or(Denial::Unproven))
+                } else {
+                    ResolveError::NoSuchName
+                });
+            }
 
-        for rr in msg.answer.into_iter() {
-            if rr.r_type == RecordType::AAAA {
-                let aaaa = try!(rr.read_rdata::<AAAA>());
-                f(aaaa.address);
+            Ok(msg.answer)
+        }));
+
+        Ok((canonical, answer, status))
+    }
+
+    /// Queries the given name and record type, following any `CNAME` or
+    /// `DNAME` aliases, using `query` to fetch the answer records for a name.
+    fn follow_aliases<F>(&self, name: &str, r_type: RecordType, mut query: F)
+            -> Result<(String, Vec<Resource<'static>>), ResolveError>
+            where F: FnMut(&str) -> Result<Vec<Resource<'static>>, ResolveError> {
+        let mut chain = vec![name.to_owned()];
+
+        loop {
+            let answer = try!(query(chain.last().unwrap()));
+
+            if let Some(res) = try!(follow_answer(&answer, r_type, &mut chain)) {
+                return Ok(res);
             }
         }
+    }
+
+    /// Queries the given name and record type and returns the records in the
+    /// answer section of the response. If caching is enabled, results are
+    /// taken from the cache when available and stored there otherwise.
+    fn query(&self, name: &str, r_type: RecordType)
+            -> Result<Vec<Resource<'static>>, Error> {
+        if let Some(res) = self.cached(name, r_type) {
+            return res;
+        }
+
+        let out_msg = self.query_message(name, r_type);
+
+        let mut buf = [0; MESSAGE_LIMIT];
+        let res = self.exchange(&out_msg, &mut buf);
+
+        self.answer(name, r_type, res)
+    }
+
+    /// Queries the `A` and `AAAA` records of the given name, as `query` does.
+    /// Unless either answer is cached, both queries are sent before either
+    /// response is awaited.
+    fn query_pair(&self, name: &str)
+            -> (Result<Vec<Resource<'static>>, Error>, Result<Vec<Resource<'static>>, Error>) {
+        match (self.cached(name, RecordType::A), self.cached(name, RecordType::AAAA)) {
+            (None, None) => (),
+            (v4, v6) => return (
+                v4.unwrap_or_else(|| self.query(name, RecordType::A)),
+                v6.unwrap_or_else(|| self.query(name, RecordType::AAAA)))
+        }
+
+        let (v4, v6) = self.exchange_pair(&self.query_message(name, RecordType::A),
+            &self.query_message(name, RecordType::AAAA));
+
+        (self.answer(name, RecordType::A, v4), self.answer(name, RecordType::AAAA, v6))
+    }
+
+    /// Returns the cached answer to a query, if caching is enabled and the
+    /// answer is held.
+    fn cached(&self, name: &str, r_type: RecordType)
+            -> Option<Result<Vec<Resource<'static>>, Error>> {
+        match self.cache.as_ref().and_then(|c| c.get(name, r_type, Class::Internet)) {
+            Some(CachedAnswer::Records(rrs)) => Some(Ok(rrs)),
+            Some(CachedAnswer::NoData) => Some(Ok(Vec::new())),
+            Some(CachedAnswer::NoSuchName) =>
+                Some(Err(Error::DnsError(DnsError(RCode::NameError)))),
+            None => None
+        }
+    }
+
+    /// Returns the answer records of a response to a query, storing the
+    /// response in the cache if caching is enabled.
+    fn answer(&self, name: &str, r_type: RecordType, res: Result<Message, Error>)
+            -> Result<Vec<Resource<'static>>, Error> {
+        let msg = try!(res);
+
+        if let Some(ref cache) = self.cache {
+            cache.insert_response(name, r_type, Class::Internet, &msg);
+        }
+
+        try!(msg.get_error());
+
+        Ok(msg.answer.into_iter().map(Resource::into_owned).collect())
+    }
+
+    /// Returns a recursive query for the given name and record type.
+    fn query_message<'a>(&'a self, name: &str, r_type: RecordType) -> Message<'a> {
+        let mut out_msg = self.basic_message();
+        out_msg.question.push(Question::new(name.to_owned(), r_type, Class::Internet));
+        out_msg
+    }
+
+    /// Queries the given name and record type with the `DO` bit set and
+    /// returns the response, regardless of its response code.
+    #[cfg(feature = "dnssec")]
+    fn query_dnssec(&self, name: &str, r_type: RecordType)
+            -> Result<Message<'static>, ResolveError> {
+        let mut out_msg = self.basic_message();
+
+        let mut edns = out_msg.edns.take().unwrap_or_else(Edns::new);
+        edns.dnssec_ok = true;
+        out_msg.edns = Some(edns);
+
+        out_msg.question.push(Question::new(name.to_owned(), r_type, Class::Internet));
+
+        let mut buf = [0; MESSAGE_LIMIT];
+        let msg = try!(self.exchange(&out_msg, &mut buf));
 
-        Ok(())
+        Ok(msg.into_owned())
     }
 
     fn basic_message(&self) -> Message {
         let mut msg = Message::new();
 
         msg.header.recursion_desired = true;
+
+        if self.config.edns0 {
+            msg.edns = Some(Edns::new());
+        }
+
         msg
     }
 
     /// Sends a message to the DNS server and attempts to read a response.
+    ///
+    /// If the response received over UDP is truncated, the query is repeated
+    /// over TCP to the same name server.
     pub fn send_message<'buf>(&self, out_msg: &Message, buf: &'buf mut [u8])
             -> Result<Message<'buf>, Error> {
-        let mut last_err = None;
+        let msg = try!(self.exchange(out_msg, buf));
+        try!(msg.get_error());
+        Ok(msg)
+    }
+
+    /// Sends a message to the DNS server and returns the response,
+    /// regardless of its response code.
+    ///
+    /// If the message contains EDNS data and the name server rejects it,
+    /// the message is sent again without EDNS data.
+    fn exchange<'buf>(&self, out_msg: &Message, buf: &'buf mut [u8])
+            -> Result<Message<'buf>, Error> {
+        self.exchange_from(out_msg, 0, buf)
+    }
 
+    /// Exchanges a message, as `exchange` does, beginning with the attempt
+    /// numbered `first`.
+    fn exchange_from<'buf>(&self, out_msg: &Message, first: u32, buf: &'buf mut [u8])
+            -> Result<Message<'buf>, Error> {
         // FIXME(rust-lang/rust#21906):
         // Workaround for mutable borrow interfering with itself.
-        // The drop probably isn't necessary, but it makes me feel better.
-        let buf_ptr = buf as *mut _;
-        drop(buf);
+        let buf_ptr = buf as *mut [u8];
 
-        'retry: for retries in 0..self.config.attempts {
-            let ns_addr = if self.config.rotate {
-                self.next_nameserver()
-            } else {
-                let n = self.config.name_servers.len();
-                self.config.name_servers[retries as usize % n]
-            };
+        let msg = try!(self.exchange_attempts(out_msg, first, unsafe { &mut *buf_ptr }));
 
-            let mut timeout = self.config.timeout;
+        if out_msg.edns.is_some() && rejects_edns(&msg) {
+            info!("name server rejected EDNS query; retrying without EDNS");
 
-            info!("resolver sending message to {}", ns_addr);
+            let mut out_msg = out_msg.clone();
+            out_msg.edns = None;
 
-            try!(self.sock.send_message(out_msg, &ns_addr));
+            return self.exchange_attempts(&out_msg, 0, unsafe { &mut *buf_ptr });
+        }
 
-            loop {
-                try!(self.sock.get().set_read_timeout(Some(timeout)));
+        Ok(msg)
+    }
 
-                // The other part of the aforementioned workaround.
-                let buf = unsafe { &mut *buf_ptr };
+    /// Sends two messages, as `exchange` does, awaiting both responses
+    /// together on the first attempt.
+    ///
+    /// An exchange which times out is continued alone with the remaining
+    /// attempts. A truncated response is repeated over TCP, and a query whose
+    /// EDNS data is rejected is repeated without it, as by `exchange`.
+    fn exchange_pair(&self, first: &Message, second: &Message) -> PairResult {
+        let ns_addr = self.attempt_nameserver(0);
 
-                let start = Instant::now();
+        info!("resolver sending messages to {}", ns_addr);
 
-                match self.sock.recv_message(&ns_addr, buf) {
-                    Ok(None) => {
-                        let passed = start.elapsed();
+        let (a, b) = self.udp.exchange_pair(first, second, &ns_addr, self.config.timeout);
 
-                        // Maintain the right total timeout if we're interrupted
-                        // by irrelevant messages.
-                        if timeout < passed {
-                            timeout = Duration::from_secs(0);
-                        } else {
-                            timeout = timeout - passed;
-                        }
-                    }
-                    Ok(Some(msg)) => {
-                        // Ignore irrelevant messages
-                        if msg.header.id == out_msg.header.id &&
-                                msg.header.qr == Qr::Response {
-                            try!(msg.get_error());
-                            return Ok(msg);
-                        }
-                    }
-                    Err(e) => {
-                        // Retry on timeout
-                        if e.is_timeout() {
-                            last_err = Some(e);
-                            continue 'retry;
-                        }
-                        // Immediately bail for other errors
-                        return Err(e);
+        (self.complete_exchange(first, &ns_addr, a),
+            self.complete_exchange(second, &ns_addr, b))
+    }
+
+    /// Completes the first attempt of an exchange begun by `exchange_pair`.
+    fn complete_exchange(&self, out_msg: &Message, ns_addr: &SocketAddr,
+            res: Result<Message<'static>, Error>) -> Result<Message<'static>, Error> {
+        let mut buf = [0; MESSAGE_LIMIT];
+
+        let msg = match res {
+            Ok(ref msg) if msg.header.truncated => {
+                info!("response from {} truncated; retrying over TCP", ns_addr);
+
+                try!(self.tcp.exchange(out_msg, ns_addr, self.config.timeout, &mut buf))
+                    .into_owned()
+            }
+            Ok(msg) => msg,
+            Err(ref e) if e.is_timeout() && self.config.attempts > 1 =>
+                return self.exchange_from(out_msg, 1, &mut buf).map(Message::into_owned),
+            Err(e) => return Err(e)
+        };
+
+        if out_msg.edns.is_some() && rejects_edns(&msg) {
+            info!("name server rejected EDNS query; retrying without EDNS");
+
+            let mut out_msg = out_msg.clone();
+            out_msg.edns = None;
+
+            return self.exchange_attempts(&out_msg, 0, &mut buf).map(Message::into_owned);
+        }
+
+        Ok(msg)
+    }
+
+    /// Sends a message to each name server in turn, for the configured
+    /// number of attempts, beginning with the attempt numbered `first`,
+    /// until a response is received.
+    fn exchange_attempts<'buf>(&self, out_msg: &Message, first: u32, buf: &'buf mut [u8])
+            -> Result<Message<'buf>, Error> {
+        let mut last_err = None;
+
+        // FIXME(rust-lang/rust#21906):
+        // Workaround for mutable borrow interfering with itself.
+        let buf_ptr = buf as *mut [u8];
+
+        for retries in first..self.config.attempts {
+            let ns_addr = self.attempt_nameserver(retries);
+
+            info!("resolver sending message to {}", ns_addr);
+
+            // The other part of the aforementioned workaround.
+            let buf = unsafe { &mut *buf_ptr };
+
+            let res = match self.udp.exchange(out_msg, &ns_addr, self.config.timeout, buf) {
+                Ok(ref msg) if msg.header.truncated => {
+                    info!("response from {} truncated; retrying over TCP", ns_addr);
+
+                    let buf = unsafe { &mut *buf_ptr };
+                    self.tcp.exchange(out_msg, &ns_addr, self.config.timeout, buf)
+                }
+                res => res
+            };
+
+            match res {
+                Ok(msg) => return Ok(msg),
+                Err(e) => {
+                    // Retry on timeout
+                    if e.is_timeout() {
+                        last_err = Some(e);
+                        continue;
                     }
+                    // Immediately bail for other errors
+                    return Err(e);
                 }
             }
         }
@@ -247,13 +706,364 @@ impl DnsResolver {
         Err(last_err.unwrap())
     }
 
+    /// Returns the address of the name server to which the attempt
+    /// numbered `retries` is sent.
+    fn attempt_nameserver(&self, retries: u32) -> SocketAddr {
+        if self.config.rotate {
+            self.next_nameserver()
+        } else {
+            let n = self.config.name_servers.len();
+            self.config.name_servers[retries as usize % n]
+        }
+    }
+
     fn next_nameserver(&self) -> SocketAddr {
-        let n = self.next_ns.get();
-        self.next_ns.set((n + 1) % self.config.name_servers.len());
-        self.config.name_servers[n]
+        let n = self.next_ns.fetch_add(1, Ordering::Relaxed);
+        self.config.name_servers[n % self.config.name_servers.len()]
+    }
+}
+
+/// Represents an error in a high-level resolution operation.
+#[derive(Debug)]
+pub enum ResolveError {
+    /// The queried name does not exist
+    NoSuchName,
+    /// The queried name exists, but has no records of the requested type
+    NoData,
+    /// Name server responded with an error other than `NoSuchName`
+    ServerFailure(DnsError),
+    /// No response was received from any name server
+    Timeout,
+    /// A response could not be decoded
+    Malformed(DecodeError),
+    /// A query could not be encoded, e.g. because of an invalid name
+    InvalidQuery(EncodeError),
+    /// A chain of `CNAME` or `DNAME` aliases looped or exceeded
+    /// `MAX_ALIAS_CHAIN` in length
+    AliasLoop,
+    /// An answer failed DNSSEC validation
+    #[cfg(feature = "dnssec")]
+    Bogus,
+    /// The queried name or records were securely proven not to exist
+    #[cfg(feature = "dnssec")]
+    Denied(Denial),
+    /// Error generated by network operation
+    IoError(io::Error),
+}
+
+impl ResolveError {
+    /// Returns the response code of the name server error, if the error
+    /// was reported by a name server.
+    pub fn rcode(&self) -> Option<RCode> {
+        match *self {
+            ResolveError::NoSuchName => Some(RCode::NameError),
+            #[cfg(feature = "dnssec")]
+            ResolveError::Denied(Denial::NoSuchName) => Some(RCode::NameError),
+            ResolveError::ServerFailure(DnsError(rcode)) => Some(rcode),
+            _ => None
+        }
+    }
+
+    /// Returns `true` if the error is the result of an operation having timed out.
+    pub fn is_timeout(&self) -> bool {
+        match *self {
+            ResolveError::Timeout => true,
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResolveError::NoSuchName => f.write_str("no such name"),
+            ResolveError::NoData => f.write_str("no records found"),
+            ResolveError::ServerFailure(e) => write!(f, "server responded with error: {}", e),
+            ResolveError::Timeout => f.write_str("timed out waiting for response"),
+            ResolveError::Malformed(e) => write!(f, "error decoding message: {}", e),
+            ResolveError::InvalidQuery(ref e) => write!(f, "error encoding message: {}", e),
+            ResolveError::AliasLoop => f.write_str("alias loop or chain too long"),
+            #[cfg(feature = "dnssec")]
+            ResolveError::Bogus => f.write_str("answer failed DNSSEC validation"),
+            #[cfg(feature = "dnssec")]
+            ResolveError::Denied(Denial::NoSuchName) =>
+                f.write_str("no such name (authenticated)"),
+            #[cfg(feature = "dnssec")]
+            ResolveError::Denied(_) => f.write_str("no records found (authenticated)"),
+            ResolveError::IoError(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl StdError for ResolveError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ResolveError::ServerFailure(ref e) => Some(e),
+            ResolveError::Malformed(ref e) => Some(e),
+            ResolveError::InvalidQuery(ref e) => Some(e),
+            ResolveError::IoError(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<Error> for ResolveError {
+    fn from(err: Error) -> ResolveError {
+        match err {
+            Error::DecodeError(e) => ResolveError::Malformed(e),
+            Error::EncodeError(e) => ResolveError::InvalidQuery(e),
+            Error::DnsError(DnsError(RCode::NameError)) => ResolveError::NoSuchName,
+            Error::DnsError(e) => ResolveError::ServerFailure(e),
+            ref e if e.is_timeout() => ResolveError::Timeout,
+            Error::IoError(e) => ResolveError::IoError(e),
+        }
+    }
+}
+
+impl From<DecodeError> for ResolveError {
+    fn from(err: DecodeError) -> ResolveError {
+        ResolveError::Malformed(err)
+    }
+}
+
+impl From<io::Error> for ResolveError {
+    fn from(err: io::Error) -> ResolveError {
+        ResolveError::from(Error::IoError(err))
+    }
+}
+
+impl From<ResolveError> for io::Error {
+    fn from(err: ResolveError) -> io::Error {
+        match err {
+            ResolveError::IoError(e) => e,
+            ResolveError::Timeout => io::Error::new(io::ErrorKind::TimedOut, err),
+            ResolveError::NoSuchName | ResolveError::NoData =>
+                io::Error::new(io::ErrorKind::NotFound, err),
+            #[cfg(feature = "dnssec")]
+            ResolveError::Denied(_) => io::Error::new(io::ErrorKind::NotFound, err),
+            e => io::Error::new(io::ErrorKind::Other, e),
+        }
+    }
+}
+
+/// Returns whether a response indicates that the name server does not
+/// support EDNS, as described in RFC 6891, section 7.
+pub(crate) fn rejects_edns(msg: &Message) -> bool {
+    msg.edns.is_none() && match msg.header.rcode {
+        RCode::FormatError | RCode::NotImplemented => true,
+        _ => false
+    }
+}
+
+/// Returns the name to which `name`, given also as `owner`, is aliased by
+/// a `CNAME` or `DNAME` record in the given answer records, if any.
+fn alias_target(answer: &[Resource], name: &str, owner: &Name)
+        -> Result<Option<String>, DecodeError> {
+    for rr in answer {
+        if rr.r_type == RecordType::CName && is_owner(rr, owner) {
+            let cname = try!(rr.read_rdata::<CName>());
+            return Ok(Some(cname.name));
+        }
+    }
+
+    for rr in answer {
+        if rr.r_type == RecordType::DName {
+            if let Some(prefix) = strip_suffix(name, &rr.name) {
+                let dname = try!(rr.read_rdata::<DName>());
+                let target = dname.name.trim_end_matches('.');
+
+                if target.is_empty() {
+                    return Ok(Some(format!("{}.", prefix)));
+                }
+                return Ok(Some(format!("{}.{}.", prefix, target)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// If `name` is a proper subdomain of `suffix`, returns the labels of `name`
+/// preceding `suffix`.
+fn strip_suffix<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let name = name.trim_end_matches('.');
+    let suffix = suffix.trim_end_matches('.');
+
+    if suffix.is_empty() {
+        return if name.is_empty() { None } else { Some(name) };
+    }
+
+    if name.len() <= suffix.len() + 1 {
+        return None;
+    }
+
+    let split = name.len() - suffix.len();
+
+    if name.is_char_boundary(split) && name[split..].eq_ignore_ascii_case(suffix) &&
+            name[..split].ends_with('.') {
+        Some(&name[..split - 1])
+    } else {
+        None
     }
 }
 
+/// Follows `CNAME` and `DNAME` aliases within the answer to a query for the
+/// last name in `chain`, appending the target of each alias to `chain`.
+///
+/// Returns the canonical name, without a trailing `.`, and the records of
+/// the requested type owned by that name. If the target of the last alias
+/// is not answered, `None` is returned and that name should be queried.
+pub(crate) fn follow_answer(answer: &[Resource<'static>], r_type: RecordType,
+        chain: &mut Vec<String>)
+        -> Result<Option<(String, Vec<Resource<'static>>)>, ResolveError> {
+    let follow = r_type != RecordType::CName && r_type != RecordType::DName;
+    let mut current = chain.last().expect("empty alias chain").clone();
+    let qname = try!(lookup_name(&current));
+    let mut owner = qname.clone();
+
+    loop {
+        let records = answer.iter()
+            .filter(|rr| rr.r_type == r_type && is_owner(rr, &owner))
+            .cloned().collect::<Vec<_>>();
+
+        if !records.is_empty() || !follow {
+            return Ok(Some((trim_dot(current), records)));
+        }
+
+        let next = match try!(alias_target(answer, &current, &owner)) {
+            Some(next) => next,
+            None => break
+        };
+
+        info!("following alias from \"{}\" to \"{}\"", current, next);
+
+        owner = try!(lookup_name(&next));
+
+        if chain.len() > MAX_ALIAS_CHAIN || in_chain(chain, &owner) {
+            return Err(ResolveError::AliasLoop);
+        }
+
+        chain.push(next.clone());
+        current = next;
+    }
+
+    // No alias was followed; the name has no records of this type.
+    if owner == qname {
+        Ok(Some((trim_dot(current), Vec::new())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Combines the results of `A` and `AAAA` lookups of a host, either of
+/// which may not have been performed, into a sorted list of addresses.
+///
+/// If `use_inet6` is set, IPv4 addresses are returned as IPv4-mapped IPv6
+/// addresses, and only if no IPv6 addresses were found.
+pub(crate) fn host_addrs(v4: Option<HostResult<Ipv4Addr>>, v6: Option<HostResult<Ipv6Addr>>,
+        config: &DnsConfig) -> Result<ResolveHost, ResolveError> {
+    let mut err = None;
+    let mut res = Vec::new();
+    let mut canonical = None;
+
+    if config.use_inet6 {
+        match v6 {
+            Some(Ok((c, addrs))) if !addrs.is_empty() => {
+                canonical = Some(c);
+                res.extend(addrs.into_iter().map(IpAddr::V6));
+            }
+            Some(Err(e)) => err = Some(e),
+            _ => ()
+        }
+
+        if res.is_empty() {
+            match v4 {
+                Some(Ok((c, addrs))) if !addrs.is_empty() => {
+                    canonical = Some(c);
+                    res.extend(addrs.into_iter()
+                        .map(|ip| IpAddr::V6(ip.to_ipv6_mapped())));
+                }
+                Some(Err(e)) => err = err.or(Some(e)),
+                _ => ()
+            }
+        }
+    } else {
+        match v4 {
+            Some(Ok((c, addrs))) if !addrs.is_empty() => {
+                canonical = Some(c);
+                res.extend(addrs.into_iter().map(IpAddr::V4));
+            }
+            Some(Err(e)) => err = Some(e),
+            _ => ()
+        }
+        match v6 {
+            Some(Ok((c, addrs))) if !addrs.is_empty() => {
+                canonical = canonical.or(Some(c));
+                res.extend(addrs.into_iter().map(IpAddr::V6));
+            }
+            Some(Err(e)) => err = err.or(Some(e)),
+            _ => ()
+        }
+    }
+
+    if res.is_empty() {
+        return Err(err.unwrap_or(ResolveError::NoData));
+    }
+
+    sort_with_config(&mut res, config);
+    Ok(ResolveHost::new(res, canonical))
+}
+
+/// Returns the addresses found by an `A` lookup.
+pub(crate) fn v4_addrs(res: Result<(String, Vec<Resource>), ResolveError>)
+        -> HostResult<Ipv4Addr> {
+    let (canonical, answer) = try!(res);
+    let mut addrs = Vec::with_capacity(answer.len());
+
+    for rr in answer {
+        let a = try!(rr.read_rdata::<A>());
+        addrs.push(a.address);
+    }
+
+    Ok((canonical, addrs))
+}
+
+/// Returns the addresses found by an `AAAA` lookup.
+pub(crate) fn v6_addrs(res: Result<(String, Vec<Resource>), ResolveError>)
+        -> HostResult<Ipv6Addr> {
+    let (canonical, answer) = try!(res);
+    let mut addrs = Vec::with_capacity(answer.len());
+
+    for rr in answer {
+        let aaaa = try!(rr.read_rdata::<AAAA>());
+        addrs.push(aaaa.address);
+    }
+
+    Ok((canonical, addrs))
+}
+
+/// Parses a name which is queried or is the target of an alias.
+fn lookup_name(name: &str) -> Result<Name, ResolveError> {
+    Name::from_idna(name).map_err(|_| ResolveError::InvalidQuery(EncodeError::InvalidName))
+}
+
+/// Returns whether the owner name of a record is `name`.
+fn is_owner(rr: &Resource, name: &Name) -> bool {
+    Name::from_idna(&rr.name).ok().as_ref() == Some(name)
+}
+
+/// Returns whether `name` is among the names of an alias chain.
+fn in_chain(chain: &[String], name: &Name) -> bool {
+    chain.iter().any(|n| Name::from_idna(n).ok().as_ref() == Some(name))
+}
+
+fn trim_dot(mut name: String) -> String {
+    if name.len() > 1 && name.ends_with('.') {
+        name.pop();
+    }
+    name
+}
+
 fn bind_addr(name_servers: &[SocketAddr]) -> IpAddr {
     match name_servers.first() {
         Some(&SocketAddr::V6(_)) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
@@ -261,40 +1071,30 @@ fn bind_addr(name_servers: &[SocketAddr]) -> IpAddr {
     }
 }
 
-fn convert_error<T, F>(desc: &str, f: F) -> io::Result<T>
-        where F: FnOnce() -> Result<T, Error> {
-    match f() {
-        Ok(t) => Ok(t),
-        Err(Error::IoError(e)) => Err(e),
-        Err(e) => Err(io::Error::new(
-            io::ErrorKind::Other, format!("{}: {}", desc, e)))
+fn query_names<F, T>(name: &str, config: &DnsConfig, mut f: F) -> Result<T, ResolveError>
+        where F: FnMut(String) -> Result<T, ResolveError> {
+    let mut err = None;
+
+    for name in search_names(name, config) {
+        match f(name) {
+            Ok(t) => return Ok(t),
+            Err(e) => err = Some(e)
+        }
     }
+
+    Err(err.unwrap_or(ResolveError::NoSuchName))
 }
 
-fn query_names<F, T>(name: &str, config: &DnsConfig, mut f: F) -> Result<T, Error>
-        where F: FnMut(String) -> Result<T, Error> {
+/// Returns the series of names to be queried, in order, when resolving `name`.
+pub(crate) fn search_names(name: &str, config: &DnsConfig) -> Vec<String> {
     let use_search = !name.ends_with('.') &&
         name.chars().filter(|&c| c == '.')
             .count() as u32 >= config.n_dots;
 
     if use_search {
-        let mut err = None;
-
-        for name in with_suffixes(name, &config.search) {
-            match f(name) {
-                Ok(t) => return Ok(t),
-                Err(e) => err = Some(e)
-            }
-        }
-
-        if let Some(e) = err {
-            Err(e)
-        } else {
-            Err(Error::IoError(io::Error::new(io::ErrorKind::Other,
-                "failed to resolve host: name not found")))
-        }
+        with_suffixes(name, &config.search)
     } else {
-        f(name.to_owned())
+        vec![name.to_owned()]
     }
 }
 
@@ -306,7 +1106,7 @@ fn with_suffixes(host: &str, suffixes: &[String]) -> Vec<String> {
 }
 
 /// Resolves an IPv4 or IPv6 address to a hostname.
-pub fn resolve_addr(addr: &IpAddr) -> io::Result<String> {
+pub fn resolve_addr(addr: &IpAddr) -> Result<String, ResolveError> {
     let r = try!(DnsResolver::new(try!(DnsConfig::load_default())));
     r.resolve_addr(addr)
 }
@@ -326,18 +1126,679 @@ pub fn resolve_addr(addr: &IpAddr) -> io::Result<String> {
 /// # Ok(())
 /// # }
 /// ```
-pub fn resolve_host(host: &str) -> io::Result<ResolveHost> {
+pub fn resolve_host(host: &str) -> Result<ResolveHost, ResolveError> {
     let r = try!(DnsResolver::new(try!(DnsConfig::load_default())));
     r.resolve_host(host)
 }
 
+/// Certificate issuance policy of a domain, from the `CAA` records
+/// found by `DnsResolver::resolve_caa`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaaPolicy {
+    /// Name owning the records; the domain itself or one of its parents
+    pub name: String,
+    /// `issue` properties, naming authorities which may issue certificates
+    pub issue: Vec<Caa>,
+    /// `issuewild` properties, naming authorities which may issue
+    /// wildcard certificates
+    pub issue_wild: Vec<Caa>,
+    /// `iodef` properties, giving where to report invalid requests
+    pub iodef: Vec<Caa>,
+    /// Whether any property of an unrecognized tag has the issuer critical
+    /// flag set; if so, no certificates may be issued
+    pub unknown_critical: bool,
+}
+
+impl CaaPolicy {
+    fn new(name: String, rec: Vec<Caa>) -> CaaPolicy {
+        let mut policy = CaaPolicy{
+            name: name,
+            issue: Vec::new(),
+            issue_wild: Vec::new(),
+            iodef: Vec::new(),
+            unknown_critical: false,
+        };
+
+        for caa in rec {
+            if caa.tag.eq_ignore_ascii_case("issue") {
+                policy.issue.push(caa);
+            } else if caa.tag.eq_ignore_ascii_case("issuewild") {
+                policy.issue_wild.push(caa);
+            } else if caa.tag.eq_ignore_ascii_case("iodef") {
+                policy.iodef.push(caa);
+            } else if caa.is_critical() {
+                policy.unknown_critical = true;
+            }
+        }
+
+        policy
+    }
+
+    /// Returns the properties which apply to wildcard certificates:
+    /// `issuewild` properties if any are present, otherwise `issue`.
+    pub fn wildcard_issue(&self) -> &[Caa] {
+        if self.issue_wild.is_empty() {
+            &self.issue
+        } else {
+            &self.issue_wild
+        }
+    }
+}
+
 /// Yields a series of `IpAddr` values from `resolve_host`.
-pub struct ResolveHost(IntoIter<IpAddr>);
+pub struct ResolveHost {
+    addrs: IntoIter<IpAddr>,
+    canonical_name: Option<String>,
+}
+
+impl ResolveHost {
+    pub(crate) fn new(addrs: Vec<IpAddr>, canonical_name: Option<String>) -> ResolveHost {
+        ResolveHost{
+            addrs: addrs.into_iter(),
+            canonical_name: canonical_name,
+        }
+    }
+
+    /// Returns the canonical name of the host, found by following any
+    /// `CNAME` or `DNAME` aliases, if known.
+    pub fn canonical_name(&self) -> Option<&str> {
+        self.canonical_name.as_ref().map(|s| &s[..])
+    }
+}
 
 impl Iterator for ResolveHost {
     type Item = IpAddr;
 
     fn next(&mut self) -> Option<IpAddr> {
-        self.0.next()
+        self.addrs.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use cache::DnsCache;
+    use config::DnsConfig;
+    use message::{Message, Qr, RCode, Resource, MESSAGE_LIMIT};
+    use record::{A, Caa, CName, Class, DName, Https, RecordType, Soa, Txt};
+    use socket::{DnsSocket, DnsStream};
+    use transport::{MockTransport, UdpTransport};
+    use super::{DnsResolver, ResolveError};
+
+    fn ns(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn mock_resolver(config: DnsConfig, udp: &Arc<MockTransport>,
+            tcp: &Arc<MockTransport>) -> DnsResolver {
+        DnsResolver::with_transport(config,
+            Box::new(udp.clone()), Box::new(tcp.clone()))
+    }
+
+    fn no_reply() -> Arc<MockTransport> {
+        Arc::new(MockTransport::new(|_, _| None))
+    }
+
+    fn a_reply<'a>(query: &Message, addr: Ipv4Addr) -> Message<'a> {
+        let mut msg = Message::new();
+        msg.header.qr = Qr::Response;
+        msg.question = query.question.clone();
+
+        if query.question[0].q_type == RecordType::A {
+            let mut rr = Resource::new(query.question[0].name.clone(),
+                RecordType::A, Class::Internet, 60);
+            rr.write_rdata(&A{address: addr}).unwrap();
+            msg.answer.push(rr);
+        }
+
+        msg
+    }
+
+    fn reply<'a>(query: &Message, truncated: bool) -> Message<'a> {
+        let mut msg = Message::with_id(query.header.id);
+        msg.header.qr = Qr::Response;
+        msg.header.truncated = truncated;
+        msg.question = query.question.clone();
+
+        if !truncated {
+            let mut rr = Resource::new(query.question[0].name.clone(),
+                RecordType::Txt, Class::Internet, 60);
+            rr.write_rdata(&Txt::new(b"hello")).unwrap();
+            msg.answer.push(rr);
+        }
+
+        msg
+    }
+
+    #[test]
+    fn test_tcp_fallback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let udp = UdpSocket::bind(addr).unwrap();
+
+        thread::spawn(move || {
+            let mut buf = [0; MESSAGE_LIMIT];
+            let (n, peer) = udp.recv_from(&mut buf).unwrap();
+            let query = Message::decode(&buf[..n]).unwrap();
+
+            let mut out = [0; MESSAGE_LIMIT];
+            let data = reply(&query, true).encode(&mut out).unwrap();
+            udp.send_to(data, peer).unwrap();
+        });
+
+        thread::spawn(move || {
+            let stream = DnsStream::from_stream(listener.accept().unwrap().0);
+
+            let mut buf = [0; MESSAGE_LIMIT];
+            let query = stream.recv_message(&mut buf).unwrap();
+
+            stream.send_message(&reply(&query, false)).unwrap();
+        });
+
+        let mut config = DnsConfig::with_name_servers(vec![addr]);
+        config.timeout = Duration::from_secs(2);
+        config.attempts = 1;
+
+        let resolver = DnsResolver::new(config).unwrap();
+        let txt = resolver.resolve_record::<Txt>("example.com.").unwrap();
+
+        assert_eq!(txt, [Txt::new(b"hello")]);
+    }
+
+    #[test]
+    fn test_retry() {
+        let udp = Arc::new(MockTransport::new(|query, addr| {
+            if *addr == ns("192.0.2.2:53") {
+                Some(a_reply(query, Ipv4Addr::new(10, 0, 0, 1)))
+            } else {
+                None
+            }
+        }));
+
+        let mut config = DnsConfig::with_name_servers(
+            vec![ns("192.0.2.1:53"), ns("192.0.2.2:53")]);
+        config.attempts = 2;
+
+        let resolver = mock_resolver(config, &udp, &no_reply());
+        let addrs = resolver.resolve_record::<A>("example.com.").unwrap();
+
+        assert_eq!(addrs, [A{address: Ipv4Addr::new(10, 0, 0, 1)}]);
+
+        let sent = udp.queries().into_iter().map(|(addr, _)| addr).collect::<Vec<_>>();
+        assert_eq!(sent, [ns("192.0.2.1:53"), ns("192.0.2.2:53")]);
+
+        // Without another attempt, the unresponsive server results in an error
+        let mut config = DnsConfig::with_name_servers(
+            vec![ns("192.0.2.1:53"), ns("192.0.2.2:53")]);
+        config.attempts = 1;
+
+        let resolver = mock_resolver(config, &udp, &no_reply());
+        assert!(resolver.resolve_record::<A>("example.com.").is_err());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let udp = Arc::new(MockTransport::new(
+            |query, _| Some(a_reply(query, Ipv4Addr::new(10, 0, 0, 1)))));
+
+        let mut config = DnsConfig::with_name_servers(
+            vec![ns("192.0.2.1:53"), ns("192.0.2.2:53")]);
+        config.rotate = true;
+
+        let resolver = mock_resolver(config, &udp, &no_reply());
+
+        for _ in 0..3 {
+            resolver.resolve_record::<A>("example.com.").unwrap();
+        }
+
+        let sent = udp.queries().into_iter().map(|(addr, _)| addr).collect::<Vec<_>>();
+        assert_eq!(sent, [ns("192.0.2.1:53"), ns("192.0.2.2:53"), ns("192.0.2.1:53")]);
+    }
+
+    #[test]
+    fn test_search() {
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+
+            if query.question[0].name != "www.foo.example.com" {
+                msg.header.rcode = RCode::NameError;
+                msg.answer.clear();
+            }
+
+            Some(msg)
+        }));
+
+        let mut config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+        config.search = vec!["bar.example.com".to_owned(), "example.com".to_owned()];
+
+        let resolver = mock_resolver(config, &udp, &no_reply());
+        let addrs = resolver.resolve_host("www.foo").unwrap().collect::<Vec<_>>();
+
+        assert_eq!(addrs, ["10.0.0.1".parse::<IpAddr>().unwrap()]);
+
+        let mut names = udp.queries().into_iter()
+            .map(|(_, msg)| (msg.question[0].name.clone(), msg.question[0].q_type))
+            .collect::<Vec<_>>();
+
+        // A and AAAA queries for each name may be sent in either order
+        names.sort_by_key(|&(ref name, r_type)| (name.clone(), r_type == RecordType::AAAA));
+
+        assert_eq!(names, [
+            ("www.foo.bar.example.com".to_owned(), RecordType::A),
+            ("www.foo.bar.example.com".to_owned(), RecordType::AAAA),
+            ("www.foo.example.com".to_owned(), RecordType::A),
+            ("www.foo.example.com".to_owned(), RecordType::AAAA),
+        ]);
+    }
+
+    #[test]
+    fn test_parallel() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // Both queries are received before either is answered
+        thread::spawn(move || {
+            let mut received = Vec::new();
+
+            for _ in 0..2 {
+                let mut buf = [0; MESSAGE_LIMIT];
+                let (n, peer) = server.recv_from(&mut buf).unwrap();
+                received.push((buf[..n].to_vec(), peer));
+            }
+
+            for &(ref data, peer) in received.iter().rev() {
+                let query = Message::decode(data).unwrap();
+                let mut msg = a_reply(&query, Ipv4Addr::new(10, 0, 0, 1));
+                msg.header.id = query.header.id;
+
+                let mut buf = [0; MESSAGE_LIMIT];
+                server.send_to(msg.encode(&mut buf).unwrap(), peer).unwrap();
+            }
+        });
+
+        let mut config = DnsConfig::with_name_servers(vec![addr]);
+        config.attempts = 1;
+        config.timeout = Duration::from_secs(5);
+
+        let udp = UdpTransport::new(DnsSocket::bind("127.0.0.1:0").unwrap());
+        let resolver = DnsResolver::with_transport(config.clone(),
+            Box::new(udp), Box::new(no_reply()));
+        let addrs = resolver.resolve_host("example.com.").unwrap().collect::<Vec<_>>();
+
+        assert_eq!(addrs, ["10.0.0.1".parse::<IpAddr>().unwrap()]);
+
+        let udp = Arc::new(MockTransport::new(
+            |query, _| Some(a_reply(query, Ipv4Addr::new(10, 0, 0, 1)))));
+
+        config.single_request = true;
+
+        let resolver = mock_resolver(config.clone(), &udp, &no_reply());
+        resolver.resolve_host("example.com.").unwrap();
+
+        let types = udp.queries().into_iter()
+            .map(|(_, msg)| msg.question[0].q_type).collect::<Vec<_>>();
+        assert_eq!(types, [RecordType::A, RecordType::AAAA]);
+
+        // With `use_inet6`, `A` records are queried only if
+        // no `AAAA` records are found
+        let udp = Arc::new(MockTransport::new(
+            |query, _| Some(a_reply(query, Ipv4Addr::new(10, 0, 0, 1)))));
+
+        config.single_request = false;
+        config.use_inet6 = true;
+
+        let resolver = mock_resolver(config, &udp, &no_reply());
+        let addrs = resolver.resolve_host("example.com.").unwrap().collect::<Vec<_>>();
+
+        assert_eq!(addrs, ["::ffff:10.0.0.1".parse::<IpAddr>().unwrap()]);
+
+        let types = udp.queries().into_iter()
+            .map(|(_, msg)| msg.question[0].q_type).collect::<Vec<_>>();
+        assert_eq!(types, [RecordType::AAAA, RecordType::A]);
+    }
+
+    #[test]
+    fn test_edns_fallback() {
+        // Name server does not support EDNS
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+
+            if query.edns.is_some() {
+                msg.header.rcode = RCode::FormatError;
+                msg.answer.clear();
+            }
+
+            Some(msg)
+        }));
+
+        let mut config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+        config.edns0 = true;
+
+        let resolver = mock_resolver(config, &udp, &no_reply());
+        let addrs = resolver.resolve_record::<A>("example.com.").unwrap();
+
+        assert_eq!(addrs, [A{address: Ipv4Addr::new(10, 0, 0, 1)}]);
+
+        let edns = udp.queries().into_iter()
+            .map(|(_, msg)| msg.edns.is_some()).collect::<Vec<_>>();
+        assert_eq!(edns, [true, false]);
+    }
+
+    #[test]
+    fn test_shared() {
+        let udp = Arc::new(MockTransport::new(
+            |query, _| Some(a_reply(query, Ipv4Addr::new(10, 0, 0, 1)))));
+
+        let mut config = DnsConfig::with_name_servers(
+            vec![ns("192.0.2.1:53"), ns("192.0.2.2:53")]);
+        config.rotate = true;
+
+        let resolver = Arc::new(mock_resolver(config, &udp, &no_reply()));
+
+        let threads = (0..4).map(|_| {
+            let resolver = resolver.clone();
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    resolver.resolve_record::<A>("example.com.").unwrap();
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let queries = udp.queries();
+        let first = queries.iter().filter(|q| q.0 == ns("192.0.2.1:53")).count();
+
+        assert_eq!(queries.len(), 20);
+        assert_eq!(first, 10);
+    }
+
+    #[test]
+    fn test_cache() {
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+
+            if query.question[0].name == "missing.example.com." {
+                let mut soa = Resource::new("example.com.".to_owned(),
+                    RecordType::Soa, Class::Internet, 60);
+                soa.write_rdata(&Soa{
+                    mname: "ns.example.com.".to_owned(),
+                    rname: "admin.example.com.".to_owned(),
+                    serial: 1,
+                    refresh: 7200,
+                    retry: 3600,
+                    expire: 86400,
+                    minimum: 300,
+                }).unwrap();
+
+                msg.header.rcode = RCode::NameError;
+                msg.answer.clear();
+                msg.authority.push(soa);
+            }
+
+            Some(msg)
+        }));
+
+        let config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+
+        let mut resolver = mock_resolver(config, &udp, &no_reply());
+        resolver.set_cache(Some(DnsCache::new(16)));
+
+        for _ in 0..2 {
+            let addrs = resolver.resolve_record::<A>("example.com.").unwrap();
+            assert_eq!(addrs, [A{address: Ipv4Addr::new(10, 0, 0, 1)}]);
+
+            assert!(resolver.resolve_record::<A>("missing.example.com.").is_err());
+        }
+
+        assert_eq!(udp.queries().len(), 2);
+        assert_eq!(resolver.cache().unwrap().len(), 2);
+
+        resolver.cache().unwrap().clear();
+        resolver.resolve_record::<A>("example.com.").unwrap();
+
+        assert_eq!(udp.queries().len(), 3);
+    }
+
+    fn alias<'a>(name: &str, r_type: RecordType, target: &str) -> Resource<'a> {
+        let mut rr = Resource::new(name.to_owned(), r_type, Class::Internet, 60);
+        match r_type {
+            RecordType::CName => rr.write_rdata(&CName{name: target.to_owned()}),
+            _ => rr.write_rdata(&DName{name: target.to_owned()}),
+        }.unwrap();
+        rr
+    }
+
+    #[test]
+    fn test_alias_chain() {
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let name = query.question[0].name.clone();
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+
+            match &name[..] {
+                // The alias target is answered in a separate query
+                "www.example.com" => {
+                    msg.answer = vec![alias("www.example.com.", RecordType::CName,
+                        "www.example.net.")];
+                }
+                // DNAME maps a subtree onto another
+                "www.example.net." => {
+                    let mut a = a_reply(query, Ipv4Addr::new(10, 0, 0, 2)).answer;
+                    for rr in &mut a {
+                        rr.name = "www.example.org.".to_owned();
+                    }
+
+                    msg.answer = vec![alias("example.net.", RecordType::DName,
+                        "example.org.")];
+                    msg.answer.extend(a);
+                }
+                "loop.example.com" => {
+                    msg.answer = vec![
+                        alias("loop.example.com.", RecordType::CName, "loop2.example.com."),
+                        alias("loop2.example.com.", RecordType::CName, "loop.example.com."),
+                    ];
+                }
+                _ => ()
+            }
+
+            Some(msg)
+        }));
+
+        let config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+        let resolver = mock_resolver(config, &udp, &no_reply());
+
+        let (name, addrs) = resolver.resolve_record_canonical::<A>("www.example.com").unwrap();
+
+        assert_eq!(name, "www.example.org");
+        assert_eq!(addrs, [A{address: Ipv4Addr::new(10, 0, 0, 2)}]);
+        assert_eq!(udp.queries().len(), 2);
+
+        let host = resolver.resolve_host("www.example.com").unwrap();
+        assert_eq!(host.canonical_name(), Some("www.example.org"));
+
+        match resolver.resolve_record::<A>("loop.example.com") {
+            Err(ResolveError::AliasLoop) => (),
+            r => panic!("unexpected result: {:?}", r)
+        }
+
+        // Requesting CNAME records does not follow the alias
+        let cname = resolver.resolve_record::<CName>("www.example.com").unwrap();
+        assert_eq!(cname, [CName{name: "www.example.net.".to_owned()}]);
+    }
+
+    #[test]
+    fn test_caa() {
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let q = &query.question[0];
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+
+            let rdata: &[&str] = match &q.name[..] {
+                "example.com." => &[
+                    "0 issue \"ca.example.net\"",
+                    "0 ISSUE \"other.example.net\"",
+                    "0 iodef \"mailto:security@example.com\"",
+                    "0 future \"ignored\"",
+                ],
+                "example.net." => &[
+                    "0 issuewild \";\"",
+                    "128 tbs \"Unknown\"",
+                ],
+                "missing.example.net." => {
+                    msg.header.rcode = RCode::NameError;
+                    &[]
+                }
+                _ => &[]
+            };
+
+            for rdata in rdata {
+                let mut rr = Resource::new(q.name.clone(), RecordType::Caa,
+                    Class::Internet, 60);
+                rr.write_rdata(&rdata.parse::<Caa>().unwrap()).unwrap();
+                msg.answer.push(rr);
+            }
+
+            Some(msg)
+        }));
+
+        let config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+        let resolver = mock_resolver(config, &udp, &no_reply());
+
+        let policy = resolver.resolve_caa("www.sub.example.com").unwrap().unwrap();
+        assert_eq!(udp.queries().len(), 3);
+        assert_eq!(policy.name, "example.com.");
+        assert_eq!(policy.issue.len(), 2);
+        assert_eq!(policy.iodef[0].value, b"mailto:security@example.com");
+        assert!(policy.issue_wild.is_empty());
+        assert_eq!(policy.wildcard_issue(), &policy.issue[..]);
+        assert!(!policy.unknown_critical);
+
+        let policy = resolver.resolve_caa("missing.example.net").unwrap().unwrap();
+        assert_eq!(policy.name, "example.net.");
+        assert!(policy.issue.is_empty());
+        assert_eq!(policy.wildcard_issue()[0].value, b";");
+        assert!(policy.unknown_critical);
+
+        assert_eq!(resolver.resolve_caa("example.org").unwrap(), None);
+    }
+
+    #[test]
+    fn test_https() {
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let q = &query.question[0];
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+
+            let rdata: &[&str] = match &q.name[..] {
+                "example.com" => &["0 svc.example.net."],
+                "svc.example.net." => &[
+                    "2 . alpn=h2",
+                    "1 pool.example.net. alpn=h2,h3 port=8443",
+                    "3 other.example.net. mandatory=key1234 key1234=x",
+                ],
+                "gone.example.com" => &["0 ."],
+                "loop.example.com" => &["0 loop.example.com."],
+                _ => &[]
+            };
+
+            for rdata in rdata {
+                let mut rr = Resource::new(q.name.clone(), RecordType::Https,
+                    Class::Internet, 60);
+                rr.write_rdata(&rdata.parse::<Https>().unwrap()).unwrap();
+                msg.answer.push(rr);
+            }
+
+            Some(msg)
+        }));
+
+        let config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+        let resolver = mock_resolver(config, &udp, &no_reply());
+
+        let rec = resolver.resolve_https("example.com").unwrap();
+        assert_eq!(rec, [
+            "1 pool.example.net. alpn=h2,h3 port=8443".parse().unwrap(),
+            "2 svc.example.net. alpn=h2".parse::<Https>().unwrap(),
+        ]);
+
+        match resolver.resolve_https("gone.example.com") {
+            Err(ResolveError::NoData) => (),
+            r => panic!("unexpected result: {:?}", r)
+        }
+        match resolver.resolve_https("none.example.com") {
+            Err(ResolveError::NoData) => (),
+            r => panic!("unexpected result: {:?}", r)
+        }
+        match resolver.resolve_https("loop.example.com") {
+            Err(ResolveError::AliasLoop) => (),
+            r => panic!("unexpected result: {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+            msg.header.truncated = true;
+            msg.answer.clear();
+            Some(msg)
+        }));
+        let tcp = Arc::new(MockTransport::new(
+            |query, _| Some(a_reply(query, Ipv4Addr::new(10, 0, 0, 2)))));
+
+        let config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+
+        let resolver = mock_resolver(config, &udp, &tcp);
+        let addrs = resolver.resolve_record::<A>("example.com.").unwrap();
+
+        assert_eq!(addrs, [A{address: Ipv4Addr::new(10, 0, 0, 2)}]);
+        assert_eq!(tcp.queries().len(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        let udp = Arc::new(MockTransport::new(|query, _| {
+            let mut msg = a_reply(query, Ipv4Addr::new(10, 0, 0, 1));
+
+            match &query.question[0].name[..] {
+                "missing.example.com." => msg.header.rcode = RCode::NameError,
+                "broken.example.com." => msg.header.rcode = RCode::ServerFailure,
+                "slow.example.com." => return None,
+                _ => ()
+            }
+
+            if msg.header.rcode != RCode::NoError {
+                msg.answer.clear();
+            }
+
+            Some(msg)
+        }));
+
+        let config = DnsConfig::with_name_servers(vec![ns("192.0.2.1:53")]);
+        let resolver = mock_resolver(config, &udp, &no_reply());
+
+        match resolver.resolve_record::<A>("missing.example.com.") {
+            Err(ResolveError::NoSuchName) => (),
+            r => panic!("unexpected result: {:?}", r)
+        }
+
+        match resolver.resolve_record::<A>("broken.example.com.") {
+            Err(ref e @ ResolveError::ServerFailure(_)) =>
+                assert_eq!(e.rcode(), Some(RCode::ServerFailure)),
+            r => panic!("unexpected result: {:?}", r)
+        }
+
+        match resolver.resolve_record::<A>("slow.example.com.") {
+            Err(ref e) if e.is_timeout() => (),
+            r => panic!("unexpected result: {:?}", r)
+        }
+
+        match resolver.resolve_record::<Txt>("example.com.") {
+            Err(ResolveError::NoData) => (),
+            r => panic!("unexpected result: {:?}", r)
+        }
     }
 }
@@ -1,8 +1,10 @@
-//! Low-level UDP socket operations
+//! Low-level UDP and TCP socket operations
 
+use std::error::Error as StdError;
 use std::fmt;
-use std::io;
-use std::net::{IpAddr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
 
 use address::socket_address_equal;
 use message::{DecodeError, DnsError, EncodeError, Message, MESSAGE_LIMIT};
@@ -70,6 +72,76 @@ impl DnsSocket {
     }
 }
 
+/// Represents a TCP stream transmitting DNS messages.
+///
+/// Each message is preceded by a two byte length field, as described in
+/// RFC 1035, section 4.2.2. Any number of messages may be exchanged over
+/// a single stream.
+pub struct DnsStream {
+    stream: TcpStream,
+}
+
+impl DnsStream {
+    /// Returns a `DnsStream`, connected to the given address.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<DnsStream> {
+        Ok(DnsStream{
+            stream: try!(TcpStream::connect(addr)),
+        })
+    }
+
+    /// Returns a `DnsStream`, connected to the given address.
+    /// The connection attempt will fail if it is not completed within `timeout`.
+    pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> io::Result<DnsStream> {
+        Ok(DnsStream{
+            stream: try!(TcpStream::connect_timeout(addr, timeout)),
+        })
+    }
+
+    /// Returns a `DnsStream` wrapping an already connected `TcpStream`.
+    pub fn from_stream(stream: TcpStream) -> DnsStream {
+        DnsStream{stream: stream}
+    }
+
+    /// Returns a reference to the wrapped `TcpStream`.
+    pub fn get(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Sends a message to the remote peer.
+    pub fn send_message(&self, message: &Message) -> Result<(), Error> {
+        let mut buf = [0; MESSAGE_LIMIT + 2];
+        let len = try!(message.encode(&mut buf[2..])).len();
+
+        buf[0] = (len >> 8) as u8;
+        buf[1] = len as u8;
+
+        try!((&self.stream).write_all(&buf[..len + 2]));
+        Ok(())
+    }
+
+    /// Reads a single message from the remote peer.
+    /// The given buffer is used to store and parse message data.
+    ///
+    /// The buffer should be exactly `MESSAGE_LIMIT` bytes in length.
+    pub fn recv_message<'buf>(&self, buf: &'buf mut [u8])
+            -> Result<Message<'buf>, Error> {
+        let mut len_buf = [0; 2];
+        try!((&self.stream).read_exact(&mut len_buf));
+
+        let len = ((len_buf[0] as usize) << 8) | len_buf[1] as usize;
+
+        if len > buf.len() {
+            return Err(Error::IoError(io::Error::new(io::ErrorKind::InvalidInput,
+                "buffer too small for message")));
+        }
+
+        try!((&self.stream).read_exact(&mut buf[..len]));
+
+        let msg = try!(Message::decode(&buf[..len]));
+        Ok(msg)
+    }
+}
+
 /// Represents an error in sending or receiving a DNS message.
 #[derive(Debug)]
 pub enum Error {
@@ -108,6 +180,17 @@ impl fmt::Display for Error {
     }
 }
 
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::DecodeError(ref e) => Some(e),
+            Error::EncodeError(ref e) => Some(e),
+            Error::DnsError(ref e) => Some(e),
+            Error::IoError(ref e) => Some(e),
+        }
+    }
+}
+
 impl From<DecodeError> for Error {
     fn from(err: DecodeError) -> Error {
         Error::DecodeError(err)
@@ -131,3 +214,41 @@ impl From<io::Error> for Error {
         Error::IoError(err)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use message::{Message, Question, MESSAGE_LIMIT};
+    use record::{Class, RecordType};
+    use super::DnsStream;
+
+    #[test]
+    fn test_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let stream = DnsStream::from_stream(listener.accept().unwrap().0);
+            let mut buf = [0; MESSAGE_LIMIT];
+
+            // Echo each message back to the client
+            while let Ok(msg) = stream.recv_message(&mut buf) {
+                stream.send_message(&msg).unwrap();
+            }
+        });
+
+        let stream = DnsStream::connect(addr).unwrap();
+        let mut buf = [0; MESSAGE_LIMIT];
+
+        for &name in &["foo.com.", "bar.org."] {
+            let mut msg = Message::new();
+            msg.question.push(Question::new(name.to_owned(),
+                RecordType::A, Class::Internet));
+
+            stream.send_message(&msg).unwrap();
+            assert_eq!(stream.recv_message(&mut buf).unwrap(), msg);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! Ordering of destination addresses
+//!
+//! Implements the destination address selection rules of RFC 6724 and the
+//! `sortlist` directive of `resolv.conf(5)`.
+
+use std::cmp::Ordering;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+use config::DnsConfig;
+
+/// An entry in the RFC 6724 policy table
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Policy {
+    /// Address prefix to which the policy applies
+    pub prefix: Ipv6Addr,
+    /// Length of the prefix, in bits
+    pub len: u8,
+    /// Precedence of matching addresses; higher values are preferred
+    pub precedence: u8,
+    /// Label of matching addresses; source and destination addresses
+    /// with the same label are preferred
+    pub label: u8,
+}
+
+impl Policy {
+    /// Constructs a new `Policy`.
+    pub fn new(prefix: Ipv6Addr, len: u8, precedence: u8, label: u8) -> Policy {
+        Policy{
+            prefix: prefix,
+            len: len,
+            precedence: precedence,
+            label: label,
+        }
+    }
+
+    /// Returns whether the given address falls within the policy prefix.
+    pub fn matches(&self, addr: &Ipv6Addr) -> bool {
+        common_prefix_len(&self.prefix, addr) >= self.len as u32
+    }
+}
+
+/// Returns the default policy table, given in RFC 6724, section 2.1.
+pub fn default_policy_table() -> Vec<Policy> {
+    vec![
+        Policy::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1), 128, 50, 0),
+        Policy::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 0, 40, 1),
+        Policy::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0xffff, 0, 0), 96, 35, 4),
+        Policy::new(Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0), 16, 30, 2),
+        Policy::new(Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 32, 5, 5),
+        Policy::new(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7, 3, 13),
+        Policy::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 96, 1, 3),
+        Policy::new(Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0), 10, 1, 11),
+        Policy::new(Ipv6Addr::new(0x3ffe, 0, 0, 0, 0, 0, 0, 0), 16, 1, 12),
+    ]
+}
+
+/// A network given in the `sortlist` directive of `resolv.conf`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SortlistEntry {
+    /// Network address
+    pub network: IpAddr,
+    /// Network mask; must be of the same family as `network`
+    pub netmask: IpAddr,
+}
+
+impl SortlistEntry {
+    /// Parses a `sortlist` entry of the form `address[/netmask]`.
+    ///
+    /// The netmask may be given as an address or a prefix length. If it is
+    /// omitted, the natural (classful) netmask is used for IPv4 networks
+    /// and the full address is used for IPv6 networks.
+    pub fn parse(s: &str) -> Option<SortlistEntry> {
+        let (addr, mask) = match s.find('/') {
+            Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+            None => (s, None)
+        };
+
+        let network = match addr.parse::<IpAddr>() {
+            Ok(addr) => addr,
+            Err(_) => return None
+        };
+
+        let netmask = match (network, mask) {
+            (IpAddr::V4(net), None) => IpAddr::V4(natural_netmask(&net)),
+            (IpAddr::V6(_), None) => IpAddr::V6(Ipv6Addr::from(!0u128)),
+            (_, Some(mask)) => {
+                if let Ok(len) = mask.parse::<u8>() {
+                    match network {
+                        IpAddr::V4(_) if len <= 32 => IpAddr::V4(
+                            Ipv4Addr::from((!0u32).checked_shl(32 - len as u32).unwrap_or(0))),
+                        IpAddr::V6(_) if len <= 128 => IpAddr::V6(
+                            Ipv6Addr::from((!0u128).checked_shl(128 - len as u32).unwrap_or(0))),
+                        _ => return None
+                    }
+                } else {
+                    match (network, mask.parse::<IpAddr>()) {
+                        (IpAddr::V4(_), Ok(mask @ IpAddr::V4(_))) => mask,
+                        (IpAddr::V6(_), Ok(mask @ IpAddr::V6(_))) => mask,
+                        _ => return None
+                    }
+                }
+            }
+        };
+
+        Some(SortlistEntry{
+            network: network,
+            netmask: netmask,
+        })
+    }
+
+    /// Returns whether the given address belongs to the network.
+    /// IPv4-mapped IPv6 addresses are matched against IPv4 networks.
+    pub fn matches(&self, addr: &IpAddr) -> bool {
+        let addr = match *addr {
+            IpAddr::V6(ref a) => match a.to_ipv4_mapped() {
+                Some(a) => IpAddr::V4(a),
+                None => IpAddr::V6(*a)
+            },
+            a => a
+        };
+
+        match (self.network, self.netmask, addr) {
+            (IpAddr::V4(net), IpAddr::V4(mask), IpAddr::V4(a)) => {
+                let mask = u32::from(mask);
+                u32::from(net) & mask == u32::from(a) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(mask), IpAddr::V6(a)) => {
+                let mask = u128::from(mask);
+                u128::from(net) & mask == u128::from(a) & mask
+            }
+            _ => false
+        }
+    }
+}
+
+fn natural_netmask(addr: &Ipv4Addr) -> Ipv4Addr {
+    match addr.octets()[0] {
+        0 ..= 127 => Ipv4Addr::new(255, 0, 0, 0),
+        128 ..= 191 => Ipv4Addr::new(255, 255, 0, 0),
+        _ => Ipv4Addr::new(255, 255, 255, 0),
+    }
+}
+
+/// Orders addresses according to the given configuration.
+///
+/// If `config.sort_addresses` is set, addresses are first ordered according
+/// to RFC 6724. Then, if `config.sortlist` is not empty, addresses are
+/// ordered by the first matching entry; addresses matching no entry are
+/// placed last.
+pub fn sort_with_config(addrs: &mut [IpAddr], config: &DnsConfig) {
+    if config.sort_addresses {
+        sort_addresses(addrs);
+    }
+    if !config.sortlist.is_empty() {
+        apply_sortlist(addrs, &config.sortlist);
+    }
+}
+
+/// Orders addresses by the first matching `sortlist` entry.
+/// Addresses matching no entry are placed last.
+/// The relative order of addresses matching the same entry is preserved.
+pub fn apply_sortlist(addrs: &mut [IpAddr], sortlist: &[SortlistEntry]) {
+    addrs.sort_by_key(|addr| {
+        sortlist.iter().position(|ent| ent.matches(addr)).unwrap_or(sortlist.len())
+    });
+}
+
+/// Orders addresses according to the destination address selection rules
+/// of RFC 6724, using the default policy table.
+///
+/// The source address for each destination is the one chosen by the system
+/// when connecting a UDP socket to it. No packets are sent.
+pub fn sort_addresses(addrs: &mut [IpAddr]) {
+    sort_addresses_with(addrs, &default_policy_table(), source_address);
+}
+
+/// Orders addresses according to the destination address selection rules
+/// of RFC 6724, using the given policy table and function to find the
+/// source address used to reach each destination.
+///
+/// `source` should return `None` if a destination is unreachable.
+pub fn sort_addresses_with<F>(addrs: &mut [IpAddr], policy: &[Policy], mut source: F)
+        where F: FnMut(&IpAddr) -> Option<IpAddr> {
+    let mut cands = addrs.iter().map(|addr| {
+        let dest = to_ipv6(addr);
+        let src = source(addr).map(|a| to_ipv6(&a));
+
+        Candidate{
+            addr: *addr,
+            dest: dest,
+            dest_policy: find_policy(policy, &dest),
+            src: src.map(|src| (src, find_policy(policy, &src))),
+        }
+    }).collect::<Vec<_>>();
+
+    // The rules do not necessarily define a total order, which the standard
+    // library sorting functions require; a simple insertion sort is used.
+    for i in 1..cands.len() {
+        let mut j = i;
+
+        while j > 0 && compare(&cands[j], &cands[j - 1]) == Ordering::Less {
+            cands.swap(j, j - 1);
+            j -= 1;
+        }
+    }
+
+    for (addr, cand) in addrs.iter_mut().zip(cands) {
+        *addr = cand.addr;
+    }
+}
+
+struct Candidate {
+    addr: IpAddr,
+    dest: Ipv6Addr,
+    dest_policy: (u8, u8),
+    src: Option<(Ipv6Addr, (u8, u8))>,
+}
+
+/// Compares two destinations; the preferred destination is `Less`.
+fn compare(a: &Candidate, b: &Candidate) -> Ordering {
+    // Rule 1: Avoid unusable destinations
+    let (a_src, a_src_policy) = match (a.src, b.src) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) => return Ordering::Greater,
+        (Some(_), None) => return Ordering::Less,
+        (Some(src), Some(_)) => src,
+    };
+    let (b_src, b_src_policy) = b.src.unwrap();
+
+    let a_scope = scope(&a.dest);
+    let b_scope = scope(&b.dest);
+
+    // Rule 2: Prefer matching scope
+    let a_match = a_scope == scope(&a_src);
+    let b_match = b_scope == scope(&b_src);
+
+    if a_match != b_match {
+        return if a_match { Ordering::Less } else { Ordering::Greater };
+    }
+
+    // Rules 3 and 4, concerning deprecated and home addresses, require
+    // information not available here.
+
+    // Rule 5: Prefer matching label
+    let (a_prec, a_label) = a.dest_policy;
+    let (b_prec, b_label) = b.dest_policy;
+
+    let a_match = a_label == a_src_policy.1;
+    let b_match = b_label == b_src_policy.1;
+
+    if a_match != b_match {
+        return if a_match { Ordering::Less } else { Ordering::Greater };
+    }
+
+    // Rule 6: Prefer higher precedence
+    if a_prec != b_prec {
+        return b_prec.cmp(&a_prec);
+    }
+
+    // Rule 7, preferring native transport, is not applied.
+
+    // Rule 8: Prefer smaller scope
+    if a_scope != b_scope {
+        return a_scope.cmp(&b_scope);
+    }
+
+    // Rule 9: Use longest matching prefix, for IPv6 destinations
+    if a.dest.to_ipv4_mapped().is_none() && b.dest.to_ipv4_mapped().is_none() {
+        let a_len = common_prefix_len(&a.dest, &a_src);
+        let b_len = common_prefix_len(&b.dest, &b_src);
+
+        if a_len != b_len {
+            return b_len.cmp(&a_len);
+        }
+    }
+
+    // Rule 10: Otherwise, leave the order unchanged
+    Ordering::Equal
+}
+
+fn find_policy(policy: &[Policy], addr: &Ipv6Addr) -> (u8, u8) {
+    policy.iter()
+        .filter(|p| p.matches(addr))
+        .max_by_key(|p| p.len)
+        .map_or((0, 0), |p| (p.precedence, p.label))
+}
+
+/// Returns the scope of an address, as defined in RFC 4291, section 2.7,
+/// and RFC 6724, section 3.2.
+fn scope(addr: &Ipv6Addr) -> u8 {
+    const LINK_LOCAL: u8 = 0x2;
+    const SITE_LOCAL: u8 = 0x5;
+    const GLOBAL: u8 = 0xe;
+
+    if let Some(v4) = addr.to_ipv4_mapped() {
+        return if v4.is_loopback() || v4.is_link_local() {
+            LINK_LOCAL
+        } else {
+            GLOBAL
+        };
+    }
+
+    let seg = addr.segments();
+
+    if seg[0] & 0xff00 == 0xff00 {
+        // Multicast scope field
+        (seg[0] & 0xf) as u8
+    } else if addr.is_loopback() || seg[0] & 0xffc0 == 0xfe80 {
+        LINK_LOCAL
+    } else if seg[0] & 0xffc0 == 0xfec0 {
+        SITE_LOCAL
+    } else {
+        GLOBAL
+    }
+}
+
+fn common_prefix_len(a: &Ipv6Addr, b: &Ipv6Addr) -> u32 {
+    (u128::from(*a) ^ u128::from(*b)).leading_zeros()
+}
+
+fn to_ipv6(addr: &IpAddr) -> Ipv6Addr {
+    match *addr {
+        IpAddr::V4(ref a) => a.to_ipv6_mapped(),
+        IpAddr::V6(a) => a,
+    }
+}
+
+/// Returns the source address the system would use to reach `addr`.
+fn source_address(addr: &IpAddr) -> Option<IpAddr> {
+    let bind = match *addr {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+    };
+
+    let sock = match UdpSocket::bind(SocketAddr::new(bind, 0)) {
+        Ok(sock) => sock,
+        Err(_) => return None
+    };
+
+    // Connecting a UDP socket selects a route and source address,
+    // but sends nothing.
+    match sock.connect(SocketAddr::new(*addr, 53)) {
+        Ok(()) => sock.local_addr().ok().map(|a| a.ip()),
+        Err(_) => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use super::{apply_sortlist, default_policy_table, sort_addresses_with, SortlistEntry};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn sort(addrs: &[&str], sources: &[(&str, &str)]) -> Vec<IpAddr> {
+        let mut addrs = addrs.iter().map(|s| ip(s)).collect::<Vec<_>>();
+
+        sort_addresses_with(&mut addrs, &default_policy_table(), |addr| {
+            sources.iter().find(|&&(d, _)| ip(d) == *addr).map(|&(_, s)| ip(s))
+        });
+
+        addrs
+    }
+
+    #[test]
+    fn test_rfc6724() {
+        // Examples from RFC 6724, section 10.2
+        assert_eq!(sort(&["2001:db8:1::1", "198.51.100.121"],
+                &[("2001:db8:1::1", "2001:db8:1::2"), ("198.51.100.121", "169.254.13.78")]),
+            [ip("2001:db8:1::1"), ip("198.51.100.121")]);
+
+        assert_eq!(sort(&["2001:db8:1::1", "198.51.100.121"],
+                &[("2001:db8:1::1", "fe80::1"), ("198.51.100.121", "198.51.100.117")]),
+            [ip("198.51.100.121"), ip("2001:db8:1::1")]);
+
+        assert_eq!(sort(&["2001:db8:1::1", "10.1.2.3"],
+                &[("2001:db8:1::1", "2001:db8:1::2"), ("10.1.2.3", "10.1.2.4")]),
+            [ip("2001:db8:1::1"), ip("10.1.2.3")]);
+
+        assert_eq!(sort(&["2001:db8:1::1", "fe80::1"],
+                &[("2001:db8:1::1", "2001:db8:1::2"), ("fe80::1", "fe80::2")]),
+            [ip("fe80::1"), ip("2001:db8:1::1")]);
+
+        assert_eq!(sort(&["2002:c633:6401::1", "2001:db8:1::1"],
+                &[("2002:c633:6401::1", "2002:c633:6401::2"),
+                  ("2001:db8:1::1", "2002:c633:6401::2")]),
+            [ip("2002:c633:6401::1"), ip("2001:db8:1::1")]);
+
+        // Unreachable destinations are placed last
+        assert_eq!(sort(&["2001:db8:1::1", "192.0.2.1"], &[("192.0.2.1", "192.0.2.2")]),
+            [ip("192.0.2.1"), ip("2001:db8:1::1")]);
+    }
+
+    #[test]
+    fn test_sortlist() {
+        let sortlist = ["130.155.160.0/255.255.240.0", "130.155.0.0", "2001:db8::/32"]
+            .iter().map(|s| SortlistEntry::parse(s).unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(sortlist[1].netmask, ip("255.255.0.0"));
+        assert_eq!(SortlistEntry::parse("10.0.0.0/8").unwrap().netmask, ip("255.0.0.0"));
+        assert!(SortlistEntry::parse("10.0.0.0/ffff::").is_none());
+        assert!(SortlistEntry::parse("foo").is_none());
+
+        let mut addrs = vec![ip("192.0.2.1"), ip("130.155.1.1"), ip("2001:db8::1"),
+            ip("::ffff:130.155.161.1")];
+
+        apply_sortlist(&mut addrs, &sortlist);
+
+        assert_eq!(addrs, [ip("::ffff:130.155.161.1"), ip("130.155.1.1"),
+            ip("2001:db8::1"), ip("192.0.2.1")]);
+    }
+}
@@ -0,0 +1,444 @@
+//! Transports used by `DnsResolver` to exchange messages with name servers
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use address::socket_address_equal;
+use message::{DecodeError, Message, Qr, Question, MESSAGE_LIMIT};
+use socket::{DnsSocket, DnsStream, Error};
+
+/// Responses to the two messages sent by `Transport::exchange_pair`
+pub type PairResult = (Result<Message<'static>, Error>, Result<Message<'static>, Error>);
+
+/// Sends DNS queries to a name server and receives the responses.
+///
+/// Transports may be shared between threads; an implementation must allow
+/// several exchanges to be in progress at once.
+pub trait Transport: Send + Sync {
+    /// Sends a message to the name server at `addr` and waits up to `timeout`
+    /// for a response bearing the same message ID.
+    ///
+    /// The buffer should be exactly `MESSAGE_LIMIT` bytes in length.
+    fn exchange<'buf>(&self, message: &Message, addr: &SocketAddr,
+        timeout: Duration, buf: &'buf mut [u8]) -> Result<Message<'buf>, Error>;
+
+    /// Sends two messages to the name server at `addr` and waits up to
+    /// `timeout` for both responses.
+    ///
+    /// The default implementation performs each exchange in turn.
+    fn exchange_pair(&self, first: &Message, second: &Message, addr: &SocketAddr,
+            timeout: Duration) -> PairResult {
+        let mut buf = [0; MESSAGE_LIMIT];
+        let first = self.exchange(first, addr, timeout, &mut buf).map(Message::into_owned);
+        let second = self.exchange(second, addr, timeout, &mut buf).map(Message::into_owned);
+        (first, second)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn exchange<'buf>(&self, message: &Message, addr: &SocketAddr,
+            timeout: Duration, buf: &'buf mut [u8]) -> Result<Message<'buf>, Error> {
+        (**self).exchange(message, addr, timeout, buf)
+    }
+
+    fn exchange_pair(&self, first: &Message, second: &Message, addr: &SocketAddr,
+            timeout: Duration) -> PairResult {
+        (**self).exchange_pair(first, second, addr, timeout)
+    }
+}
+
+/// Exchanges messages over UDP using a `DnsSocket`.
+///
+/// A single socket is shared by all exchanges. Whichever thread is waiting
+/// reads from the socket and hands each response to the exchange it belongs
+/// to, matching on the message ID, question, and name server address.
+pub struct UdpTransport {
+    sock: DnsSocket,
+    state: Mutex<DispatchState>,
+    cond: Condvar,
+}
+
+struct DispatchState {
+    /// Whether a thread is currently reading from the socket
+    reading: bool,
+    /// Ticket value to be assigned to the next pending query
+    next_ticket: u64,
+    /// Queries awaiting a response
+    pending: Vec<PendingQuery>,
+}
+
+struct PendingQuery {
+    ticket: u64,
+    id: u16,
+    addr: SocketAddr,
+    question: Vec<Question>,
+    response: Option<Vec<u8>>,
+}
+
+impl UdpTransport {
+    /// Constructs a `UdpTransport` which sends messages on the given socket.
+    pub fn new(sock: DnsSocket) -> UdpTransport {
+        UdpTransport{
+            sock: sock,
+            state: Mutex::new(DispatchState{
+                reading: false,
+                next_ticket: 0,
+                pending: Vec::new(),
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// Returns a reference to the wrapped `DnsSocket`.
+    pub fn get(&self) -> &DnsSocket {
+        &self.sock
+    }
+
+    fn register(&self, message: &Message, addr: &SocketAddr) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.next_ticket;
+
+        state.next_ticket += 1;
+        state.pending.push(PendingQuery{
+            ticket: ticket,
+            id: message.header.id,
+            addr: *addr,
+            question: message.question.clone(),
+            response: None,
+        });
+
+        ticket
+    }
+
+    /// Sends a message registered as the query identified by `ticket`.
+    fn send(&self, message: &Message, addr: &SocketAddr, ticket: u64) -> Result<(), Error> {
+        let res = self.sock.send_message(message, addr);
+
+        if res.is_err() {
+            self.state.lock().unwrap().pending.retain(|p| p.ticket != ticket);
+        }
+
+        res
+    }
+
+    /// Waits for the response to the query identified by `ticket`,
+    /// reading from the socket if no other thread is doing so.
+    fn wait_response(&self, ticket: u64, deadline: Instant) -> Result<Vec<u8>, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            let pos = state.pending.iter().position(|p| p.ticket == ticket)
+                .expect("pending query removed");
+
+            if state.pending[pos].response.is_some() {
+                return Ok(state.pending.remove(pos).response.unwrap());
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                state.pending.remove(pos);
+                return Err(Error::IoError(io::Error::new(
+                    io::ErrorKind::TimedOut, "timed out waiting for response")));
+            }
+
+            if state.reading {
+                state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
+                continue;
+            }
+
+            state.reading = true;
+            drop(state);
+
+            let res = self.recv_packet(deadline - now);
+
+            state = self.state.lock().unwrap();
+            state.reading = false;
+            self.cond.notify_all();
+
+            match res {
+                Ok((addr, data)) => dispatch(&mut state, &addr, data),
+                Err(ref e) if e.is_timeout() => (),
+                Err(e) => {
+                    state.pending.retain(|p| p.ticket != ticket);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn recv_packet(&self, timeout: Duration) -> Result<(SocketAddr, Vec<u8>), Error> {
+        let mut buf = [0; MESSAGE_LIMIT];
+
+        try!(self.sock.get().set_read_timeout(Some(timeout)));
+        let (n, addr) = try!(self.sock.get().recv_from(&mut buf));
+
+        Ok((addr, buf[..n].to_vec()))
+    }
+}
+
+/// Delivers a received packet to the pending query it answers.
+/// Packets which do not answer any pending query are discarded.
+fn dispatch(state: &mut DispatchState, addr: &SocketAddr, data: Vec<u8>) {
+    let pos = match Message::decode(&data) {
+        Ok(msg) => {
+            if msg.header.qr != Qr::Response {
+                return;
+            }
+
+            state.pending.iter().position(|p| p.response.is_none() &&
+                p.id == msg.header.id &&
+                socket_address_equal(&p.addr, addr) &&
+                question_matches(&p.question, &msg.question))
+        }
+        Err(e) => {
+            info!("discarding invalid message from {}: {}", addr, e);
+            return;
+        }
+    };
+
+    match pos {
+        Some(pos) => state.pending[pos].response = Some(data),
+        None => info!("discarding unexpected message from {}", addr)
+    }
+}
+
+/// Returns whether the question section of a response matches that of the
+/// query. Some servers omit the question from error responses, so an empty
+/// question section is accepted.
+pub(crate) fn question_matches(query: &[Question], response: &[Question]) -> bool {
+    response.is_empty() || (query.len() == response.len() &&
+        query.iter().zip(response).all(|(a, b)| {
+            a.q_type == b.q_type && a.q_class == b.q_class &&
+                a.name.trim_end_matches('.')
+                    .eq_ignore_ascii_case(b.name.trim_end_matches('.'))
+        }))
+}
+
+impl Transport for UdpTransport {
+    fn exchange<'buf>(&self, message: &Message, addr: &SocketAddr,
+            timeout: Duration, buf: &'buf mut [u8]) -> Result<Message<'buf>, Error> {
+        let deadline = Instant::now() + timeout;
+        let ticket = self.register(message, addr);
+
+        try!(self.send(message, addr, ticket));
+        let data = try!(self.wait_response(ticket, deadline));
+
+        if data.len() > buf.len() {
+            return Err(Error::IoError(io::Error::new(io::ErrorKind::InvalidInput,
+                "buffer too small for message")));
+        }
+
+        let buf = &mut buf[..data.len()];
+        buf.copy_from_slice(&data);
+
+        Ok(try!(Message::decode(buf)))
+    }
+
+    /// Sends both messages before waiting for either response. While one
+    /// response is awaited, the other is received and held until wanted.
+    fn exchange_pair(&self, first: &Message, second: &Message, addr: &SocketAddr,
+            timeout: Duration) -> PairResult {
+        let deadline = Instant::now() + timeout;
+        let tickets = (self.register(first, addr), self.register(second, addr));
+        let sent = (self.send(first, addr, tickets.0), self.send(second, addr, tickets.1));
+
+        let receive = |ticket| -> Result<Message<'static>, Error> {
+            let data = try!(self.wait_response(ticket, deadline));
+            Ok(try!(Message::decode(&data)).into_owned())
+        };
+
+        (sent.0.and_then(|()| receive(tickets.0)), sent.1.and_then(|()| receive(tickets.1)))
+    }
+}
+
+/// Exchanges messages over TCP, using a new `DnsStream` for each exchange.
+pub struct TcpTransport;
+
+impl TcpTransport {
+    /// Constructs a `TcpTransport`.
+    pub fn new() -> TcpTransport {
+        TcpTransport
+    }
+}
+
+impl Default for TcpTransport {
+    fn default() -> TcpTransport {
+        TcpTransport::new()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn exchange<'buf>(&self, message: &Message, addr: &SocketAddr,
+            timeout: Duration, buf: &'buf mut [u8]) -> Result<Message<'buf>, Error> {
+        let stream = try!(DnsStream::connect_timeout(addr, timeout));
+        try!(stream.get().set_read_timeout(Some(timeout)));
+        try!(stream.get().set_write_timeout(Some(timeout)));
+
+        try!(stream.send_message(message));
+        let msg = try!(stream.recv_message(buf));
+
+        if msg.header.id != message.header.id || msg.header.qr != Qr::Response {
+            return Err(Error::DecodeError(DecodeError::InvalidMessage));
+        }
+
+        Ok(msg)
+    }
+}
+
+/// Produces a canned response to a query sent through a `MockTransport`.
+///
+/// Returning `None` simulates a name server which does not respond.
+pub type MockHandler = Box<dyn Fn(&Message, &SocketAddr)
+    -> Option<Message<'static>> + Send + Sync>;
+
+/// An in-memory transport which answers queries using a handler function
+/// and records each query sent.
+///
+/// This allows `DnsResolver` behavior to be tested without network access.
+pub struct MockTransport {
+    handler: MockHandler,
+    queries: Mutex<Vec<(SocketAddr, Message<'static>)>>,
+}
+
+impl MockTransport {
+    /// Constructs a `MockTransport` which answers queries using the given
+    /// function. The ID of each response is set to that of the query.
+    pub fn new<F>(handler: F) -> MockTransport
+            where F: Fn(&Message, &SocketAddr) -> Option<Message<'static>>
+                + Send + Sync + 'static {
+        MockTransport{
+            handler: Box::new(handler),
+            queries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns each query sent through the transport, along with the address
+    /// of the name server to which it was sent.
+    pub fn queries(&self) -> Vec<(SocketAddr, Message<'static>)> {
+        self.queries.lock().unwrap().clone()
+    }
+}
+
+impl Transport for MockTransport {
+    fn exchange<'buf>(&self, message: &Message, addr: &SocketAddr,
+            _timeout: Duration, buf: &'buf mut [u8]) -> Result<Message<'buf>, Error> {
+        self.queries.lock().unwrap().push((*addr, message.clone().into_owned()));
+
+        match (self.handler)(message, addr) {
+            Some(mut reply) => {
+                reply.header.id = message.header.id;
+
+                let data = try!(reply.encode(buf));
+                Ok(try!(Message::decode(data)))
+            }
+            None => Err(Error::IoError(io::Error::new(
+                io::ErrorKind::TimedOut, "timed out waiting for response")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use message::{Message, Qr, Question, MESSAGE_LIMIT};
+    use record::{Class, RecordType};
+    use socket::DnsSocket;
+    use super::{Transport, UdpTransport};
+
+    #[test]
+    fn test_udp_dispatch() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // Collect two queries, then answer them in reverse order.
+        thread::spawn(move || {
+            let mut received = Vec::new();
+
+            for _ in 0..2 {
+                let mut buf = [0; MESSAGE_LIMIT];
+                let (n, peer) = server.recv_from(&mut buf).unwrap();
+                received.push((buf[..n].to_vec(), peer));
+            }
+
+            for &(ref data, peer) in received.iter().rev() {
+                let mut msg = Message::decode(data).unwrap();
+                msg.header.qr = Qr::Response;
+
+                let mut buf = [0; MESSAGE_LIMIT];
+                server.send_to(msg.encode(&mut buf).unwrap(), peer).unwrap();
+            }
+        });
+
+        let transport = Arc::new(UdpTransport::new(
+            DnsSocket::bind("127.0.0.1:0").unwrap()));
+
+        let threads = ["foo.com.", "bar.org."].iter().map(|&name| {
+            let transport = transport.clone();
+
+            thread::spawn(move || {
+                let mut msg = Message::new();
+                msg.question.push(Question::new(name.to_owned(),
+                    RecordType::A, Class::Internet));
+
+                let mut buf = [0; MESSAGE_LIMIT];
+                let reply = transport.exchange(&msg, &addr,
+                    Duration::from_secs(5), &mut buf).unwrap();
+
+                assert_eq!(reply.header.id, msg.header.id);
+                assert_eq!(reply.question, msg.question);
+            })
+        }).collect::<Vec<_>>();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_udp_pair() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // Answer the second query only after receiving both
+        thread::spawn(move || {
+            let mut received = Vec::new();
+
+            for _ in 0..2 {
+                let mut buf = [0; MESSAGE_LIMIT];
+                let (n, peer) = server.recv_from(&mut buf).unwrap();
+                received.push((buf[..n].to_vec(), peer));
+            }
+
+            for &(ref data, peer) in received.iter().rev() {
+                let mut msg = Message::decode(data).unwrap();
+                msg.header.qr = Qr::Response;
+
+                let mut buf = [0; MESSAGE_LIMIT];
+                server.send_to(msg.encode(&mut buf).unwrap(), peer).unwrap();
+            }
+        });
+
+        let transport = UdpTransport::new(DnsSocket::bind("127.0.0.1:0").unwrap());
+
+        let msgs = ["foo.com.", "bar.org."].iter().map(|&name| {
+            let mut msg = Message::new();
+            msg.question.push(Question::new(name.to_owned(), RecordType::A, Class::Internet));
+            msg
+        }).collect::<Vec<_>>();
+
+        let (a, b) = transport.exchange_pair(&msgs[0], &msgs[1],
+            &addr, Duration::from_secs(5));
+
+        for (reply, msg) in vec![a.unwrap(), b.unwrap()].into_iter().zip(&msgs) {
+            assert_eq!(reply.header.id, msg.header.id);
+            assert_eq!(reply.question, msg.question);
+        }
+    }
+}
@@ -0,0 +1,627 @@
+//! Zero-copy views of encoded DNS messages
+//!
+//! A `MessageView` validates an encoded message once, then yields its
+//! questions and records as views which borrow from the message buffer.
+//! Names are read only when requested and nothing is allocated unless
+//! data is explicitly converted to an owned form.
+
+use std::fmt;
+
+use message::{push_label, DecodeError, Edns, Header, MsgReader, Question,
+    RCode, Resource, NAME_LIMIT};
+use name::{escape_label, Name};
+use record::{AnyRecord, Class, Record, RecordType};
+
+/// A borrowed view of an encoded DNS message
+#[derive(Copy, Clone, Debug)]
+pub struct MessageView<'a> {
+    data: &'a [u8],
+    header: Header,
+    qd_count: u16,
+    an_count: u16,
+    ns_count: u16,
+    ar_count: u16,
+    /// Offsets of the answer, authority, and additional sections
+    sections: [usize; 3],
+    /// Offset of the `OPT` pseudo-record, if present
+    opt: Option<usize>,
+}
+
+impl<'a> MessageView<'a> {
+    /// Validates an encoded message and returns a view of its contents.
+    ///
+    /// The same checks are performed as in `Message::decode`, but no
+    /// names or records are copied.
+    pub fn new(data: &'a [u8]) -> Result<MessageView<'a>, DecodeError> {
+        let mut r = MsgReader::new(data);
+        let hdr = try!(r.read_header());
+        let mut header = hdr.to_header();
+
+        for _ in 0..hdr.qd_count {
+            try!(read_question(&mut r));
+        }
+
+        let an_start = r.position();
+
+        for _ in 0..hdr.an_count {
+            try!(read_record(&mut r));
+        }
+
+        let ns_start = r.position();
+
+        for _ in 0..hdr.ns_count {
+            try!(read_record(&mut r));
+        }
+
+        let ar_start = r.position();
+        let mut opt = None;
+
+        for _ in 0..hdr.ar_count {
+            let pos = r.position();
+            let rr = try!(read_record(&mut r));
+
+            if rr.r_type == RecordType::Opt {
+                // A message may contain at most one OPT record,
+                // which must be owned by the root domain.
+                if opt.is_some() || !rr.name.is_root() {
+                    return Err(DecodeError::InvalidMessage);
+                }
+
+                try!(check_options(rr.rdata()));
+
+                let ext_rcode = (rr.ttl >> 24) as u16;
+                header.rcode = RCode::from_u16(
+                    (ext_rcode << 4) | header.rcode.to_u16());
+                opt = Some(pos);
+            }
+        }
+
+        try!(r.finish());
+
+        Ok(MessageView{
+            data: data,
+            header: header,
+            qd_count: hdr.qd_count,
+            an_count: hdr.an_count,
+            ns_count: hdr.ns_count,
+            ar_count: hdr.ar_count,
+            sections: [an_start, ns_start, ar_start],
+            opt: opt,
+        })
+    }
+
+    /// Returns the message header.
+    ///
+    /// If the message contains EDNS data, the response code includes
+    /// the upper bits of the extended response code.
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    /// Returns the encoded message.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns an iterator over the question section.
+    pub fn questions(&self) -> Questions<'a> {
+        Questions{
+            r: MsgReader::with_offset(self.data, 12),
+            remaining: self.qd_count,
+        }
+    }
+
+    /// Returns an iterator over the answer section.
+    pub fn answers(&self) -> Records<'a> {
+        self.records_from(self.sections[0], self.an_count as usize)
+    }
+
+    /// Returns an iterator over the authority section.
+    pub fn authority(&self) -> Records<'a> {
+        self.records_from(self.sections[1], self.ns_count as usize)
+    }
+
+    /// Returns an iterator over the additional section.
+    ///
+    /// An `OPT` pseudo-record is not included.
+    pub fn additional(&self) -> Records<'a> {
+        self.records_from(self.sections[2], self.ar_count as usize)
+    }
+
+    /// Returns an iterator over the records in all sections.
+    ///
+    /// An `OPT` pseudo-record is not included.
+    pub fn records(&self) -> Records<'a> {
+        self.records_from(self.sections[0],
+            self.an_count as usize + self.ns_count as usize + self.ar_count as usize)
+    }
+
+    /// Returns the `OPT` pseudo-record, if present.
+    pub fn opt(&self) -> Option<RecordView<'a>> {
+        self.opt.and_then(|pos| read_record(
+            &mut MsgReader::with_offset(self.data, pos)).ok())
+    }
+
+    /// Decodes EDNS data from the `OPT` pseudo-record, if present.
+    pub fn edns(&self) -> Option<Edns> {
+        self.opt().and_then(|rr| Edns::decode(rr.r_class, rr.ttl,
+            MsgReader::new(rr.rdata())).ok())
+    }
+
+    fn records_from(&self, pos: usize, count: usize) -> Records<'a> {
+        Records{
+            r: MsgReader::with_offset(self.data, pos),
+            remaining: count,
+            opt: self.opt,
+        }
+    }
+}
+
+/// Yields `QuestionView` items from a `MessageView`.
+pub struct Questions<'a> {
+    r: MsgReader<'a>,
+    remaining: u16,
+}
+
+impl<'a> Iterator for Questions<'a> {
+    type Item = QuestionView<'a>;
+
+    fn next(&mut self) -> Option<QuestionView<'a>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+        read_question(&mut self.r).ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+/// Yields `RecordView` items from a `MessageView`.
+pub struct Records<'a> {
+    r: MsgReader<'a>,
+    remaining: usize,
+    opt: Option<usize>,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = RecordView<'a>;
+
+    fn next(&mut self) -> Option<RecordView<'a>> {
+        while self.remaining != 0 {
+            self.remaining -= 1;
+
+            let pos = self.r.position();
+            let rr = read_record(&mut self.r).ok();
+
+            if Some(pos) != self.opt {
+                return rr;
+            }
+        }
+
+        None
+    }
+}
+
+/// A borrowed view of a question item
+#[derive(Copy, Clone, Debug)]
+pub struct QuestionView<'a> {
+    /// Query name
+    pub name: NameView<'a>,
+    /// Query type
+    pub q_type: RecordType,
+    /// Query class
+    pub q_class: Class,
+}
+
+impl<'a> QuestionView<'a> {
+    /// Returns an owned `Question`.
+    pub fn to_question(&self) -> Question {
+        Question::new(self.name.read_name(), self.q_type, self.q_class)
+    }
+}
+
+/// A borrowed view of a resource record item
+#[derive(Copy, Clone, Debug)]
+pub struct RecordView<'a> {
+    /// Resource name
+    pub name: NameView<'a>,
+    /// Resource type
+    pub r_type: RecordType,
+    /// Resource class
+    pub r_class: Class,
+    /// Time-to-live
+    pub ttl: u32,
+    data: &'a [u8],
+    /// Beginning of rdata within `data`
+    offset: usize,
+    /// Length of rdata
+    len: usize,
+}
+
+impl<'a> RecordView<'a> {
+    /// Returns resource data.
+    pub fn rdata(&self) -> &'a [u8] {
+        &self.data[self.offset..self.offset + self.len]
+    }
+
+    /// Decodes resource data into the given `Record` type.
+    pub fn read_rdata<R: Record>(&self) -> Result<R, DecodeError> {
+        let mut r = MsgReader::with_offset(
+            &self.data[..self.offset + self.len], self.offset);
+        let res = try!(Record::decode(&mut r));
+        try!(r.finish());
+        Ok(res)
+    }
+
+    /// Decodes resource data of any type, as given by `r_type`.
+    pub fn read_any(&self) -> Result<AnyRecord, DecodeError> {
+        let mut r = MsgReader::with_offset(
+            &self.data[..self.offset + self.len], self.offset);
+        let res = try!(AnyRecord::decode(self.r_type, &mut r));
+        try!(r.finish());
+        Ok(res)
+    }
+
+    /// Returns a `Resource` borrowing resource data from the message.
+    pub fn to_resource(&self) -> Resource<'a> {
+        Resource::borrowed(self.name.read_name(), self.r_type, self.r_class,
+            self.ttl, &self.data[..self.offset + self.len], self.offset)
+    }
+}
+
+/// A borrowed view of a name within an encoded message
+///
+/// Labels are read, following compression pointers, only when requested.
+#[derive(Copy, Clone, Debug)]
+pub struct NameView<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> NameView<'a> {
+    /// Returns an iterator over the labels of the name, beginning with the
+    /// leftmost label. The root label is not included.
+    pub fn labels(&self) -> Labels<'a> {
+        Labels::new(self.data, self.offset)
+    }
+
+    /// Returns whether this is the root name.
+    pub fn is_root(&self) -> bool {
+        self.labels().next().is_none()
+    }
+
+    /// Returns the name as a `String`, in the form returned by
+    /// `MsgReader::read_name`.
+    pub fn read_name(&self) -> String {
+        let mut res = String::new();
+
+        for label in self.labels() {
+            push_label(&mut res, label);
+            res.push('.');
+        }
+
+        if res.is_empty() {
+            res.push('.');
+        }
+
+        res
+    }
+
+    /// Returns an owned `Name`.
+    pub fn to_name(&self) -> Name {
+        Name::from_raw_labels(self.labels().map(|l| l.to_vec()).collect())
+    }
+}
+
+impl<'a> fmt::Display for NameView<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return f.write_str(".");
+        }
+
+        for label in self.labels() {
+            try!(f.write_str(&escape_label(label)));
+            try!(f.write_str("."));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> PartialEq<Name> for NameView<'a> {
+    fn eq(&self, other: &Name) -> bool {
+        let mut labels = self.labels();
+
+        other.labels().iter().all(|b| match labels.next() {
+            Some(a) => a.eq_ignore_ascii_case(b),
+            None => false
+        }) && labels.next().is_none()
+    }
+}
+
+/// Yields the labels of a name within an encoded message, following
+/// compression pointers.
+///
+/// Iteration stops if the name is invalid.
+#[derive(Clone, Debug)]
+pub struct Labels<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Pointers must refer to offsets before this position
+    limit: usize,
+    /// Position following the name in the message
+    end: Option<usize>,
+    total_read: usize,
+    error: Option<DecodeError>,
+    done: bool,
+}
+
+impl<'a> Labels<'a> {
+    pub(crate) fn new(data: &'a [u8], offset: usize) -> Labels<'a> {
+        Labels{
+            data: data,
+            pos: offset,
+            limit: offset,
+            end: None,
+            total_read: 0,
+            error: None,
+            done: false,
+        }
+    }
+
+    /// Reads any remaining labels and returns the position following
+    /// the name in the message.
+    pub(crate) fn finish(mut self) -> Result<usize, DecodeError> {
+        while self.next().is_some() {}
+
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.end.unwrap_or(self.pos))
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, DecodeError> {
+        match self.data.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => Err(DecodeError::ShortMessage)
+        }
+    }
+
+    fn read_label(&mut self) -> Result<Option<&'a [u8]>, DecodeError> {
+        loop {
+            let len = try!(self.read_byte());
+
+            if len == 0 {
+                if self.total_read + 1 > NAME_LIMIT {
+                    return Err(DecodeError::InvalidName);
+                }
+                if self.end.is_none() {
+                    self.end = Some(self.pos);
+                }
+                return Ok(None);
+            }
+
+            // If the length flag starts with "11", it will be followed by a
+            // pointer reference. For more information see RFC 1035 section
+            // 4.1.4 (Message compression).
+            // Prefix "00" means "no compression". Prefixes 0x01 and 0x10
+            // are reserved for future use.
+            let compressed = match len >> 6 {
+                0b11 => true,
+                0b00 => false,
+                _ => return Err(DecodeError::InvalidMessage),
+            };
+
+            if compressed {
+                // The beginning of a pointer reference. 14 bit denote the
+                // offset from the start of the message.
+                let hi = (len & 0b00111111) as usize;
+                let lo = try!(self.read_byte()) as usize;
+                let offset = (hi << 8) | lo;
+
+                // To prevent an infinite loop, we require each pointer to
+                // point before the labels which contain it.
+                if offset >= self.limit {
+                    return Err(DecodeError::InvalidName);
+                }
+
+                if self.end.is_none() {
+                    self.end = Some(self.pos);
+                }
+
+                self.pos = offset;
+                self.limit = offset;
+                continue;
+            }
+
+            let len = len as usize;
+
+            if self.total_read + 1 + len > NAME_LIMIT {
+                return Err(DecodeError::InvalidName);
+            }
+            self.total_read += 1 + len;
+
+            if self.pos + len > self.data.len() {
+                return Err(DecodeError::ShortMessage);
+            }
+
+            let label = &self.data[self.pos..self.pos + len];
+            self.pos += len;
+
+            return Ok(Some(label));
+        }
+    }
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_label() {
+            Ok(Some(label)) => Some(label),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.error = Some(e);
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+fn read_name<'a>(r: &mut MsgReader<'a>) -> Result<NameView<'a>, DecodeError> {
+    let name = NameView{data: r.data(), offset: r.position()};
+    let end = try!(name.labels().finish());
+    r.set_position(end);
+    Ok(name)
+}
+
+fn read_question<'a>(r: &mut MsgReader<'a>) -> Result<QuestionView<'a>, DecodeError> {
+    let name = try!(read_name(r));
+    let q_type = try!(r.read_u16());
+    let q_class = try!(r.read_u16());
+
+    Ok(QuestionView{
+        name: name,
+        q_type: RecordType::from_u16(q_type),
+        q_class: Class::from_u16(q_class),
+    })
+}
+
+fn read_record<'a>(r: &mut MsgReader<'a>) -> Result<RecordView<'a>, DecodeError> {
+    let name = try!(read_name(r));
+    let r_type = try!(r.read_u16());
+    let r_class = try!(r.read_u16());
+    let ttl = try!(r.read_u32());
+    let len = try!(r.read_u16()) as usize;
+
+    if r.remaining() < len {
+        return Err(DecodeError::ShortMessage);
+    }
+
+    let offset = r.position();
+    r.consume(len as u64);
+
+    Ok(RecordView{
+        name: name,
+        r_type: RecordType::from_u16(r_type),
+        r_class: Class::from_u16(r_class),
+        ttl: ttl,
+        data: r.data(),
+        offset: offset,
+        len: len,
+    })
+}
+
+/// Checks the structure of EDNS options without decoding them.
+fn check_options(rdata: &[u8]) -> Result<(), DecodeError> {
+    let mut r = MsgReader::new(rdata);
+
+    while r.remaining() != 0 {
+        try!(r.read_u16());
+        let len = try!(r.read_u16()) as usize;
+
+        if r.remaining() < len {
+            return Err(DecodeError::ShortMessage);
+        }
+
+        r.consume(len as u64);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use message::{DecodeError, Edns, Message, Question, Resource, MESSAGE_LIMIT};
+    use name::Name;
+    use record::{A, CName, Class, RecordType};
+    use super::MessageView;
+
+    fn sample_message() -> Message<'static> {
+        let mut msg = Message::with_id(0x1234);
+        msg.question.push(Question::new("www.Example.com.".to_owned(),
+            RecordType::A, Class::Internet));
+
+        let mut rr = Resource::new("www.Example.com.".to_owned(),
+            RecordType::CName, Class::Internet, 300);
+        rr.write_rdata(&CName{name: "host.example.com.".to_owned()}).unwrap();
+        msg.answer.push(rr);
+
+        let mut rr = Resource::new("host.example.com.".to_owned(),
+            RecordType::A, Class::Internet, 60);
+        rr.write_rdata(&A{address: Ipv4Addr::new(192, 0, 2, 1)}).unwrap();
+        msg.answer.push(rr);
+
+        msg.edns = Some(Edns::new());
+        msg
+    }
+
+    #[test]
+    fn test_view() {
+        let msg = sample_message();
+        let mut buf = [0; MESSAGE_LIMIT];
+        let bytes = msg.encode(&mut buf).unwrap();
+
+        let view = MessageView::new(bytes).unwrap();
+        let decoded = Message::decode(bytes).unwrap();
+
+        assert_eq!(view.header(), decoded.header);
+        assert_eq!(view.edns(), decoded.edns);
+
+        let q = view.questions().collect::<Vec<_>>();
+        assert_eq!(q.len(), 1);
+        assert_eq!(q[0].to_question(), decoded.question[0]);
+        assert!(q[0].name == "WWW.example.COM".parse::<Name>().unwrap());
+        assert_eq!(q[0].name.to_string(), "www.Example.com.");
+
+        let answers = view.answers().collect::<Vec<_>>();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[0].read_rdata::<CName>().unwrap().name, "host.example.com.");
+        assert_eq!(answers[1].read_rdata::<A>().unwrap().address,
+            Ipv4Addr::new(192, 0, 2, 1));
+        assert_eq!(answers[1].rdata(), &[192, 0, 2, 1]);
+
+        let resources = view.records().map(|rr| rr.to_resource()).collect::<Vec<_>>();
+        assert_eq!(resources, decoded.answer);
+
+        assert_eq!(view.authority().count(), 0);
+        assert_eq!(view.additional().count(), 0);
+        assert_eq!(view.opt().unwrap().r_type, RecordType::Opt);
+    }
+
+    #[test]
+    fn test_view_errors() {
+        let msg = sample_message();
+        let mut buf = [0; MESSAGE_LIMIT];
+        let len = msg.encode(&mut buf).unwrap().len();
+
+        // Extra data after the last record
+        assert_eq!(MessageView::new(&buf[..len + 1]).err(),
+            Some(DecodeError::ExtraneousData));
+        assert_eq!(MessageView::new(&buf[..len - 1]).err(),
+            Some(DecodeError::ShortMessage));
+
+        // Question name pointing forward, to itself
+        let data = b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+            \xc0\x0c\x00\x01\x00\x01".to_vec();
+        assert_eq!(MessageView::new(&data).err(), Some(DecodeError::InvalidName));
+        assert_eq!(Message::decode(&data).err(), Some(DecodeError::InvalidName));
+    }
+}
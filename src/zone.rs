@@ -0,0 +1,510 @@
+//! Master zone file parser and writer, as described in RFC 1035, section 5
+//!
+//! Directives `$ORIGIN`, `$TTL` (RFC 2308), and `$INCLUDE` are supported.
+//! Records may span multiple lines using parentheses and may omit their
+//! owner, TTL, or class, which are then taken from the previous record.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use message::{EncodeError, Resource};
+use name::Name;
+use record::{parse_ttl, tokenize, AnyRecord, Class, Fields, ParseError, RecordType, Token};
+
+/// Maximum depth of nested `$INCLUDE` directives
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Represents the contents of a zone file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Zone {
+    /// Origin of the zone; an absolute name
+    pub origin: String,
+    /// Records, in the order in which they appear; all names are absolute
+    pub records: Vec<Resource<'static>>,
+}
+
+impl Zone {
+    /// Writes the zone in canonical text form.
+    ///
+    /// Each record is written on a single line with its absolute owner
+    /// name, TTL, and class. Records are ordered by owner name, in the
+    /// canonical order of RFC 4034; an `SOA` record is written first among
+    /// records of the same owner.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "{}", self)
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut records = self.records.iter().collect::<Vec<_>>();
+
+        records.sort_by_cached_key(|rr| (Name::from_idna(&rr.name).ok(),
+            rr.r_type != RecordType::Soa));
+
+        try!(writeln!(f, "$ORIGIN {}", self.origin));
+
+        for rr in records {
+            try!(writeln!(f, "{}", rr));
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads a zone from the given file, with the given initial origin.
+///
+/// Relative `$INCLUDE` paths are interpreted relative to the directory
+/// containing the file.
+pub fn load_zone(path: &Path, origin: &str) -> io::Result<Zone> {
+    let mut p = Parser::new(origin);
+    try!(p.load(path));
+    Ok(p.finish())
+}
+
+/// Parses a zone in master file format, with the given initial origin.
+///
+/// Relative `$INCLUDE` paths are interpreted relative to the current
+/// directory.
+pub fn parse_zone(data: &str, origin: &str) -> io::Result<Zone> {
+    let mut p = Parser::new(origin);
+    try!(p.parse(data, None));
+    Ok(p.finish())
+}
+
+struct Parser {
+    zone_origin: String,
+    origin: String,
+    /// Default TTL, set by `$TTL`
+    default_ttl: Option<u32>,
+    last_ttl: Option<u32>,
+    last_owner: Option<String>,
+    last_class: Class,
+    records: Vec<Resource<'static>>,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(origin: &str) -> Parser {
+        let origin = if origin.ends_with('.') {
+            origin.to_owned()
+        } else {
+            format!("{}.", origin)
+        };
+
+        Parser{
+            zone_origin: origin.clone(),
+            origin: origin,
+            default_ttl: None,
+            last_ttl: None,
+            last_owner: None,
+            last_class: Class::Internet,
+            records: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn finish(self) -> Zone {
+        Zone{
+            origin: self.zone_origin,
+            records: self.records,
+        }
+    }
+
+    fn load(&mut self, path: &Path) -> io::Result<()> {
+        let mut f = try!(File::open(path));
+        let mut buf = String::new();
+
+        try!(f.read_to_string(&mut buf));
+        self.parse(&buf, path.parent())
+    }
+
+    fn parse(&mut self, data: &str, dir: Option<&Path>) -> io::Result<()> {
+        for entry in try!(lex(data)) {
+            try!(self.entry(&entry, dir).map_err(|e| io::Error::new(e.kind(),
+                format!("line {}: {}", entry.line, e))));
+        }
+
+        Ok(())
+    }
+
+    fn entry(&mut self, entry: &Entry, dir: Option<&Path>) -> io::Result<()> {
+        let tokens = &entry.tokens;
+        let first = tokens[0].text;
+
+        if !entry.blank_owner && !tokens[0].quoted && first.starts_with('$') {
+            return self.directive(tokens, dir);
+        }
+
+        let mut rest = &tokens[..];
+
+        let owner = if entry.blank_owner {
+            match self.last_owner {
+                Some(ref owner) => owner.clone(),
+                None => return Err(invalid_data("missing owner name"))
+            }
+        } else {
+            rest = &rest[1..];
+            try!(self.name(tokens[0]))
+        };
+
+        let mut ttl = None;
+        let mut class = None;
+
+        // TTL and class may appear in either order
+        while let Some(tok) = rest.first() {
+            if ttl.is_none() {
+                if let Some(n) = parse_ttl(tok.text) {
+                    ttl = Some(n);
+                    rest = &rest[1..];
+                    continue;
+                }
+            }
+            if class.is_none() {
+                if let Ok(c) = tok.text.parse::<Class>() {
+                    class = Some(c);
+                    rest = &rest[1..];
+                    continue;
+                }
+            }
+            break;
+        }
+
+        let r_type = match rest.first() {
+            Some(tok) => try!(tok.text.parse::<RecordType>().map_err(invalid_data)),
+            None => return Err(invalid_data("missing record type"))
+        };
+
+        let ttl = match ttl.or(self.default_ttl).or(self.last_ttl) {
+            Some(ttl) => ttl,
+            None => return Err(invalid_data("missing TTL"))
+        };
+        let class = class.unwrap_or(self.last_class);
+
+        let mut rr = Resource::new(owner.clone(), r_type, class, ttl);
+        let mut fields = Fields::new(rest[1..].to_vec(), Some(&self.origin));
+
+        try!(parse_rdata(&mut rr, &mut fields).map_err(invalid_data));
+        try!(fields.finish().map_err(invalid_data));
+
+        self.last_owner = Some(owner);
+        self.last_ttl = Some(ttl);
+        self.last_class = class;
+        self.records.push(rr);
+
+        Ok(())
+    }
+
+    fn directive(&mut self, tokens: &[Token], dir: Option<&Path>) -> io::Result<()> {
+        let args = &tokens[1..];
+        let directive = tokens[0].text;
+
+        if directive.eq_ignore_ascii_case("$ORIGIN") {
+            if args.len() != 1 {
+                return Err(invalid_data("expected origin name"));
+            }
+            self.origin = try!(self.name(args[0]));
+        } else if directive.eq_ignore_ascii_case("$TTL") {
+            match args.first().and_then(|tok| parse_ttl(tok.text)) {
+                Some(ttl) if args.len() == 1 => self.default_ttl = Some(ttl),
+                _ => return Err(invalid_data("expected TTL value"))
+            }
+        } else if directive.eq_ignore_ascii_case("$INCLUDE") {
+            if args.is_empty() || args.len() > 2 {
+                return Err(invalid_data("expected file name"));
+            }
+            if self.depth == MAX_INCLUDE_DEPTH {
+                return Err(invalid_data("too many nested $INCLUDE directives"));
+            }
+
+            let path = match dir {
+                Some(dir) => dir.join(args[0].text),
+                None => Path::new(args[0].text).to_path_buf()
+            };
+
+            let saved_origin = self.origin.clone();
+
+            if let Some(&tok) = args.get(1) {
+                self.origin = try!(self.name(tok));
+            }
+
+            self.depth += 1;
+            let res = self.load(&path);
+            self.depth -= 1;
+
+            // The origin reverts at the end of an included file
+            self.origin = saved_origin;
+            try!(res);
+        } else {
+            return Err(invalid_data(format!("unknown directive: {}", directive)));
+        }
+
+        Ok(())
+    }
+
+    fn name(&self, tok: Token) -> io::Result<String> {
+        Fields::new(vec![tok], Some(&self.origin)).name().map_err(invalid_data)
+    }
+}
+
+fn invalid_data<E: fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Parses record data of the type given by `rr.r_type`, writing it to `rr`.
+fn parse_rdata(rr: &mut Resource, fields: &mut Fields) -> Result<(), ParseError> {
+    let rec = try!(AnyRecord::parse_fields(rr.r_type, fields));
+    rr.write_any(&rec).map_err(|e| match e {
+        EncodeError::InvalidName => ParseError::InvalidName,
+        EncodeError::TooLong => ParseError::TooLong,
+        EncodeError::InvalidRCode |
+        EncodeError::InvalidRecord => ParseError::InvalidRecord,
+    })
+}
+
+/// A single entry of a zone file, which may span multiple lines
+struct Entry<'a> {
+    /// Line on which the entry begins
+    line: usize,
+    /// Whether the entry begins with whitespace, omitting the owner name
+    blank_owner: bool,
+    tokens: Vec<Token<'a>>,
+}
+
+/// Splits a zone file into entries, removing comments and joining lines
+/// enclosed in parentheses.
+fn lex<'a>(data: &'a str) -> io::Result<Vec<Entry<'a>>> {
+    let mut entries = Vec::new();
+    let mut line = 1;
+    let mut depth = 0;
+    let mut line_start = 0;
+    let mut entry_line = 1;
+    let mut blank_owner = false;
+    let mut tokens = Vec::new();
+
+    let mut chars = data.char_indices().peekable();
+
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\n' => {
+                line += 1;
+                line_start = i + 1;
+
+                if depth == 0 && !tokens.is_empty() {
+                    entries.push(Entry{
+                        line: entry_line,
+                        blank_owner: blank_owner,
+                        tokens: tokens,
+                    });
+                    tokens = Vec::new();
+                }
+            }
+            ';' => {
+                while let Some(&(_, ch)) = chars.peek() {
+                    if ch == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            '(' => depth += 1,
+            ')' => {
+                if depth == 0 {
+                    return Err(invalid_data(format!("line {}: unbalanced parentheses", line)));
+                }
+                depth -= 1;
+            }
+            ch if ch.is_whitespace() => (),
+            _ => {
+                if tokens.is_empty() {
+                    entry_line = line;
+                    blank_owner = i != line_start;
+                }
+
+                let start = i;
+                let mut end = data.len();
+                let mut escape = ch == '\\';
+                let quoted = ch == '"';
+
+                while let Some(&(j, ch)) = chars.peek() {
+                    if escape {
+                        escape = false;
+                    } else if quoted {
+                        if ch == '"' {
+                            chars.next();
+                            end = j + 1;
+                            break;
+                        }
+                        escape = ch == '\\';
+                    } else if ch.is_whitespace() || ch == ';' || ch == '(' || ch == ')' {
+                        end = j;
+                        break;
+                    } else {
+                        escape = ch == '\\';
+                    }
+
+                    if ch == '\n' {
+                        line += 1;
+                        line_start = j + 1;
+                    }
+                    chars.next();
+                }
+
+                let text = &data[start..end];
+                let mut toks = try!(tokenize(text).map_err(|e|
+                    invalid_data(format!("line {}: {}", line, e))));
+
+                if toks.len() != 1 {
+                    return Err(invalid_data(format!("line {}: {}", line,
+                        ParseError::InvalidString)));
+                }
+
+                tokens.push(toks.remove(0));
+            }
+        }
+    }
+
+    if depth != 0 {
+        return Err(invalid_data(format!("line {}: unbalanced parentheses", line)));
+    }
+
+    if !tokens.is_empty() {
+        entries.push(Entry{
+            line: entry_line,
+            blank_owner: blank_owner,
+            tokens: tokens,
+        });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env::temp_dir;
+    use std::fs::File;
+    use std::io::Write;
+    use std::net::Ipv4Addr;
+
+    use record::{A, CName, Class, Mx, RecordType, Soa, Txt};
+    use super::{load_zone, parse_zone};
+
+    const ZONE: &'static str = "\
+$ORIGIN example.com.
+$TTL 1h
+; Start of authority
+@   IN  SOA ns hostmaster (
+        2018010101  ; serial
+        2h          ; refresh
+        3600 1w 300 )
+    IN  NS  ns
+    IN  MX  10 mail.example.com.
+ns      A   192.0.2.1
+mail 300 IN A 192.0.2.2
+         A   192.0.2.3
+txt IN 60 TXT \"v=spf1 ; not a comment\"
+$ORIGIN sub
+www CNAME @
+";
+
+    #[test]
+    fn test_parse_zone() {
+        let zone = parse_zone(ZONE, "example.com").unwrap();
+        let rr = &zone.records;
+
+        assert_eq!(zone.origin, "example.com.");
+        assert_eq!(rr.len(), 8);
+
+        assert_eq!(rr[0].name, "example.com.");
+        assert_eq!(rr[0].r_type, RecordType::Soa);
+        assert_eq!(rr[0].ttl, 3600);
+        assert_eq!(rr[0].read_rdata::<Soa>().unwrap(), Soa{
+            mname: "ns.example.com.".to_owned(),
+            rname: "hostmaster.example.com.".to_owned(),
+            serial: 2018010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 604800,
+            minimum: 300,
+        });
+
+        assert_eq!(rr[1].name, "example.com.");
+        assert_eq!(rr[1].r_type, RecordType::Ns);
+        assert_eq!(rr[2].read_rdata::<Mx>().unwrap().exchange, "mail.example.com.");
+
+        assert_eq!(rr[3].name, "ns.example.com.");
+        assert_eq!(rr[3].r_class, Class::Internet);
+        assert_eq!(rr[3].ttl, 3600);
+
+        assert_eq!(rr[4].ttl, 300);
+        assert_eq!(rr[5].name, "mail.example.com.");
+        assert_eq!(rr[5].ttl, 3600);
+        assert_eq!(rr[5].read_rdata::<A>().unwrap().address, Ipv4Addr::new(192, 0, 2, 3));
+
+        assert_eq!(rr[6].ttl, 60);
+        assert_eq!(rr[6].read_rdata::<Txt>().unwrap().join(), b"v=spf1 ; not a comment");
+
+        assert_eq!(rr[7].name, "www.sub.example.com.");
+        assert_eq!(rr[7].read_rdata::<CName>().unwrap().name, "sub.example.com.");
+    }
+
+    #[test]
+    fn test_write_zone() {
+        let zone = parse_zone(ZONE, "example.com.").unwrap();
+        let text = zone.to_string();
+
+        assert!(text.starts_with("$ORIGIN example.com.\n\
+            example.com.\t3600\tIN\tSOA\tns.example.com. hostmaster.example.com. \
+                2018010101 7200 3600 604800 300\n\
+            example.com.\t3600\tIN\tNS\tns.example.com.\n\
+            example.com.\t3600\tIN\tMX\t10 mail.example.com.\n"));
+        assert!(text.ends_with("txt.example.com.\t60\tIN\tTXT\t\"v=spf1 ; not a comment\"\n"));
+
+        let reparsed = parse_zone(&text, "example.com.").unwrap();
+        assert_eq!(reparsed.to_string(), text);
+
+        let mut buf = Vec::new();
+        zone.write(&mut buf).unwrap();
+        assert_eq!(buf, text.as_bytes());
+    }
+
+    #[test]
+    fn test_include() {
+        let path = temp_dir().join(format!("resolve-test-{}.zone", ::std::process::id()));
+        File::create(&path).unwrap().write_all(b"host 30 A 192.0.2.9\n").unwrap();
+
+        let zone = parse_zone(&format!("\
+$TTL 60
+$INCLUDE {} sub.example.com.
+after A 192.0.2.10
+", path.display()), "example.com.").unwrap();
+
+        let zone2 = load_zone(&path, "example.net.").unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(zone.records[0].name, "host.sub.example.com.");
+        assert_eq!(zone.records[0].ttl, 30);
+        assert_eq!(zone.records[1].name, "after.example.com.");
+        assert_eq!(zone.records[1].ttl, 60);
+        assert_eq!(zone2.records[0].name, "host.example.net.");
+    }
+
+    #[test]
+    fn test_errors() {
+        fn err(s: &str) -> String {
+            parse_zone(s, "example.com.").unwrap_err().to_string()
+        }
+
+        assert_eq!(err("a 60 A 1.2.3.4 5"), "line 1: extra field in record data");
+        assert_eq!(err("$TTL 60\n\n a A 192.0.2.1"), "line 3: missing owner name");
+        assert_eq!(err("a A 192.0.2.1"), "line 1: missing TTL");
+        assert_eq!(err("a 60 BOGUS x"), "line 1: unknown record type");
+        assert_eq!(err("a 60 TYPE65534 x"), "line 1: invalid generic record data");
+        assert_eq!(err(&format!("a 60 TYPE65534 \\# 65536 {}", "00".repeat(65536))),
+            "line 1: record data too long");
+        assert_eq!(err("a 60 MX ( 10\nb."), "line 2: unbalanced parentheses");
+        assert_eq!(err("$FOO"), "line 1: unknown directive: $FOO");
+    }
+}